@@ -1,50 +1,154 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
 use std::collections::hash_map::DefaultHasher;
 use std::fs;
 use std::hash::{Hash, Hasher};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CacheEntry {
     pub template_hash: String,
+    /// Hash of the stylesheet; empty for caches written before it was tracked
+    #[serde(default)]
+    pub style_hash: String,
+    /// Hash of the command line of every bar (config and stylesheet path);
+    /// empty for caches written before it was tracked
+    #[serde(default)]
+    pub bars_hash: String,
     pub monitors: Vec<String>,
     pub preferred_monitor: String,
     pub timestamp: i64,
+    /// Last time waybar was launched, regardless of regeneration
+    #[serde(default)]
+    pub last_launch: i64,
+    /// Content hash of each generated config as written, by path, to notice
+    /// files edited outside the manager
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_hashes: BTreeMap<String, String>,
+    /// Template of each monitor, by name, as the configs were generated, so
+    /// `launch --commit` starts the bars on the configs that were prepared
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub assignments: BTreeMap<String, String>,
 }
 
-pub fn get_cache_path() -> Result<PathBuf> {
-    let home = dirs::home_dir().context("The home directory could not be retrieved")?;
-    Ok(home.join(".local/share/omynix/modules/waybar-manager/waybar_cache.toml"))
+/// Storage backend for the generation cache
+pub trait CacheStore {
+    fn load(&self) -> Result<Option<CacheEntry>>;
+    fn save(&self, cache: &CacheEntry) -> Result<()>;
+    /// Forget the stored entry; clearing an empty store is not an error
+    fn clear(&self) -> Result<()>;
+    /// `save`, creating a backing file with `mode` (`[launch]
+    /// generated_file_mode`); stores without one just save
+    fn save_with_mode(&self, cache: &CacheEntry, _mode: Option<u32>) -> Result<()> {
+        self.save(cache)
+    }
+}
+
+/// Cache persisted as a TOML file on disk
+pub struct FileCache {
+    cache_dir: Option<PathBuf>,
+    /// Resolved on first use, so commands that never touch the cache (e.g.
+    /// `version`) do not need a data directory
+    path: OnceCell<PathBuf>,
 }
 
-pub fn load_cache() -> Result<Option<CacheEntry>> {
-    let cache_path = get_cache_path()?;
+impl FileCache {
+    /// Cache at the default location, or inside `cache_dir` when given
+    pub fn open(cache_dir: Option<&Path>) -> Self {
+        FileCache {
+            cache_dir: cache_dir.map(Path::to_path_buf),
+            path: OnceCell::new(),
+        }
+    }
+
+    fn path(&self) -> Result<&Path> {
+        if let Some(path) = self.path.get() {
+            return Ok(path);
+        }
+        let path = get_cache_path(self.cache_dir.as_deref())?;
+        Ok(self.path.get_or_init(|| path))
+    }
+}
+
+impl CacheStore for FileCache {
+    fn load(&self) -> Result<Option<CacheEntry>> {
+        let path = self.path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(path).context("The cache file could not be read")?;
+
+        let cache: CacheEntry = toml::from_str(&contents).context("Error parsing cache file")?;
+
+        Ok(Some(cache))
+    }
+
+    fn save(&self, cache: &CacheEntry) -> Result<()> {
+        // Rewriting in place keeps the mode the file was created with
+        self.save_with_mode(cache, None)
+    }
 
-    if !cache_path.exists() {
-        return Ok(None);
+    fn clear(&self) -> Result<()> {
+        match fs::remove_file(self.path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).context("The cache file could not be removed")
+            }
+            _ => Ok(()),
+        }
     }
 
-    let contents = fs::read_to_string(&cache_path).context("The cache file could not be read")?;
+    fn save_with_mode(&self, cache: &CacheEntry, mode: Option<u32>) -> Result<()> {
+        let path = self.path()?;
+        // Create directory if it does not exist
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("The cache directory could not be created")?;
+        }
 
-    let cache: CacheEntry = toml::from_str(&contents).context("Error parsing cache file")?;
+        let toml_string = toml::to_string_pretty(cache).context("Error serializing cache")?;
 
-    Ok(Some(cache))
+        crate::templates::write_with_mode(path, &toml_string, mode)
+            .context("Error writing cache file")
+    }
 }
 
-pub fn save_cache(cache: &CacheEntry) -> Result<()> {
-    let cache_path = get_cache_path()?;
+/// Cache kept in memory only, so cache flows can be tested without touching disk
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryCache {
+    entry: std::cell::RefCell<Option<CacheEntry>>,
+}
 
-    // Create directory if it does not exist
-    if let Some(parent) = cache_path.parent() {
-        fs::create_dir_all(parent).context("The cache directory could not be created")?;
+#[cfg(test)]
+impl CacheStore for MemoryCache {
+    fn load(&self) -> Result<Option<CacheEntry>> {
+        Ok(self.entry.borrow().clone())
     }
 
-    let toml_string = toml::to_string_pretty(cache).context("Error serializing cache")?;
+    fn save(&self, cache: &CacheEntry) -> Result<()> {
+        *self.entry.borrow_mut() = Some(cache.clone());
+        Ok(())
+    }
 
-    fs::write(&cache_path, toml_string).context("Error writing cache file")?;
+    fn clear(&self) -> Result<()> {
+        *self.entry.borrow_mut() = None;
+        Ok(())
+    }
+}
 
-    Ok(())
+/// Resolve the cache file path.
+///
+/// Precedence: `cache_dir` (from `--cache-dir`), then `$XDG_DATA_HOME`,
+/// then `~/.local/share`.
+pub fn get_cache_path(cache_dir: Option<&Path>) -> Result<PathBuf> {
+    if let Some(dir) = cache_dir {
+        return Ok(dir.join("waybar_cache.toml"));
+    }
+
+    let data_dir = dirs::data_dir().context("The data directory could not be retrieved")?;
+    Ok(data_dir.join("omynix/modules/waybar-manager/waybar_cache.toml"))
 }
 
 pub fn calculate_template_hash(template_content: &str) -> String {
@@ -53,6 +157,115 @@ pub fn calculate_template_hash(template_content: &str) -> String {
     hasher.finish().to_string()
 }
 
+/// Everything that shapes the generated configs, serialized to hash it.
+/// Settings that are not in use are left out, so plain launches keep
+/// their cache when a new one is added.
+#[derive(Serialize)]
+struct GenerationKey<'a> {
+    template: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    modules: &'a BTreeMap<String, toml::Table>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    assign: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    hook_assign: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    template_rules: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    monitor_layers: &'a BTreeMap<String, crate::config::Layer>,
+    /// Rotating a monitor swaps its template, so orientation is part of it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vertical_template: Option<(&'a str, &'a [String])>,
+    /// What `{{width}}`-style placeholders are replaced with, per monitor
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    geometry: Vec<(&'a str, u32, u32, f64, f64)>,
+    /// Profiles may share monitors but not bars, so switching regenerates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<&'a str>,
+}
+
+/// Hash of everything that shapes the generated configs: the template, the
+/// active theme and the config-level module overrides merged into it
+pub fn generation_hash(cfg: &crate::config::Config, template_content: &str) -> String {
+    let display = &cfg.display;
+    let key = GenerationKey {
+        template: template_content,
+        theme: display.theme.as_deref(),
+        modules: &cfg.modules,
+        assign: &display.assign,
+        hook_assign: &display.hook_assign,
+        template_rules: &display.template_rules,
+        monitor_layers: &display.monitor_layers,
+        vertical_template: display
+            .vertical_template
+            .as_deref()
+            .map(|vertical| (vertical, display.portrait.as_slice())),
+        geometry: display
+            .geometry
+            .iter()
+            .filter(|info| info.has_geometry())
+            .map(|info| {
+                let crate::monitor::MonitorInfo {
+                    name,
+                    width,
+                    height,
+                    refresh,
+                    scale,
+                    ..
+                } = info;
+                (name.as_str(), *width, *height, *refresh, *scale)
+            })
+            .collect(),
+        profile: cfg.active_profile.as_deref(),
+    };
+    hash_serialized(&key)
+}
+
+/// Hash of the JSON form of `key`, rather than of its `Debug` output,
+/// whose format is not guaranteed
+fn hash_serialized(key: &impl Serialize) -> String {
+    calculate_template_hash(&serde_json::to_string(key).unwrap_or_default())
+}
+
+/// Hash of the stylesheet's contents; a missing file hashes as ""
+pub fn style_hash(path: &Path) -> String {
+    fs::read_to_string(path)
+        .map(|content| calculate_template_hash(&content))
+        .unwrap_or_default()
+}
+
+/// Hash of everything that ends up in the bars' stylesheets: the base
+/// stylesheet plus any `[styles.<monitor>]` inline CSS
+pub fn stylesheet_hash(cfg: &crate::config::Config) -> String {
+    let base = style_hash(&crate::templates::get_style_path(cfg));
+    if cfg.styles.is_empty() && cfg.display.monitor_styles.is_empty() {
+        return base;
+    }
+
+    let home = dirs::home_dir().unwrap_or_default();
+    let monitor_styles: BTreeMap<&String, String> = cfg
+        .display
+        .monitor_styles
+        .iter()
+        .map(|(monitor, path)| {
+            (
+                monitor,
+                style_hash(&crate::config::expand_home(&home, path)),
+            )
+        })
+        .collect();
+    hash_serialized(&(base, &cfg.styles, monitor_styles))
+}
+
+/// Hash of the command lines of `bars`. Bars need a relaunch when it
+/// changes: waybar cannot switch to another config or stylesheet on reload.
+pub fn bars_hash(bars: &[crate::templates::PlannedBar]) -> String {
+    let commands: Vec<String> = bars.iter().map(|bar| bar.command()).collect();
+    hash_serialized(&commands)
+}
+
 pub fn get_current_timestamp() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -60,63 +273,1216 @@ pub fn get_current_timestamp() -> i64 {
         .as_secs() as i64
 }
 
-pub fn should_regenerate(
+/// What `explain_regeneration` compares against the cache
+#[derive(Debug, Clone, Default)]
+pub struct GenerationInputs {
+    /// `generation_hash` of the template and config
+    pub template_hash: String,
+    /// `stylesheet_hash` of the stylesheets
+    pub style_hash: String,
+    /// `bars_hash` of the bars to launch
+    pub bars_hash: String,
+    /// Monitors the bars are launched on
+    pub monitors: Vec<String>,
+    pub preferred_monitor: String,
+    pub generated_files_exist: bool,
+    /// `mismatched_outputs` for the monitors
+    pub mismatched_outputs: Vec<String>,
+    /// `drifted_files` of the cache, empty when drift is ignored
+    pub drifted_files: Vec<String>,
+    /// `--force-regenerate`: skip every check
+    pub force: bool,
+    /// Template of each monitor, by name, recorded with the generation
+    pub assignments: BTreeMap<String, String>,
+}
+
+/// Why a launch regenerates the configs or reloads the bars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Forced,
+    NoCache,
+    FilesMissing,
+    /// A generated config names another monitor as its `output`
+    OutputMismatch,
+    /// A generated config was edited since it was written
+    FilesEdited,
+    TemplateChanged,
+    PreferredChanged,
+    MonitorsChanged,
+    /// A bar's config or stylesheet path changed
+    BarsChanged,
+    /// Only the contents of the stylesheets changed
+    StyleChanged,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Forced => "forced",
+            Reason::NoCache => "no cache",
+            Reason::FilesMissing => "generated files missing",
+            Reason::OutputMismatch => "generated output mismatch",
+            Reason::FilesEdited => "generated files edited",
+            Reason::TemplateChanged => "template changed",
+            Reason::PreferredChanged => "preferred monitor changed",
+            Reason::MonitorsChanged => "monitor list changed",
+            Reason::BarsChanged => "bar commands changed",
+            Reason::StyleChanged => "style changed",
+        }
+    }
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Outcome of `explain_regeneration`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Why the configs need regenerating or the bars reloading, `None`
+    /// when the cache is valid
+    pub reason: Option<Reason>,
+    /// One line per check, in the order they are evaluated, for
+    /// `launch --explain-cache`
+    pub trace: Vec<String>,
+}
+
+impl Decision {
+    pub fn strategy(&self) -> ApplyStrategy {
+        ApplyStrategy::from_reason(self.reason)
+    }
+}
+
+/// Why the configs need regenerating, or `None` when the cache is valid
+pub fn regeneration_reason(
     cache: Option<&CacheEntry>,
-    template_hash: &str,
-    monitors: &[String],
-    preferred_monitor: &str,
-    generated_files_exist: bool,
-) -> bool {
+    inputs: &GenerationInputs,
+) -> Option<Reason> {
+    explain_regeneration(cache, inputs).reason
+}
+
+/// Compare `inputs` against the cache and decide what a launch has to do
+pub fn explain_regeneration(cache: Option<&CacheEntry>, inputs: &GenerationInputs) -> Decision {
+    let mut trace = Vec::new();
+    let decide = |reason: Option<Reason>, mut trace: Vec<String>| {
+        trace.push(match reason {
+            Some(reason) => format!("=> regenerate ({})", reason),
+            None => "=> use cache".to_string(),
+        });
+        Decision { reason, trace }
+    };
+    let problems = |problems: &[String]| {
+        if problems.is_empty() {
+            "true".to_string()
+        } else {
+            format!("false ({})", problems.join(", "))
+        }
+    };
+
+    // `--force-regenerate` skips every check
+    if inputs.force {
+        trace.push("forced? true".to_string());
+        return decide(Some(Reason::Forced), trace);
+    }
+
     // If there is no cache, regenerate
+    trace.push(format!("cache present? {}", cache.is_some()));
     let Some(cache) = cache else {
-        return true;
+        return decide(Some(Reason::NoCache), trace);
     };
 
     // If the generated files do not exist, regenerate
-    if !generated_files_exist {
-        return true;
+    trace.push(format!(
+        "generated files exist? {}",
+        inputs.generated_files_exist
+    ));
+    if !inputs.generated_files_exist {
+        return decide(Some(Reason::FilesMissing), trace);
     }
 
     // If the template hash changed, regenerate
-    if cache.template_hash != template_hash {
-        return true;
+    let same = cache.template_hash == inputs.template_hash;
+    trace.push(format!(
+        "template hash: cached {} vs current {} -> {}",
+        cache.template_hash,
+        inputs.template_hash,
+        if same { "same" } else { "changed" }
+    ));
+    if !same {
+        return decide(Some(Reason::TemplateChanged), trace);
     }
 
     // If you changed your preferred monitor, regenerate
-    if cache.preferred_monitor != preferred_monitor {
-        return true;
+    let same = cache.preferred_monitor == inputs.preferred_monitor;
+    trace.push(format!(
+        "preferred monitor: cached {:?} vs current {:?} -> {}",
+        cache.preferred_monitor,
+        inputs.preferred_monitor,
+        if same { "same" } else { "changed" }
+    ));
+    if !same {
+        return decide(Some(Reason::PreferredChanged), trace);
     }
 
     // If the monitor list has changed, regenerate
     let mut cache_monitors = cache.monitors.clone();
-    let mut current_monitors = monitors.to_vec();
+    let mut current_monitors = inputs.monitors.clone();
     cache_monitors.sort();
     current_monitors.sort();
 
-    if cache_monitors != current_monitors {
-        return true;
+    let same = cache_monitors == current_monitors;
+    trace.push(format!(
+        "monitors: cached {:?} vs current {:?} -> {}",
+        cache_monitors,
+        current_monitors,
+        if same { "same" } else { "changed" }
+    ));
+    if !same {
+        return decide(Some(Reason::MonitorsChanged), trace);
+    }
+
+    // A bar started on another config or stylesheet needs a relaunch
+    let same = !cache.bars_hash.is_empty() && cache.bars_hash == inputs.bars_hash;
+    trace.push(format!(
+        "bar commands: cached {} vs current {} -> {}",
+        cache.bars_hash,
+        inputs.bars_hash,
+        if same { "same" } else { "changed" }
+    ));
+    if !same {
+        return decide(Some(Reason::BarsChanged), trace);
+    }
+
+    // A generated config naming another monitor as its `output`, e.g. left
+    // over from a run where monitor names shifted, regenerates
+    trace.push(format!(
+        "generated outputs match? {}",
+        problems(&inputs.mismatched_outputs)
+    ));
+    if !inputs.mismatched_outputs.is_empty() {
+        return decide(Some(Reason::OutputMismatch), trace);
+    }
+
+    // So does a generated config edited since it was written
+    trace.push(format!(
+        "generated files unchanged? {}",
+        problems(&inputs.drifted_files)
+    ));
+    if !inputs.drifted_files.is_empty() {
+        return decide(Some(Reason::FilesEdited), trace);
+    }
+
+    // Checked last: when only the stylesheet contents changed, the configs
+    // are still valid and the bars just need a reload
+    let same = cache.style_hash == inputs.style_hash;
+    trace.push(format!(
+        "style hash: cached {} vs current {} -> {}",
+        cache.style_hash,
+        inputs.style_hash,
+        if same { "same" } else { "changed" }
+    ));
+    if !same {
+        return decide(Some(Reason::StyleChanged), trace);
     }
 
     // Everything matches up, not regenerating
-    false
+    decide(None, trace)
+}
+
+/// Store the cache entry for configs just generated from `inputs`, with
+/// the cache file created with `mode`
+pub fn record_generation(
+    store: &dyn CacheStore,
+    inputs: &GenerationInputs,
+    files: &[PathBuf],
+    mode: Option<u32>,
+) -> Result<CacheEntry> {
+    let entry = CacheEntry {
+        template_hash: inputs.template_hash.clone(),
+        style_hash: inputs.style_hash.clone(),
+        bars_hash: inputs.bars_hash.clone(),
+        monitors: inputs.monitors.clone(),
+        preferred_monitor: inputs.preferred_monitor.clone(),
+        timestamp: get_current_timestamp(),
+        last_launch: 0,
+        file_hashes: file_hashes(files),
+        assignments: inputs.assignments.clone(),
+    };
+    store.save_with_mode(&entry, mode)?;
+    Ok(entry)
+}
+
+/// Save what a launch applied under `strategy`: a regeneration records
+/// `inputs` and the `files` written from them; a stylesheet-only change
+/// records the new stylesheet hash, but only when `bars_updated`.
+/// `launch --prepare` leaves the bars alone, so the change stays pending
+/// and the next launch still reloads them.
+pub fn record_apply(
+    store: &dyn CacheStore,
+    cached: Option<&CacheEntry>,
+    inputs: &GenerationInputs,
+    strategy: ApplyStrategy,
+    files: &[PathBuf],
+    mode: Option<u32>,
+    bars_updated: bool,
+) -> Result<()> {
+    match (strategy, cached) {
+        (ApplyStrategy::RegenerateAndRelaunch, _) => {
+            record_generation(store, inputs, files, mode)?;
+        }
+        (ApplyStrategy::ReloadOnly, Some(cached)) if bars_updated => {
+            store.save(&CacheEntry {
+                style_hash: inputs.style_hash.clone(),
+                ..cached.clone()
+            })?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Content hash of each of `paths` that can be read, by path
+pub fn file_hashes(paths: &[PathBuf]) -> BTreeMap<String, String> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let content = fs::read_to_string(path).ok()?;
+            Some((
+                path.display().to_string(),
+                calculate_template_hash(&content),
+            ))
+        })
+        .collect()
+}
+
+/// Paths of the generated files recorded in `cache`
+pub fn cached_files(cache: Option<&CacheEntry>) -> Vec<String> {
+    cache.map_or_else(Vec::new, |entry| {
+        entry.file_hashes.keys().cloned().collect()
+    })
+}
+
+/// Generated configs whose contents no longer match the hash recorded when
+/// they were written, i.e. edited or removed outside the manager
+pub fn drifted_files(cache: &CacheEntry) -> Vec<String> {
+    cache
+        .file_hashes
+        .iter()
+        .filter(|(path, hash)| {
+            fs::read_to_string(path)
+                .map(|content| calculate_template_hash(&content))
+                .ok()
+                .as_ref()
+                != Some(*hash)
+        })
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Human-readable UTC time for a cache timestamp
+pub fn format_timestamp(timestamp: i64) -> String {
+    use chrono::{DateTime, TimeZone, Utc};
+    let dt: DateTime<Utc> = Utc.timestamp_opt(timestamp, 0).unwrap();
+    dt.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// Render a cache entry for `cache show`, as TOML or JSON
+pub fn format_cache(cache: Option<&CacheEntry>, json: bool) -> Result<String> {
+    let Some(cache) = cache else {
+        return Ok("no cache".to_string());
+    };
+
+    if json {
+        let mut value = serde_json::to_value(cache).context("Error serializing cache")?;
+        value["latest_generation"] = format_timestamp(cache.timestamp).into();
+        return serde_json::to_string_pretty(&value).context("Error serializing cache");
+    }
+
+    let toml_string = toml::to_string_pretty(cache).context("Error serializing cache")?;
+    Ok(format!(
+        "{}# Latest generation: {}",
+        toml_string,
+        format_timestamp(cache.timestamp)
+    ))
+}
+
+/// Human-readable summary of a cache entry for `cache info`
+pub fn format_cache_info(cache: Option<&CacheEntry>) -> String {
+    let Some(cache) = cache else {
+        return "No cache: the next launch will generate configs".to_string();
+    };
+
+    let preferred = if cache.preferred_monitor.is_empty() {
+        "(none)"
+    } else {
+        &cache.preferred_monitor
+    };
+    let mut lines = vec![
+        format!("Template hash:     {}", cache.template_hash),
+        format!("Monitors:          {}", cache.monitors.join(", ")),
+        format!("Preferred monitor: {}", preferred),
+        format!("Generated:         {}", format_timestamp(cache.timestamp)),
+    ];
+    if cache.last_launch > 0 {
+        lines.push(format!(
+            "Last launch:       {}",
+            format_timestamp(cache.last_launch)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// What a launch has to do given the cache state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyStrategy {
+    /// Configs and stylesheet are unchanged (e.g. only the stored order moved)
+    NoOp,
+    /// Only the stylesheet contents changed: SIGUSR2 makes the bars pick
+    /// them up
+    ReloadOnly,
+    /// Configs must be regenerated and bars relaunched
+    RegenerateAndRelaunch,
+}
+
+impl ApplyStrategy {
+    /// Strategy for a reason returned by `explain_regeneration`
+    pub fn from_reason(reason: Option<Reason>) -> Self {
+        match reason {
+            None => ApplyStrategy::NoOp,
+            Some(Reason::StyleChanged) => ApplyStrategy::ReloadOnly,
+            Some(_) => ApplyStrategy::RegenerateAndRelaunch,
+        }
+    }
+}
+
+pub fn choose_apply_strategy(
+    cache: Option<&CacheEntry>,
+    inputs: &GenerationInputs,
+) -> ApplyStrategy {
+    explain_regeneration(cache, inputs).strategy()
+}
+
+/// Whether a launch already happened within the last `window_secs`
+pub fn launched_recently(cache: Option<&CacheEntry>, now: i64, window_secs: u64) -> bool {
+    let Some(cache) = cache else {
+        return false;
+    };
+
+    cache.last_launch > 0 && now - cache.last_launch < window_secs as i64
+}
+
+/// The generation `launch --prepare` left for `launch --commit`: the cached
+/// entry, provided `files_exist` still finds the configs it describes
+pub fn prepared_generation(
+    store: &dyn CacheStore,
+    files_exist: impl Fn(&CacheEntry) -> bool,
+) -> Result<CacheEntry> {
+    let entry = store
+        .load()?
+        .context("Nothing was prepared; run `launch --prepare` first")?;
+    if !files_exist(&entry) {
+        return Err(anyhow::anyhow!(
+            "The prepared configs for {} are missing; run `launch --prepare` again",
+            entry.monitors.join(", ")
+        ));
+    }
+    Ok(entry)
+}
+
+/// `cfg` as it was when `entry` was generated: its preferred monitor and
+/// template assignments, so bars start on the configs that were written
+pub fn prepared_config(cfg: crate::config::Config, entry: &CacheEntry) -> crate::config::Config {
+    let mut display = crate::config::Display {
+        preferred_monitor: entry.preferred_monitor.clone(),
+        ..cfg.display
+    };
+    // Caches written before assignments were recorded use the current rules
+    if !entry.assignments.is_empty() {
+        display.assign = entry.assignments.clone();
+    }
+    crate::config::Config { display, ..cfg }
+}
+
+/// Generated configs a launch writes for `monitors`: the combined config,
+/// or one per monitor except frozen ones
+pub fn generated_files(
+    cfg: &crate::config::Config,
+    monitors: &[String],
+    wm: &crate::window_manager::WindowManager,
+) -> Vec<PathBuf> {
+    use crate::templates::{get_combined_config_path, get_generated_dir};
+
+    if cfg.display.combined_config {
+        return vec![get_combined_config_path(wm)];
+    }
+
+    generated_files_in(&get_generated_dir(), cfg, monitors, wm)
+}
+
+pub fn generated_files_in(
+    dir: &Path,
+    cfg: &crate::config::Config,
+    monitors: &[String],
+    wm: &crate::window_manager::WindowManager,
+) -> Vec<PathBuf> {
+    use crate::templates::{determine_config_assignments, generated_config_path_in};
+
+    determine_config_assignments(cfg, monitors)
+        .iter()
+        .filter(|(monitor, _)| !cfg.display.is_frozen(monitor))
+        .map(|(monitor, template_type)| {
+            generated_config_path_in(dir, cfg, wm, monitor, template_type)
+        })
+        .collect()
 }
 
 pub fn check_generated_files_exist(
+    cfg: &crate::config::Config,
+    monitors: &[String],
+    wm: &crate::window_manager::WindowManager,
+) -> bool {
+    use crate::templates::{get_combined_config_path, get_generated_dir};
+
+    if cfg.display.combined_config {
+        return get_combined_config_path(wm).exists();
+    }
+
+    generated_files_exist_in(&get_generated_dir(), cfg, monitors, wm)
+}
+
+pub fn generated_files_exist_in(
+    dir: &Path,
+    cfg: &crate::config::Config,
     monitors: &[String],
     wm: &crate::window_manager::WindowManager,
 ) -> bool {
-    use crate::templates::{TemplateType, get_generated_config_path};
+    // Frozen monitors are never generated, so whatever is there counts
+    generated_files_in(dir, cfg, monitors, wm)
+        .iter()
+        .all(|path| path.exists())
+}
 
-    // Verify that files exist for at least all monitors
-    for monitor in monitors {
-        // Verify at least one type (full or simple)
-        let full_path = get_generated_config_path(wm, monitor, &TemplateType::Full);
-        let simple_path = get_generated_config_path(wm, monitor, &TemplateType::Simple);
+/// Monitors whose generated config names a different `output` (e.g. left
+/// over from a run where monitor names shifted). Missing or unreadable
+/// files are left to `check_generated_files_exist`.
+pub fn mismatched_outputs(
+    cfg: &crate::config::Config,
+    monitors: &[String],
+    wm: &crate::window_manager::WindowManager,
+) -> Vec<String> {
+    use crate::templates::{get_combined_config_path, get_generated_dir};
 
-        if !full_path.exists() && !simple_path.exists() {
-            return false;
+    if cfg.display.combined_config {
+        let Some(bars) = read_json(&get_combined_config_path(wm)) else {
+            return Vec::new();
+        };
+        let outputs: Vec<String> = bars
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bar| bar["output"].as_str().map(str::to_string))
+            .collect();
+        return monitors
+            .iter()
+            .filter(|m| {
+                crate::monitor::find_monitor(&outputs, m, cfg.display.case_insensitive_match)
+                    .is_none()
+            })
+            .cloned()
+            .collect();
+    }
+
+    mismatched_outputs_in(&get_generated_dir(), cfg, monitors, wm)
+}
+
+pub fn mismatched_outputs_in(
+    dir: &Path,
+    cfg: &crate::config::Config,
+    monitors: &[String],
+    wm: &crate::window_manager::WindowManager,
+) -> Vec<String> {
+    use crate::templates::{determine_config_assignments, generated_config_path_in};
+
+    let assignments = determine_config_assignments(cfg, monitors);
+    let mut mismatched: Vec<String> = assignments
+        .iter()
+        .filter(|(monitor, _)| !cfg.display.is_frozen(monitor))
+        .filter(|(monitor, template_type)| {
+            let path = generated_config_path_in(dir, cfg, wm, monitor, template_type);
+            read_json(&path).is_some_and(|config| {
+                !config["output"].as_str().is_some_and(|output| {
+                    crate::monitor::names_equal(output, monitor, cfg.display.case_insensitive_match)
+                })
+            })
+        })
+        .map(|(monitor, _)| monitor.clone())
+        .collect();
+    mismatched.sort();
+    mismatched
+}
+
+fn read_json(path: &Path) -> Option<serde_json::Value> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(monitors: &[&str], preferred: &str) -> CacheEntry {
+        CacheEntry {
+            template_hash: "abc".to_string(),
+            style_hash: String::new(),
+            bars_hash: "bars".to_string(),
+            monitors: monitors.iter().map(|m| m.to_string()).collect(),
+            preferred_monitor: preferred.to_string(),
+            timestamp: 0,
+            last_launch: 0,
+            file_hashes: BTreeMap::new(),
+            assignments: BTreeMap::new(),
         }
     }
 
-    true
+    /// What a launch on `monitors` sees when nothing changed since `entry`
+    fn current(monitors: &[String], preferred: &str) -> GenerationInputs {
+        GenerationInputs {
+            template_hash: "abc".to_string(),
+            bars_hash: "bars".to_string(),
+            monitors: monitors.to_vec(),
+            preferred_monitor: preferred.to_string(),
+            generated_files_exist: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stale_output_regenerates_despite_cache_hit() {
+        use crate::templates::{TemplateType, generated_config_path_in};
+        use crate::window_manager::WindowManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let wm = WindowManager::Niri;
+        let mut cfg = crate::config::Config::default();
+        cfg.display.mode = "multiple".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        let monitors = vec!["DP-1".to_string(), "eDP-1".to_string()];
+
+        let write = |monitor: &str, template_type: &TemplateType, output: &str| {
+            let path = generated_config_path_in(dir.path(), &cfg, &wm, monitor, template_type);
+            fs::write(path, format!(r#"{{"output": "{}"}}"#, output)).unwrap();
+        };
+        write("DP-1", &TemplateType::Full, "DP-1");
+        write("eDP-1", &TemplateType::Simple, "HDMI-A-1");
+
+        let cached = entry(&["DP-1", "eDP-1"], "DP-1");
+        let inputs = |dir: &Path| GenerationInputs {
+            mismatched_outputs: mismatched_outputs_in(dir, &cfg, &monitors, &wm),
+            ..current(&monitors, "DP-1")
+        };
+
+        let Decision { reason, trace } = explain_regeneration(Some(&cached), &inputs(dir.path()));
+        assert_eq!(reason, Some(Reason::OutputMismatch));
+        assert_eq!(
+            &trace[trace.len() - 2..],
+            [
+                "generated outputs match? false (eDP-1)",
+                "=> regenerate (generated output mismatch)"
+            ]
+        );
+
+        write("eDP-1", &TemplateType::Simple, "eDP-1");
+        assert_eq!(
+            regeneration_reason(Some(&cached), &inputs(dir.path())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_refresh_flow_updates_config_and_cache_without_touching_bars() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        let mut cfg = crate::config::Config::default();
+        cfg.display.available_monitors = vec!["eDP-1".to_string()];
+        cfg.display.preferred_monitor = "eDP-1".to_string();
+        crate::config::save_config(&config_path, &cfg).unwrap();
+
+        let store = MemoryCache::default();
+        let mut launched = entry(&["eDP-1"], "eDP-1");
+        launched.last_launch = 42;
+        store.save(&launched).unwrap();
+        let monitors = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+
+        // What refresh does: sync without asking, then record what was
+        // generated without the bars being updated
+        crate::config::sync_available_monitors(&config_path, &mut cfg, &monitors, "eDP-1").unwrap();
+        let inputs = current(&monitors, "eDP-1");
+        let cached = store.load().unwrap();
+        let strategy = choose_apply_strategy(cached.as_ref(), &inputs);
+        assert_eq!(strategy, ApplyStrategy::RegenerateAndRelaunch);
+        record_apply(&store, cached.as_ref(), &inputs, strategy, &[], None, false).unwrap();
+
+        let saved = crate::config::load_config_from(&config_path, false).unwrap();
+        assert_eq!(saved.display.available_monitors, monitors);
+        let cached = store.load().unwrap().unwrap();
+        assert_eq!(cached.monitors, monitors);
+        // No launch was recorded, so nothing claims the bars were replaced
+        assert_eq!(cached.last_launch, 0);
+        assert_eq!(
+            choose_apply_strategy(Some(&cached), &inputs),
+            ApplyStrategy::NoOp
+        );
+    }
+
+    #[test]
+    fn test_prepare_brings_cache_up_to_date_but_leaves_reloads() {
+        let store = MemoryCache::default();
+        store.save(&entry(&["eDP-1"], "eDP-1")).unwrap();
+        let monitors = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        let apply = |inputs: &GenerationInputs, bars_updated| {
+            let cached = store.load().unwrap();
+            let strategy = choose_apply_strategy(cached.as_ref(), inputs);
+            record_apply(
+                &store,
+                cached.as_ref(),
+                inputs,
+                strategy,
+                &[],
+                None,
+                bars_updated,
+            )
+            .unwrap();
+            strategy
+        };
+
+        // A new monitor is generated and cached by `launch --prepare`
+        let inputs = current(&monitors, "eDP-1");
+        assert_eq!(apply(&inputs, false), ApplyStrategy::RegenerateAndRelaunch);
+        let cached = store.load().unwrap().unwrap();
+        assert_eq!(cached.monitors, monitors);
+        assert_eq!(cached.last_launch, 0);
+        assert_eq!(apply(&inputs, false), ApplyStrategy::NoOp);
+
+        // A stylesheet change is left for the next launch to reload
+        let restyled = GenerationInputs {
+            style_hash: "restyled".to_string(),
+            ..current(&monitors, "eDP-1")
+        };
+        assert_eq!(apply(&restyled, false), ApplyStrategy::ReloadOnly);
+        assert_eq!(apply(&restyled, true), ApplyStrategy::ReloadOnly);
+        assert_eq!(apply(&restyled, true), ApplyStrategy::NoOp);
+    }
+
+    #[test]
+    fn test_prepare_then_commit_uses_prepared_configs() {
+        use crate::templates::{TemplateType, determine_config_assignments};
+        use crate::window_manager::WindowManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let wm = WindowManager::Niri;
+        let store = MemoryCache::default();
+        let monitors = vec!["DP-1".to_string(), "eDP-1".to_string()];
+        let mut prepared = crate::config::Config::default();
+        prepared.display.preferred_monitor = "DP-1".to_string();
+        prepared.display.assign = [("eDP-1".to_string(), "media".to_string())].into();
+        let files_exist = |entry: &CacheEntry| {
+            let cfg = prepared_config(crate::config::Config::default(), entry);
+            generated_files_exist_in(dir.path(), &cfg, &entry.monitors, &wm)
+        };
+        assert!(prepared_generation(&store, files_exist).is_err());
+
+        // --prepare records the assignments it generated configs for
+        let assignments = determine_config_assignments(&prepared, &monitors);
+        let files = generated_files_in(dir.path(), &prepared, &monitors, &wm);
+        for file in &files {
+            fs::write(file, "{}").unwrap();
+        }
+        let inputs = GenerationInputs {
+            assignments: assignments
+                .iter()
+                .map(|(monitor, template)| (monitor.clone(), template.name().to_string()))
+                .collect(),
+            ..current(&monitors, "DP-1")
+        };
+        record_generation(&store, &inputs, &files, None).unwrap();
+
+        // --commit starts those bars even though the config moved on since
+        let entry = prepared_generation(&store, files_exist).unwrap();
+        let mut current = crate::config::Config::default();
+        current.display.preferred_monitor = "eDP-1".to_string();
+        let committed = prepared_config(current, &entry);
+        assert_eq!(
+            determine_config_assignments(&committed, &entry.monitors),
+            vec![
+                ("DP-1".to_string(), TemplateType::Full),
+                (
+                    "eDP-1".to_string(),
+                    TemplateType::Custom("media".to_string())
+                )
+            ]
+        );
+        assert_eq!(
+            determine_config_assignments(&committed, &monitors),
+            assignments
+        );
+
+        fs::remove_file(&files[0]).unwrap();
+        assert!(prepared_generation(&store, files_exist).is_err());
+    }
+
+    #[test]
+    fn test_edited_generated_file_triggers_regeneration() {
+        use crate::window_manager::WindowManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = crate::config::Config::default();
+        let monitors = vec!["DP-1".to_string()];
+        let files = generated_files_in(dir.path(), &cfg, &monitors, &WindowManager::Niri);
+        fs::write(&files[0], r#"{"output": "DP-1"}"#).unwrap();
+
+        let store = MemoryCache::default();
+        let entry = record_generation(&store, &current(&monitors, "DP-1"), &files, None).unwrap();
+        assert_eq!(entry.file_hashes.len(), 1);
+        let inputs = || GenerationInputs {
+            drifted_files: drifted_files(&entry),
+            ..current(&monitors, "DP-1")
+        };
+        assert_eq!(regeneration_reason(Some(&entry), &inputs()), None);
+
+        fs::write(&files[0], r#"{"output": "DP-1", "height": 40}"#).unwrap();
+        assert_eq!(drifted_files(&entry), vec![files[0].display().to_string()]);
+        let Decision { reason, trace } = explain_regeneration(Some(&entry), &inputs());
+        assert_eq!(reason, Some(Reason::FilesEdited));
+        assert_eq!(
+            trace.last().unwrap(),
+            "=> regenerate (generated files edited)"
+        );
+    }
+
+    #[test]
+    fn test_file_cache_clear_is_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileCache::open(Some(dir.path()));
+        store.save(&entry(&["eDP-1"], "eDP-1")).unwrap();
+
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_none());
+        store.clear().unwrap();
+
+        let mut cached = entry(&["DP-1", "eDP-1"], "DP-1");
+        cached.timestamp = 86_400;
+        let info = format_cache_info(Some(&cached));
+        assert!(info.contains("Monitors:          DP-1, eDP-1"));
+        assert!(info.contains("1970-01-02 00:00:00 UTC"));
+        assert!(!info.contains("Last launch"));
+    }
+
+    #[test]
+    fn test_memory_cache_empty_regenerates() {
+        let store = MemoryCache::default();
+        let cached = store.load().unwrap();
+        let monitors = vec!["eDP-1".to_string()];
+
+        assert!(cached.is_none());
+        assert!(regeneration_reason(cached.as_ref(), &current(&monitors, "eDP-1")).is_some());
+    }
+
+    #[test]
+    fn test_memory_cache_hit_skips_regeneration() {
+        let store = MemoryCache::default();
+        store
+            .save(&entry(&["eDP-1", "HDMI-A-1"], "HDMI-A-1"))
+            .unwrap();
+
+        let cached = store.load().unwrap();
+        let monitors = vec!["HDMI-A-1".to_string(), "eDP-1".to_string()];
+
+        assert!(regeneration_reason(cached.as_ref(), &current(&monitors, "HDMI-A-1")).is_none());
+        assert!(
+            regeneration_reason(
+                cached.as_ref(),
+                &GenerationInputs {
+                    template_hash: "def".to_string(),
+                    ..current(&monitors, "HDMI-A-1")
+                }
+            )
+            .is_some()
+        );
+        assert!(regeneration_reason(cached.as_ref(), &current(&monitors, "eDP-1")).is_some());
+        assert!(
+            regeneration_reason(
+                cached.as_ref(),
+                &GenerationInputs {
+                    generated_files_exist: false,
+                    ..current(&monitors, "HDMI-A-1")
+                }
+            )
+            .is_some()
+        );
+    }
+
+    #[test]
+    fn test_reorder_only_is_noop() {
+        let cached = entry(&["eDP-1", "HDMI-A-1"], "HDMI-A-1");
+
+        let reordered = vec!["HDMI-A-1".to_string(), "eDP-1".to_string()];
+        assert_eq!(
+            choose_apply_strategy(Some(&cached), &current(&reordered, "HDMI-A-1")),
+            ApplyStrategy::NoOp
+        );
+
+        let added = vec![
+            "HDMI-A-1".to_string(),
+            "eDP-1".to_string(),
+            "DP-2".to_string(),
+        ];
+        assert_eq!(
+            choose_apply_strategy(Some(&cached), &current(&added, "HDMI-A-1")),
+            ApplyStrategy::RegenerateAndRelaunch
+        );
+    }
+
+    #[test]
+    fn test_style_and_template_changes_choose_separate_actions() {
+        let cached = entry(&["eDP-1"], "eDP-1");
+        let monitors = vec!["eDP-1".to_string()];
+
+        assert_eq!(
+            choose_apply_strategy(Some(&cached), &current(&monitors, "eDP-1")),
+            ApplyStrategy::NoOp
+        );
+        assert_eq!(
+            choose_apply_strategy(
+                Some(&cached),
+                &GenerationInputs {
+                    style_hash: "css2".to_string(),
+                    ..current(&monitors, "eDP-1")
+                }
+            ),
+            ApplyStrategy::ReloadOnly
+        );
+        assert_eq!(
+            choose_apply_strategy(
+                Some(&cached),
+                &GenerationInputs {
+                    template_hash: "def".to_string(),
+                    ..current(&monitors, "eDP-1")
+                }
+            ),
+            ApplyStrategy::RegenerateAndRelaunch
+        );
+
+        // A style change alongside a template change still regenerates
+        assert_eq!(
+            choose_apply_strategy(
+                Some(&cached),
+                &GenerationInputs {
+                    template_hash: "def".to_string(),
+                    style_hash: "css2".to_string(),
+                    ..current(&monitors, "eDP-1")
+                }
+            ),
+            ApplyStrategy::RegenerateAndRelaunch
+        );
+    }
+
+    #[test]
+    fn test_theme_switch_triggers_regeneration() {
+        let mut cfg = crate::config::Config::default();
+        let monitors = vec!["eDP-1".to_string()];
+
+        let mut cached = entry(&["eDP-1"], "eDP-1");
+        cached.template_hash = generation_hash(&cfg, "[]");
+        assert!(
+            regeneration_reason(
+                Some(&cached),
+                &GenerationInputs {
+                    template_hash: generation_hash(&cfg, "[]"),
+                    ..current(&monitors, "eDP-1")
+                }
+            )
+            .is_none()
+        );
+
+        cfg.display.theme = Some("gruvbox".to_string());
+        assert!(
+            regeneration_reason(
+                Some(&cached),
+                &GenerationInputs {
+                    template_hash: generation_hash(&cfg, "[]"),
+                    ..current(&monitors, "eDP-1")
+                }
+            )
+            .is_some()
+        );
+    }
+
+    #[test]
+    fn test_generation_hash_covers_only_settings_in_use() {
+        let mut cfg = crate::config::Config::default();
+        assert_eq!(
+            generation_hash(&cfg, "[]"),
+            calculate_template_hash(r#"{"template":"[]"}"#)
+        );
+
+        cfg.display
+            .template_rules
+            .insert("eDP".to_string(), "simple".to_string());
+        assert_eq!(
+            generation_hash(&cfg, "[]"),
+            calculate_template_hash(r#"{"template":"[]","template_rules":{"eDP":"simple"}}"#)
+        );
+    }
+
+    #[test]
+    fn test_resolution_change_changes_generation_hash() {
+        use crate::monitor::MonitorInfo;
+
+        let mut cfg = crate::config::Config::default();
+        let plain = generation_hash(&cfg, "[]");
+        // Nothing to substitute without a resolution
+        cfg.display.geometry = vec![MonitorInfo {
+            name: "DP-1".to_string(),
+            ..Default::default()
+        }];
+        assert_eq!(generation_hash(&cfg, "[]"), plain);
+
+        cfg.display.geometry[0].width = 1920;
+        cfg.display.geometry[0].height = 1080;
+        let full_hd = generation_hash(&cfg, "[]");
+        assert_ne!(full_hd, plain);
+
+        // Moving the monitor in the layout changes no placeholder
+        cfg.display.geometry[0].position = (1920, 0);
+        assert_eq!(generation_hash(&cfg, "[]"), full_hd);
+        cfg.display.geometry[0].width = 2560;
+        assert_ne!(generation_hash(&cfg, "[]"), full_hd);
+    }
+
+    #[test]
+    fn test_profile_switch_changes_generation_hash() {
+        let mut cfg = crate::config::Config::default();
+        let plain = generation_hash(&cfg, "[]");
+
+        cfg.active_profile = Some("travel".to_string());
+        let travel = generation_hash(&cfg, "[]");
+        assert_ne!(plain, travel);
+
+        cfg.active_profile = Some("desk".to_string());
+        assert_ne!(travel, generation_hash(&cfg, "[]"));
+    }
+
+    #[test]
+    fn test_explain_trace_follows_decision_path() {
+        let cached = entry(&["eDP-1", "HDMI-A-1"], "HDMI-A-1");
+        let monitors = ["HDMI-A-1".to_string(), "eDP-1".to_string()];
+
+        let Decision { reason, trace } =
+            explain_regeneration(None, &current(&monitors, "HDMI-A-1"));
+        assert_eq!(reason, Some(Reason::NoCache));
+        assert_eq!(
+            trace,
+            vec!["cache present? false", "=> regenerate (no cache)"]
+        );
+
+        let Decision { reason, trace } =
+            explain_regeneration(Some(&cached), &current(&monitors, "eDP-1"));
+        assert_eq!(reason, Some(Reason::PreferredChanged));
+        assert_eq!(
+            trace,
+            vec![
+                "cache present? true",
+                "generated files exist? true",
+                "template hash: cached abc vs current abc -> same",
+                "preferred monitor: cached \"HDMI-A-1\" vs current \"eDP-1\" -> changed",
+                "=> regenerate (preferred monitor changed)",
+            ]
+        );
+
+        let Decision { reason, trace } =
+            explain_regeneration(Some(&cached), &current(&monitors, "HDMI-A-1"));
+        assert_eq!(reason, None);
+        assert_eq!(trace.len(), 10);
+        assert_eq!(trace.last().unwrap(), "=> use cache");
+    }
+
+    #[test]
+    fn test_force_regenerates_despite_matching_cache() {
+        let cached = entry(&["eDP-1"], "eDP-1");
+        let monitors = ["eDP-1".to_string()];
+
+        let reason = explain_regeneration(Some(&cached), &current(&monitors, "eDP-1")).reason;
+        assert_eq!(reason, None);
+
+        let Decision { reason, trace } = explain_regeneration(
+            Some(&cached),
+            &GenerationInputs {
+                force: true,
+                ..current(&monitors, "eDP-1")
+            },
+        );
+        assert_eq!(reason, Some(Reason::Forced));
+        assert_eq!(trace, vec!["forced? true", "=> regenerate (forced)"]);
+    }
+
+    #[test]
+    fn test_custom_file_names_used_for_generation_and_lookup() {
+        use crate::templates::{TemplateType, generated_config_path_in};
+        use crate::window_manager::WindowManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = crate::config::Config::default();
+        cfg.display
+            .file_names
+            .insert("full".to_string(), "primary".to_string());
+        let monitors = ["DP-1".to_string()];
+
+        let path = generated_config_path_in(
+            dir.path(),
+            &cfg,
+            &WindowManager::Niri,
+            "DP-1",
+            &TemplateType::Full,
+        );
+        assert!(path.to_string_lossy().contains("niri_DP-1_primary"));
+        std::fs::write(&path, "{}").unwrap();
+
+        assert!(generated_files_exist_in(
+            dir.path(),
+            &cfg,
+            &monitors,
+            &WindowManager::Niri
+        ));
+        // Without the mapping the lookup expects the default name
+        assert!(!generated_files_exist_in(
+            dir.path(),
+            &crate::config::Config::default(),
+            &monitors,
+            &WindowManager::Niri
+        ));
+    }
+
+    #[test]
+    fn test_style_change_triggers_regeneration() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = dir.path().join("style.css");
+        let monitors = ["eDP-1".to_string()];
+        let inputs = |style_hash: String| GenerationInputs {
+            style_hash,
+            ..current(&monitors, "eDP-1")
+        };
+
+        // Old caches know neither the bars nor the stylesheet
+        let mut cached: CacheEntry = toml::from_str(
+            "template_hash = \"abc\"\nmonitors = [\"eDP-1\"]\npreferred_monitor = \"eDP-1\"\ntimestamp = 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            regeneration_reason(Some(&cached), &inputs(style_hash(&style))),
+            Some(Reason::BarsChanged)
+        );
+
+        // A missing stylesheet and an untracked one agree
+        cached.bars_hash = "bars".to_string();
+        assert_eq!(style_hash(&style), "");
+        assert_eq!(
+            regeneration_reason(Some(&cached), &inputs(style_hash(&style))),
+            None
+        );
+
+        fs::write(&style, "window#waybar { color: red; }").unwrap();
+        assert_eq!(
+            regeneration_reason(Some(&cached), &inputs(style_hash(&style))),
+            Some(Reason::StyleChanged)
+        );
+
+        cached.style_hash = style_hash(&style);
+        assert_eq!(
+            regeneration_reason(Some(&cached), &inputs(style_hash(&style))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_style_path_change_relaunches() {
+        use crate::templates::launch_plan;
+        use crate::window_manager::WindowManager;
+
+        let mut cfg = crate::config::Config::default();
+        cfg.display.style_path = Some("/etc/waybar/style.css".to_string());
+        let monitors = ["eDP-1".to_string()];
+        let bars = |cfg: &crate::config::Config| {
+            bars_hash(&launch_plan(cfg, &monitors, &WindowManager::Niri))
+        };
+
+        let mut cached = entry(&["eDP-1"], "eDP-1");
+        cached.bars_hash = bars(&cfg);
+        let inputs = |cfg: &crate::config::Config| GenerationInputs {
+            bars_hash: bars(cfg),
+            style_hash: "css2".to_string(),
+            ..current(&monitors, "eDP-1")
+        };
+        assert_eq!(
+            choose_apply_strategy(Some(&cached), &inputs(&cfg)),
+            ApplyStrategy::ReloadOnly
+        );
+
+        // waybar cannot switch stylesheets on SIGUSR2
+        cfg.display.style_path = Some("/etc/waybar/dark.css".to_string());
+        assert_eq!(
+            regeneration_reason(Some(&cached), &inputs(&cfg)),
+            Some(Reason::BarsChanged)
+        );
+        assert_eq!(
+            choose_apply_strategy(Some(&cached), &inputs(&cfg)),
+            ApplyStrategy::RegenerateAndRelaunch
+        );
+    }
+
+    #[test]
+    fn test_launched_recently() {
+        let mut cached = entry(&["eDP-1"], "eDP-1");
+        assert!(!launched_recently(None, 1_000, 30));
+        assert!(!launched_recently(Some(&cached), 1_000, 30));
+
+        cached.last_launch = 990;
+        assert!(launched_recently(Some(&cached), 1_000, 30));
+        assert!(!launched_recently(Some(&cached), 1_030, 30));
+        assert!(!launched_recently(Some(&cached), 1_000, 0));
+    }
+
+    #[test]
+    fn test_format_cache() {
+        assert_eq!(format_cache(None, false).unwrap(), "no cache");
+
+        let cached = entry(&["eDP-1", "HDMI-A-1"], "HDMI-A-1");
+        let text = format_cache(Some(&cached), false).unwrap();
+        assert!(text.contains("template_hash = \"abc\""));
+        assert!(text.contains("preferred_monitor = \"HDMI-A-1\""));
+        assert!(text.contains("eDP-1"));
+        assert!(text.contains("# Latest generation: 1970-01-01 00:00:00 UTC"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_cache(Some(&cached), true).unwrap()).unwrap();
+        assert_eq!(json["template_hash"], "abc");
+        assert_eq!(json["monitors"], serde_json::json!(["eDP-1", "HDMI-A-1"]));
+        assert_eq!(json["preferred_monitor"], "HDMI-A-1");
+        assert_eq!(json["latest_generation"], "1970-01-01 00:00:00 UTC");
+    }
+
+    #[test]
+    fn test_cache_dir_override() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            get_cache_path(Some(dir.path())).unwrap(),
+            dir.path().join("waybar_cache.toml")
+        );
+
+        let store = FileCache::open(Some(dir.path()));
+
+        store.save(&entry(&["DP-1"], "DP-1")).unwrap();
+        let cached = store.load().unwrap().unwrap();
+        assert_eq!(cached.monitors, vec!["DP-1"]);
+    }
 }
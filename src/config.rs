@@ -1,11 +1,181 @@
 use anyhow::{Context, Result};
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub display: Display,
+    #[serde(default)]
+    pub behavior: Behavior,
+    /// Per-module defaults merged into every template using the module,
+    /// e.g. `[modules.clock] format = "{:%H:%M}"`. Template values win
+    /// unless the table sets `force = true`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub modules: BTreeMap<String, toml::Table>,
+    #[serde(default)]
+    pub detection: DetectionSettings,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    /// Inline CSS per monitor, e.g. `[styles.DP-1] css = "..."`, appended
+    /// to the base stylesheet for that monitor's bar
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub styles: BTreeMap<String, MonitorStyle>,
+    /// Named alternative display setups, e.g. `[profiles.travel]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Display>,
+    /// Profile whose display settings replace `[display]` when loading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+    /// `[display]` as written in the file while a profile is active, so
+    /// saving puts the profile's changes back where they belong
+    #[serde(skip)]
+    pub base_display: Option<Display>,
+    #[serde(default)]
+    pub launch: LaunchSettings,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub paths: Paths,
+}
+
+impl Config {
+    /// This config with `display` replaced by the named profile's
+    pub fn with_profile(&self, name: &str) -> Option<Config> {
+        self.profiles.get(name).map(|display| Config {
+            display: display.clone(),
+            ..self.clone()
+        })
+    }
+
+    /// Swap in the active profile's display settings
+    fn resolve_profile(self) -> Result<Config> {
+        let Some(name) = &self.active_profile else {
+            return Ok(self);
+        };
+
+        let resolved = self.with_profile(name).context(format!(
+            "active_profile = \"{}\" but there is no [profiles.{}] section",
+            name, name
+        ))?;
+        Ok(Config {
+            base_display: Some(self.display),
+            ..resolved
+        })
+    }
+
+    /// The config as laid out in the file: the active profile's display
+    /// settings back under `[profiles.<name>]` and `[display]` restored
+    pub fn unresolved(&self) -> Config {
+        let mut config = self.clone();
+        if let (Some(name), Some(base)) = (&self.active_profile, config.base_display.take()) {
+            config.profiles.insert(name.clone(), config.display.clone());
+            config.display = base;
+        }
+        config
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MonitorStyle {
+    #[serde(default)]
+    pub css: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationSettings {
+    /// How long the config-diff notice stays up; 0 keeps it until dismissed
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u32,
+    /// "low", "normal" or "critical"; unset leaves it to the notification server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urgency: Option<String>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            timeout_ms: default_timeout_ms(),
+            urgency: None,
+        }
+    }
+}
+
+fn default_timeout_ms() -> u32 {
+    8000
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Hooks {
+    /// Shell command choosing templates: gets the connected monitors as a
+    /// JSON array on stdin and prints a JSON object monitor → template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assign_command: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Paths {
+    /// Directory holding `<wm>.jsonc` templates instead of
+    /// `~/.config/waybar/templates`; `~/…` and relative paths are taken
+    /// from the home directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates_dir: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LaunchSettings {
+    /// Octal permissions for generated configs, stylesheets and the cache,
+    /// e.g. "0600"; unset leaves them to the umask
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_file_mode: Option<String>,
+    /// Pause after starting each bar, before checking it is still running
+    /// and starting the next one
+    #[serde(default = "default_launch_stagger_ms")]
+    pub launch_stagger_ms: u64,
+}
+
+impl Default for LaunchSettings {
+    fn default() -> Self {
+        LaunchSettings {
+            generated_file_mode: None,
+            launch_stagger_ms: default_launch_stagger_ms(),
+        }
+    }
+}
+
+fn default_launch_stagger_ms() -> u64 {
+    200
+}
+
+impl LaunchSettings {
+    /// `generated_file_mode` parsed as octal
+    pub fn file_mode(&self) -> Result<Option<u32>> {
+        let Some(mode) = &self.generated_file_mode else {
+            return Ok(None);
+        };
+
+        let digits = mode.trim().trim_start_matches("0o");
+        u32::from_str_radix(digits, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+            .map(Some)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid [launch] generated_file_mode '{}', expected octal like \"0600\"",
+                    mode
+                )
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DetectionSettings {
+    /// Window managers to probe, in order (e.g. `["niri", "hyprland"]`).
+    /// Empty means every supported one in the default order.
+    #[serde(default)]
+    pub order: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -14,12 +184,188 @@ pub struct Display {
     pub available_monitors: Vec<String>,
     #[serde(default = "default_mode")]
     pub mode: String, // "single" o "multiple"
+    /// Compare monitor names ignoring case (e.g. `hdmi-a-1` == `HDMI-A-1`)
+    #[serde(default)]
+    pub case_insensitive_match: bool,
+    /// Emit one config with every bar and launch a single waybar process
+    #[serde(default)]
+    pub combined_config: bool,
+    /// Template for the built-in panel (eDP/LVDS/DSI), e.g. "simple"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_template: Option<String>,
+    /// Active theme from `~/.config/waybar/themes/<name>/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    /// Stylesheet passed to waybar; `~/` and relative paths are resolved
+    /// against the home dir (default `~/.config/waybar/omynix_style.css`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style_path: Option<String>,
+    /// Stylesheet file per monitor name (as listed by `monitors`), used
+    /// instead of the global one for that monitor's bar; resolved like
+    /// `style_path`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub monitor_styles: BTreeMap<String, String>,
+    /// Waybar `layer` per monitor name, replacing the template's value in
+    /// that monitor's generated config
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub monitor_layers: BTreeMap<String, Layer>,
+    /// Template for monitors rotated into portrait (e.g. "vertical");
+    /// landscape monitors keep their usual assignment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vertical_template: Option<String>,
+    /// Template per connector family (`edp`, `hdmi`, `dp`, ...) or per
+    /// monitor name; a name rule wins over its family's
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub template_rules: BTreeMap<String, String>,
+    /// Filename segment used for each template type in generated configs,
+    /// e.g. `full = "primary"` (defaults to the type name)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_names: BTreeMap<String, String>,
+    /// Keep monitors reporting a 0x0 resolution (e.g. in DPMS standby)
+    #[serde(default)]
+    pub include_zero_resolution: bool,
+    /// Bars for monitors mirroring each other (same position and
+    /// resolution): `one` launches a single bar per group, `all` one each
+    #[serde(default)]
+    pub mirror_policy: MirrorPolicy,
+    /// Monitors that keep the bar of their mirror group, most wanted
+    /// first; ahead of `preferred_monitor` and `available_monitors`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirror_keep: Vec<String>,
+    /// Monitors whose bars are started first, in this order (some
+    /// compositors stack bars by launch order); the others follow in
+    /// `available_monitors` order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub launch_order: Vec<String>,
+    /// In single mode, launch nothing rather than falling back to another
+    /// monitor when `preferred_monitor` is not connected
+    #[serde(default)]
+    pub strict_single: bool,
+    /// Monitors whose generated config is never rewritten (e.g. tuned by
+    /// hand); launches keep using the file that is there
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frozen_monitors: Vec<String>,
+    /// Modules every generated bar must place somewhere (e.g. "clock");
+    /// generation fails when a template drops one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_modules: Vec<String>,
+    /// Monitor → template name given with `launch --assign`; only lives
+    /// for the current run and wins over every other assignment rule
+    #[serde(skip)]
+    pub assign: BTreeMap<String, String>,
+    /// Monitor → template name returned by `[hooks] assign_command`; only
+    /// lives for the current run and replaces the built-in rules
+    #[serde(skip)]
+    pub hook_assign: BTreeMap<String, String>,
+    /// Connected monitors currently in portrait orientation; detected at
+    /// launch and only filled in when `vertical_template` is set
+    #[serde(skip)]
+    pub portrait: Vec<String>,
+    /// Geometry of the monitors the bars are launched on, for the
+    /// `{{width}}`-style placeholders; detected at launch
+    #[serde(skip)]
+    pub geometry: Vec<crate::monitor::MonitorInfo>,
+}
+
+impl Display {
+    /// Whether `monitor` is listed in `frozen_monitors`
+    pub fn is_frozen(&self, monitor: &str) -> bool {
+        crate::monitor::find_monitor(&self.frozen_monitors, monitor, self.case_insensitive_match)
+            .is_some()
+    }
+}
+
+/// Layer-shell layer a bar is drawn on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    Top,
+    Bottom,
+    Overlay,
+}
+
+impl Layer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layer::Top => "top",
+            Layer::Bottom => "bottom",
+            Layer::Overlay => "overlay",
+        }
+    }
+}
+
+/// What to do with monitors showing the same content
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorPolicy {
+    /// One bar per mirror group, on the first of `mirror_keep`,
+    /// `preferred_monitor` and `available_monitors` that is in the group
+    #[default]
+    One,
+    /// A bar on every monitor, stacked on top of each other
+    All,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Behavior {
+    /// Window (seconds) in which a repeated `launch --since-boot` is a no-op
+    #[serde(default = "default_relaunch_guard_secs")]
+    pub relaunch_guard_secs: u64,
+    /// Desktop notifications (also disabled by `--no-notify`)
+    #[serde(default = "default_true")]
+    pub notifications: bool,
+    /// Append each waybar's stdout/stderr to `logs/waybar-<monitor>.log`
+    /// under the data dir instead of inheriting the terminal
+    #[serde(default)]
+    pub log_to_files: bool,
+    /// Keep the running bars when fewer monitors than this are detected
+    /// (guards against transient detection during plug events)
+    #[serde(default)]
+    pub min_monitors: usize,
+    /// Write `state/health.json` under the data dir after every launch
+    #[serde(default)]
+    pub health_file: bool,
+    /// Seconds `launch` waits for an answer to the monitor-sync prompt
+    #[serde(default = "default_prompt_timeout_secs")]
+    pub prompt_timeout_secs: u64,
+}
+
+impl Behavior {
+    /// Whether detecting `detected` monitors is too few to replace running bars
+    pub fn below_min_monitors(&self, detected: usize) -> bool {
+        detected < self.min_monitors
+    }
 }
 
 fn default_mode() -> String {
     "single".to_string()
 }
 
+fn default_relaunch_guard_secs() -> u64 {
+    30
+}
+
+fn default_prompt_timeout_secs() -> u64 {
+    4
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for Behavior {
+    fn default() -> Self {
+        Behavior {
+            relaunch_guard_secs: default_relaunch_guard_secs(),
+            notifications: true,
+            log_to_files: false,
+            min_monitors: 0,
+            health_file: false,
+            prompt_timeout_secs: default_prompt_timeout_secs(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -27,19 +373,67 @@ impl Default for Config {
                 preferred_monitor: "".to_string(),
                 available_monitors: vec![],
                 mode: "single".to_string(),
+                case_insensitive_match: false,
+                combined_config: false,
+                internal_template: None,
+                theme: None,
+                style_path: None,
+                monitor_styles: BTreeMap::new(),
+                monitor_layers: BTreeMap::new(),
+                vertical_template: None,
+                template_rules: BTreeMap::new(),
+                file_names: BTreeMap::new(),
+                include_zero_resolution: false,
+                mirror_policy: MirrorPolicy::default(),
+                mirror_keep: vec![],
+                launch_order: vec![],
+                strict_single: false,
+                frozen_monitors: vec![],
+                required_modules: vec![],
+                assign: BTreeMap::new(),
+                hook_assign: BTreeMap::new(),
+                portrait: vec![],
+                geometry: vec![],
             },
+            behavior: Behavior::default(),
+            modules: BTreeMap::new(),
+            detection: DetectionSettings::default(),
+            notifications: NotificationSettings::default(),
+            styles: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            active_profile: None,
+            base_display: None,
+            launch: LaunchSettings::default(),
+            hooks: Hooks::default(),
+            paths: Paths::default(),
         }
     }
 }
 
-pub fn get_config_path() -> Result<PathBuf> {
+/// Config file of this run; `cli` is the `--config` flag
+pub fn get_config_path(cli: Option<&Path>) -> Result<PathBuf> {
     let home = dirs::home_dir().context("The home directory could not be retrieved.")?;
-    Ok(home.join(".local/share/omynix/modules/waybar-manager/config.toml"))
+    Ok(config_path_from(
+        cli.map(Path::to_path_buf),
+        std::env::var_os("OMYNIX_WAYBAR_CONFIG").map(PathBuf::from),
+        &home,
+    ))
 }
 
-pub fn init_config() -> Result<()> {
-    let config_path = get_config_path()?;
+/// `path` from the config resolved against `home`: `~/…` and relative
+/// paths are taken from the home directory, absolute ones kept as they are
+pub fn expand_home(home: &Path, path: &str) -> PathBuf {
+    home.join(path.strip_prefix("~/").unwrap_or(path))
+}
 
+/// Config location: `--config`, then `OMYNIX_WAYBAR_CONFIG`, then the
+/// default under `home`
+fn config_path_from(cli: Option<PathBuf>, env: Option<PathBuf>, home: &Path) -> PathBuf {
+    cli.or(env.filter(|path| !path.as_os_str().is_empty()))
+        .unwrap_or_else(|| home.join(".local/share/omynix/modules/waybar-manager/config.toml"))
+}
+
+pub fn init_config(config_path: &Path) -> Result<()> {
     if config_path.exists() {
         println!(
             "The configuration file already exists in: {}",
@@ -48,40 +442,253 @@ pub fn init_config() -> Result<()> {
         return Ok(());
     }
 
+    create_default_config(config_path)?;
+
+    println!("Configuration file created in: {}", config_path.display());
+    Ok(())
+}
+
+fn create_default_config(config_path: &Path) -> Result<()> {
     // Create directory if it does not exist
     if let Some(parent) = config_path.parent() {
         fs::create_dir_all(parent).context("The configuration directory could not be created")?;
     }
 
-    let default_config = Config::default();
-    save_config(&default_config)?;
-
-    println!("Configuration file created in: {}", config_path.display());
-    Ok(())
+    save_config(config_path, &Config::default())
 }
 
-pub fn load_config() -> Result<Config> {
-    let config_path = get_config_path()?;
+/// Load the configuration at `config_path`, creating it when missing. With
+/// `recover`, a malformed file is backed up and replaced by the defaults
+/// instead of failing.
+pub fn load_config(config_path: &Path, recover: bool) -> Result<Config> {
+    // On stderr, so machine-readable output on stdout stays clean
+    if !config_path.exists() {
+        eprintln!(
+            "No configuration file was found, creating one in: {}",
+            config_path.display()
+        );
+        create_default_config(config_path)?;
+    }
+
+    load_config_from(config_path, recover)
+}
 
+/// Like `load_config`, but the defaults stand in for a missing file, so
+/// commands that only read (e.g. `monitors`) never create it
+pub fn load_config_or_default(config_path: &Path, recover: bool) -> Result<Config> {
     if !config_path.exists() {
-        println!("No configuration file was found, creating a new one...");
-        init_config()?;
+        return Ok(Config::default());
     }
 
+    load_config_from(config_path, recover)
+}
+
+pub fn load_config_from(config_path: &Path, recover: bool) -> Result<Config> {
     let contents =
-        fs::read_to_string(&config_path).context("The configuration file could not be read")?;
+        fs::read_to_string(config_path).context("The configuration file could not be read")?;
 
-    let config: Config = toml::from_str(&contents).context("Error parsing configuration file")?;
+    match toml::from_str::<Config>(&contents) {
+        Ok(config) => config.resolve_profile(),
+        Err(e) if recover => {
+            let backup = backup_broken_config(config_path)?;
+            println!(
+                "{}",
+                format!(
+                    "⚠ The configuration file could not be parsed: {}\n  It was backed up to {} and the defaults are used instead.\n  Run 'omynix-waybar-manager config' to reconfigure (or pass --no-recover to fail instead).",
+                    e.message(),
+                    backup.display()
+                )
+                .yellow()
+            );
 
-    Ok(config)
+            let config = Config::default();
+            save_config(config_path, &config)?;
+            Ok(config)
+        }
+        Err(e) => Err(e).context("Error parsing configuration file"),
+    }
 }
 
-pub fn save_config(config: &Config) -> Result<()> {
-    let config_path = get_config_path()?;
+/// Move a malformed config aside as `config.toml.broken-<timestamp>`
+fn backup_broken_config(config_path: &Path) -> Result<PathBuf> {
+    let mut backup = config_path.as_os_str().to_owned();
+    backup.push(format!(
+        ".broken-{}",
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    ));
+    let backup = PathBuf::from(backup);
+
+    fs::copy(config_path, &backup).context("The broken configuration could not be backed up")?;
 
-    let toml_string = toml::to_string_pretty(config).context("Error serializing configuration")?;
+    Ok(backup)
+}
+
+pub fn save_config(config_path: &Path, config: &Config) -> Result<()> {
+    let toml_string =
+        toml::to_string_pretty(&config.unresolved()).context("Error serializing configuration")?;
 
-    fs::write(&config_path, toml_string).context("Error writing configuration file")?;
+    fs::write(config_path, toml_string).context("Error writing configuration file")?;
 
     Ok(())
 }
+
+/// Set `available_monitors` to the `connected` ones and save the config,
+/// with the configured `preferred_monitor` in case a one-shot override
+/// replaced it in `cfg`
+pub fn sync_available_monitors(
+    config_path: &Path,
+    cfg: &mut Config,
+    connected: &[String],
+    preferred_monitor: &str,
+) -> Result<()> {
+    cfg.display.available_monitors = connected.to_vec();
+    save_config(
+        config_path,
+        &Config {
+            display: Display {
+                preferred_monitor: preferred_monitor.to_string(),
+                ..cfg.display.clone()
+            },
+            ..cfg.clone()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config_or_default_creates_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("omynix/config.toml");
+
+        let cfg = load_config_or_default(&path, false).unwrap();
+        assert_eq!(cfg.display.mode, Config::default().display.mode);
+        assert!(!path.exists());
+        assert!(!dir.path().join("omynix").exists());
+    }
+
+    #[test]
+    fn test_config_path_precedence() {
+        let home = Path::new("/home/user");
+        let cli = Some(PathBuf::from("/tmp/cli.toml"));
+        let env = Some(PathBuf::from("/tmp/env.toml"));
+
+        assert_eq!(
+            config_path_from(cli.clone(), env.clone(), home),
+            PathBuf::from("/tmp/cli.toml")
+        );
+        assert_eq!(
+            config_path_from(None, env, home),
+            PathBuf::from("/tmp/env.toml")
+        );
+        assert_eq!(
+            config_path_from(None, Some(PathBuf::new()), home),
+            home.join(".local/share/omynix/modules/waybar-manager/config.toml")
+        );
+    }
+
+    #[test]
+    fn test_below_min_monitors_blocks_relaunch() {
+        let behavior: Behavior = toml::from_str("min_monitors = 2").unwrap();
+        assert!(behavior.below_min_monitors(1));
+        assert!(!behavior.below_min_monitors(2));
+
+        // Unset means no guard
+        assert!(!Behavior::default().below_min_monitors(0));
+    }
+
+    #[test]
+    fn test_prompt_timeout_defaults_to_four_seconds() {
+        let behavior: Behavior = toml::from_str("min_monitors = 1").unwrap();
+        assert_eq!(behavior.prompt_timeout_secs, 4);
+
+        let behavior: Behavior = toml::from_str("prompt_timeout_secs = 10").unwrap();
+        assert_eq!(behavior.prompt_timeout_secs, 10);
+    }
+
+    #[test]
+    fn test_broken_config_is_backed_up_and_defaults_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[display\npreferred_monitor = ").unwrap();
+
+        assert!(load_config_from(&path, false).is_err());
+
+        let config = load_config_from(&path, true).unwrap();
+        assert_eq!(config.display.mode, "single");
+        assert!(config.display.available_monitors.is_empty());
+
+        // The broken file was preserved and replaced by a parseable one
+        let backups: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(".broken-"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            fs::read_to_string(backups[0].path()).unwrap(),
+            "[display\npreferred_monitor = "
+        );
+        assert!(load_config_from(&path, false).is_ok());
+    }
+
+    #[test]
+    fn test_generated_file_mode_is_octal() {
+        let launch: LaunchSettings = toml::from_str(r#"generated_file_mode = "0600""#).unwrap();
+        assert_eq!(launch.file_mode().unwrap(), Some(0o600));
+        assert_eq!(LaunchSettings::default().file_mode().unwrap(), None);
+
+        let invalid = LaunchSettings {
+            generated_file_mode: Some("0900".to_string()),
+            ..LaunchSettings::default()
+        };
+        assert!(invalid.file_mode().is_err());
+    }
+
+    #[test]
+    fn test_active_profile_resolves_and_saves_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"active_profile = "travel"
+
+[display]
+preferred_monitor = "DP-1"
+available_monitors = ["DP-1", "DP-2", "eDP-1"]
+mode = "multiple"
+
+[profiles.travel]
+preferred_monitor = "eDP-1"
+available_monitors = ["eDP-1"]
+"#,
+        )
+        .unwrap();
+
+        let mut cfg = load_config_from(&path, false).unwrap();
+        assert_eq!(cfg.display.preferred_monitor, "eDP-1");
+        assert_eq!(cfg.display.mode, "single");
+
+        // Changes made while the profile is active land in the profile
+        cfg.display.available_monitors = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        save_config(&path, &cfg).unwrap();
+
+        let raw: Config = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(raw.display.preferred_monitor, "DP-1");
+        assert_eq!(raw.display.available_monitors.len(), 3);
+        assert_eq!(
+            raw.profiles["travel"].available_monitors,
+            vec!["eDP-1", "HDMI-A-1"]
+        );
+
+        // A missing profile is an error rather than a silent fallback
+        fs::write(
+            &path,
+            "active_profile = \"gone\"\n[display]\npreferred_monitor = \"DP-1\"\navailable_monitors = []\n",
+        )
+        .unwrap();
+        assert!(load_config_from(&path, false).is_err());
+    }
+}
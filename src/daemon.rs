@@ -0,0 +1,548 @@
+use anyhow::{Context, Result};
+use nix::sys::signal::{SigSet, Signal};
+use nix::unistd::getuid;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Something that may require the bars to be reconciled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    MonitorsChanged,
+    ConfigChanged,
+    Control(ControlCommand),
+    /// Time to check on supervised bars
+    SuperviseTick,
+    /// SIGTERM or SIGINT was received
+    Shutdown,
+}
+
+/// Commands accepted on the control socket, one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    Reload,
+    SwitchTheme(String),
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        match (parts.next()?, parts.next()) {
+            ("reload", None) => Some(ControlCommand::Reload),
+            ("switch-theme", Some(theme)) => Some(ControlCommand::SwitchTheme(theme.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// The coalesced result of every event received during one debounce window
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Reconcile {
+    pub monitors_changed: bool,
+    pub config_changed: bool,
+    pub reload_requested: bool,
+    /// Last theme requested in the window, if any
+    pub theme: Option<String>,
+    pub supervise_tick: bool,
+    pub shutdown: bool,
+}
+
+impl Reconcile {
+    fn add(&mut self, event: Event) {
+        match event {
+            Event::MonitorsChanged => self.monitors_changed = true,
+            Event::ConfigChanged => self.config_changed = true,
+            Event::Control(ControlCommand::Reload) => self.reload_requested = true,
+            Event::Control(ControlCommand::SwitchTheme(theme)) => self.theme = Some(theme),
+            Event::SuperviseTick => self.supervise_tick = true,
+            Event::Shutdown => self.shutdown = true,
+        }
+    }
+
+    /// Nothing but a supervise tick happened
+    pub fn only_supervise_tick(&self) -> bool {
+        *self
+            == Reconcile {
+                supervise_tick: true,
+                ..Default::default()
+            }
+    }
+}
+
+/// A restarted bar that stays up this long gets its restart count back
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// What to do about a supervised bar that is not running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Relaunch it now
+    Restart,
+    /// Still backing off from the last restart; look again on a later tick
+    Wait,
+    /// The retry cap was just exceeded: leave it down and tell the user
+    GiveUp,
+    /// Already given up on
+    Failed,
+}
+
+#[derive(Debug, Default)]
+struct InstanceState {
+    restarts: u32,
+    last_restart: Option<Instant>,
+    failed: bool,
+}
+
+/// Per-monitor restart counts and backoff for `daemon --supervise`, so one
+/// crashing bar never costs the others a restart
+#[derive(Debug)]
+pub struct Supervisor {
+    max_restarts: u32,
+    backoff: Duration,
+    instances: BTreeMap<String, InstanceState>,
+    /// Bars restarted by us, kept until reaped so they don't linger as zombies
+    children: Vec<Child>,
+}
+
+impl Supervisor {
+    pub fn new(max_restarts: u32, backoff: Duration) -> Self {
+        Supervisor {
+            max_restarts,
+            backoff,
+            instances: BTreeMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// `monitor`'s bar is not running at `now`. Restarts back off
+    /// exponentially from `backoff`, doubling after each one.
+    pub fn on_dead(&mut self, monitor: &str, now: Instant) -> Decision {
+        let state = self.instances.entry(monitor.to_string()).or_default();
+        if state.failed {
+            return Decision::Failed;
+        }
+
+        let delay = self
+            .backoff
+            .saturating_mul(2u32.pow(state.restarts.saturating_sub(1).min(6)));
+        if state
+            .last_restart
+            .is_some_and(|last| now.duration_since(last) < delay)
+        {
+            return Decision::Wait;
+        }
+
+        if state.restarts >= self.max_restarts {
+            state.failed = true;
+            return Decision::GiveUp;
+        }
+
+        state.restarts += 1;
+        state.last_restart = Some(now);
+        Decision::Restart
+    }
+
+    /// `monitor`'s bar is running at `now`; once it stayed up long enough
+    /// its earlier restarts are forgotten
+    pub fn on_alive(&mut self, monitor: &str, now: Instant) {
+        let stable = self.instances.get(monitor).is_some_and(|state| {
+            !state.failed
+                && state
+                    .last_restart
+                    .is_none_or(|last| now.duration_since(last) >= STABLE_AFTER)
+        });
+        if stable {
+            self.instances.remove(monitor);
+        }
+    }
+
+    pub fn restarts(&self, monitor: &str) -> u32 {
+        self.instances
+            .get(monitor)
+            .map_or(0, |state| state.restarts)
+    }
+
+    /// Forget everything, e.g. after every bar was relaunched. Restarted
+    /// bars are still reaped once they exit.
+    pub fn reset(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Keep a restarted bar's handle so it can be reaped
+    pub fn adopt(&mut self, child: Child) {
+        self.children.push(child);
+    }
+
+    /// Collect the restarted bars that exited. Ones already reaped
+    /// elsewhere (e.g. while waiting for killed bars) are dropped too.
+    pub fn reap(&mut self) {
+        self.children
+            .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+    }
+}
+
+/// Wait for events, coalesce everything arriving within `debounce` of the
+/// previous one, and hand the result to `apply`. Applies are serialized: a
+/// new window only starts once the previous apply returned. Returns when
+/// every event sender has been dropped.
+pub fn run_reconcile_loop(
+    rx: &Receiver<Event>,
+    debounce: Duration,
+    mut apply: impl FnMut(&Reconcile) -> Result<()>,
+) {
+    while let Ok(first) = rx.recv() {
+        let mut pending = Reconcile::default();
+        pending.add(first);
+
+        let disconnected = loop {
+            if pending.shutdown {
+                return;
+            }
+            match rx.recv_timeout(debounce) {
+                Ok(event) => pending.add(event),
+                Err(RecvTimeoutError::Timeout) => break false,
+                Err(RecvTimeoutError::Disconnected) => break true,
+            }
+        };
+
+        if let Err(e) = apply(&pending) {
+            log::error!("Reconcile failed: {:#}", e);
+        }
+
+        if disconnected {
+            return;
+        }
+    }
+}
+
+/// Turn SIGTERM and SIGINT into an `Event::Shutdown`. The signals are
+/// blocked in the calling thread, so call this before starting any other
+/// thread: those inherit the mask and leave the signals to the watcher.
+pub fn spawn_signal_watcher(tx: Sender<Event>) -> Result<()> {
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGTERM);
+    signals.add(Signal::SIGINT);
+    signals
+        .thread_block()
+        .context("Failed to block SIGTERM and SIGINT")?;
+
+    thread::spawn(move || {
+        if signals.wait().is_ok() {
+            tx.send(Event::Shutdown).ok();
+        }
+    });
+    Ok(())
+}
+
+/// Default control socket location: `$XDG_RUNTIME_DIR`, or else a private
+/// directory of ours under the temp dir, so no other user can pre-create or
+/// take over the socket
+pub fn get_socket_path() -> Result<PathBuf> {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let dir = std::env::temp_dir().join(format!("omynix-waybar-manager-{}", getuid()));
+            private_dir(&dir)?;
+            dir
+        }
+    };
+    Ok(dir.join("omynix-waybar-manager.sock"))
+}
+
+/// Create `dir` with mode 0700, or check that an existing one belongs to
+/// the current user and is not open to anyone else
+fn private_dir(dir: &Path) -> Result<()> {
+    if let Err(e) = fs::DirBuilder::new().mode(0o700).create(dir)
+        && e.kind() != std::io::ErrorKind::AlreadyExists
+    {
+        return Err(e).context(format!("Error creating {}", dir.display()));
+    }
+
+    let meta = fs::symlink_metadata(dir).context(format!("Error reading {}", dir.display()))?;
+    if !meta.is_dir() || meta.uid() != getuid().as_raw() || meta.mode() & 0o077 != 0 {
+        return Err(anyhow::anyhow!(
+            "{} is not a private directory of the current user; remove it or set XDG_RUNTIME_DIR",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Accept control commands on a Unix socket and forward them as events.
+/// Each client is served on its own thread, so a client that stays
+/// connected does not block the others.
+pub fn spawn_control_socket(path: &Path, tx: Sender<Event>) -> Result<()> {
+    // A stale socket from a previous run would make bind fail
+    if path.exists() {
+        std::fs::remove_file(path).context("Error removing stale control socket")?;
+    }
+
+    let listener = UnixListener::bind(path).context("Error binding control socket")?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let tx = tx.clone();
+            thread::spawn(move || serve_control_client(stream, &tx));
+        }
+    });
+
+    Ok(())
+}
+
+/// Answer every command a control client sends until it disconnects
+fn serve_control_client(stream: UnixStream, tx: &Sender<Event>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        let reply = match ControlCommand::parse(&line) {
+            Some(cmd) => {
+                if tx.send(Event::Control(cmd)).is_err() {
+                    return;
+                }
+                "ok\n"
+            }
+            None => "error: unknown command\n",
+        };
+        writer.write_all(reply.as_bytes()).ok();
+    }
+}
+
+/// Send one command to a running daemon and return its reply
+pub fn send_control(path: &Path, command: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(path).context("The daemon is not running")?;
+    writeln!(stream, "{}", command)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim().to_string())
+}
+
+/// Poll `probe` every `interval` and send `event` whenever its value changes
+pub fn spawn_poller<T, F>(interval: Duration, event: Event, tx: Sender<Event>, mut probe: F)
+where
+    T: PartialEq + Send + 'static,
+    F: FnMut() -> Option<T> + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut last = probe();
+
+        loop {
+            thread::sleep(interval);
+
+            // Transient failures (e.g. the compositor restarting) are skipped
+            let Some(current) = probe() else {
+                continue;
+            };
+
+            if last.as_ref() != Some(&current) {
+                last = Some(current);
+                if tx.send(event.clone()).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Send `event` every `interval` until the receiver is gone
+pub fn spawn_ticker(interval: Duration, event: Event, tx: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            if tx.send(event.clone()).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Modification time of a file, used to detect config edits
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// One-line description of a monitor set change, e.g. `eDP-1 → eDP-1, HDMI-A-1`
+pub fn describe_transition(old: &[String], new: &[String]) -> String {
+    let side = |list: &[String]| {
+        if list.is_empty() {
+            "(none)".to_string()
+        } else {
+            list.join(", ")
+        }
+    };
+    format!("{} → {}", side(old), side(new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_private_dir_refuses_shared_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("sockets");
+        private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        private_dir(&dir).unwrap();
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(private_dir(&dir).is_err());
+    }
+
+    #[test]
+    fn test_control_socket_serves_clients_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let (tx, rx) = mpsc::channel();
+        spawn_control_socket(&path, tx).unwrap();
+
+        // An idle client must not hold up the next one
+        let _idle = UnixStream::connect(&path).unwrap();
+        assert_eq!(send_control(&path, "reload").unwrap(), "ok");
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            Event::Control(ControlCommand::Reload)
+        );
+    }
+
+    #[test]
+    fn test_parse_control_commands() {
+        assert_eq!(
+            ControlCommand::parse("reload"),
+            Some(ControlCommand::Reload)
+        );
+        assert_eq!(
+            ControlCommand::parse("switch-theme gruvbox"),
+            Some(ControlCommand::SwitchTheme("gruvbox".to_string()))
+        );
+        assert_eq!(ControlCommand::parse("switch-theme"), None);
+        assert_eq!(ControlCommand::parse("launch-missiles"), None);
+    }
+
+    #[test]
+    fn test_concurrent_events_coalesce() {
+        let (tx, rx) = mpsc::channel();
+
+        let senders: Vec<_> = [
+            Event::MonitorsChanged,
+            Event::ConfigChanged,
+            Event::Control(ControlCommand::SwitchTheme("nord".to_string())),
+            Event::MonitorsChanged,
+        ]
+        .into_iter()
+        .map(|event| {
+            let tx = tx.clone();
+            thread::spawn(move || tx.send(event).unwrap())
+        })
+        .collect();
+        drop(tx);
+        senders.into_iter().for_each(|h| h.join().unwrap());
+
+        let mut applied = Vec::new();
+        run_reconcile_loop(&rx, Duration::from_millis(50), |r| {
+            applied.push(r.clone());
+            Ok(())
+        });
+
+        assert_eq!(
+            applied,
+            vec![Reconcile {
+                monitors_changed: true,
+                config_changed: true,
+                reload_requested: false,
+                theme: Some("nord".to_string()),
+                supervise_tick: false,
+                shutdown: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_shutdown_leaves_the_loop_without_applying() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(Event::MonitorsChanged).unwrap();
+        tx.send(Event::Shutdown).unwrap();
+        tx.send(Event::ConfigChanged).unwrap();
+
+        let mut applied = 0;
+        // `tx` is still alive, so only the shutdown can end the loop
+        run_reconcile_loop(&rx, Duration::from_secs(60), |_| {
+            applied += 1;
+            Ok(())
+        });
+
+        assert_eq!(applied, 0);
+        drop(tx);
+    }
+
+    #[test]
+    fn test_supervisor_tracks_each_instance() {
+        let mut supervisor = Supervisor::new(2, Duration::from_secs(3));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(supervisor.on_dead("DP-1", at(0)), Decision::Restart);
+        // Backing off 3s, then 6s
+        assert_eq!(supervisor.on_dead("DP-1", at(1)), Decision::Wait);
+        assert_eq!(supervisor.on_dead("DP-1", at(3)), Decision::Restart);
+        assert_eq!(supervisor.on_dead("DP-1", at(8)), Decision::Wait);
+        assert_eq!(supervisor.on_dead("DP-1", at(9)), Decision::GiveUp);
+        assert_eq!(supervisor.on_dead("DP-1", at(100)), Decision::Failed);
+        assert_eq!(supervisor.restarts("DP-1"), 2);
+
+        // Another monitor has its own count
+        assert_eq!(supervisor.on_dead("eDP-1", at(9)), Decision::Restart);
+        assert_eq!(supervisor.restarts("eDP-1"), 1);
+
+        // Staying up a while clears the count, but not a given-up monitor
+        supervisor.on_alive("eDP-1", at(30));
+        assert_eq!(supervisor.restarts("eDP-1"), 1);
+        supervisor.on_alive("eDP-1", at(70));
+        supervisor.on_alive("DP-1", at(200));
+        assert_eq!(supervisor.restarts("eDP-1"), 0);
+        assert_eq!(supervisor.on_dead("DP-1", at(200)), Decision::Failed);
+
+        supervisor.reset();
+        assert_eq!(supervisor.on_dead("DP-1", at(200)), Decision::Restart);
+    }
+
+    #[test]
+    fn test_supervisor_reaps_restarted_bars() {
+        let mut supervisor = Supervisor::new(2, Duration::from_secs(3));
+        supervisor.adopt(std::process::Command::new("true").spawn().unwrap());
+        supervisor.adopt(
+            std::process::Command::new("sleep")
+                .arg("5")
+                .spawn()
+                .unwrap(),
+        );
+        thread::sleep(Duration::from_millis(300));
+
+        supervisor.reap();
+        assert_eq!(supervisor.children.len(), 1);
+
+        supervisor.children[0].kill().unwrap();
+        supervisor.children[0].wait().unwrap();
+        supervisor.reap();
+        assert!(supervisor.children.is_empty());
+    }
+
+    #[test]
+    fn test_describe_transition() {
+        let old = vec!["eDP-1".to_string()];
+        let new = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        assert_eq!(describe_transition(&old, &new), "eDP-1 → eDP-1, HDMI-A-1");
+        assert_eq!(describe_transition(&new, &[]), "eDP-1, HDMI-A-1 → (none)");
+    }
+}
@@ -0,0 +1,279 @@
+use crate::config::{self, Config};
+use crate::monitor;
+use crate::templates;
+use crate::window_manager::{self, WindowManager};
+use nix::unistd::{AccessFlags, access};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Outcome of one `doctor` check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    /// A failure that keeps bars from launching; `doctor` exits nonzero
+    pub critical: bool,
+    pub detail: String,
+    /// What to do about a failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            ok: true,
+            critical: false,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: &'static str, critical: bool, detail: impl Into<String>, hint: String) -> Self {
+        Check {
+            name,
+            ok: false,
+            critical,
+            detail: detail.into(),
+            hint: Some(hint),
+        }
+    }
+}
+
+/// Run every check in the order a launch depends on them. Nothing is
+/// created or changed, except a probe file in the generated directory when
+/// it already exists.
+pub fn run_checks(config_path: &Path, templates_dir: Option<&Path>) -> Vec<Check> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut checks = vec![waybar_check(&path)];
+
+    let loaded = config_path
+        .exists()
+        .then(|| config::load_config_from(config_path, false));
+    let cfg = match &loaded {
+        Some(Ok(cfg)) => cfg.clone(),
+        _ => Config::default(),
+    };
+
+    match window_manager::detect_window_manager(&cfg.detection.order) {
+        Ok(wm) => {
+            checks.push(Check::pass("compositor", format!("{:?}", wm)));
+            checks.push(query_tool_check(&wm, &path));
+            checks.push(template_check(&templates::get_templates_path(
+                &cfg,
+                &wm,
+                templates_dir,
+            )));
+        }
+        Err(e) => checks.push(Check::fail(
+            "compositor",
+            true,
+            e.to_string(),
+            "Run this from inside the compositor session, or check [detection] order".to_string(),
+        )),
+    }
+
+    checks.push(style_check(&templates::get_style_path(&cfg)));
+    checks.push(match loaded {
+        Some(Ok(_)) => Check::pass("config", config_path.display().to_string()),
+        Some(Err(e)) => Check::fail(
+            "config",
+            true,
+            format!("{}: {:#}", config_path.display(), e),
+            "Fix the file, or run 'omynix-waybar-manager config' to rewrite it".to_string(),
+        ),
+        None => Check::fail(
+            "config",
+            true,
+            format!("{} does not exist", config_path.display()),
+            "Run 'omynix-waybar-manager init' to create it".to_string(),
+        ),
+    });
+    checks.push(generated_dir_check(&templates::get_generated_dir()));
+
+    checks
+}
+
+/// Whether any failed check keeps bars from launching
+pub fn has_critical_failure(checks: &[Check]) -> bool {
+    checks.iter().any(|check| !check.ok && check.critical)
+}
+
+fn waybar_check(path: &OsStr) -> Check {
+    match find_in_path("waybar", path) {
+        Some(found) => Check::pass("waybar", found.display().to_string()),
+        None => Check::fail(
+            "waybar",
+            true,
+            "not found on PATH",
+            "Install waybar, or add its directory to PATH".to_string(),
+        ),
+    }
+}
+
+fn query_tool_check(wm: &WindowManager, path: &OsStr) -> Check {
+    let Some(command) = monitor::query_command(wm) else {
+        return Check::pass("query tool", format!("{:?} is queried over IPC", wm));
+    };
+    let tool = command.get_program().to_string_lossy();
+
+    match find_in_path(&tool, path) {
+        Some(found) => Check::pass("query tool", found.display().to_string()),
+        None => Check::fail(
+            "query tool",
+            true,
+            format!("{} not found on PATH", tool),
+            format!(
+                "Install {} to let the manager list monitors on {:?}",
+                tool, wm
+            ),
+        ),
+    }
+}
+
+fn template_check(path: &Path) -> Check {
+    let parsed = templates::read_template(path)
+        .and_then(|content| templates::template_types(&content, false));
+
+    match parsed {
+        Ok(types) => Check::pass(
+            "templates",
+            format!("{} ({} templates)", path.display(), types.len()),
+        ),
+        Err(_) if !path.exists() => Check::fail(
+            "templates",
+            true,
+            format!("{} does not exist", path.display()),
+            "Create it (see 'Create templates' in the README) or set [paths] templates_dir"
+                .to_string(),
+        ),
+        Err(e) => Check::fail(
+            "templates",
+            true,
+            format!("{}: {:#}", path.display(), e),
+            "Run with --print-template-markers to see how the file is read".to_string(),
+        ),
+    }
+}
+
+fn style_check(path: &Path) -> Check {
+    if path.exists() {
+        return Check::pass("stylesheet", path.display().to_string());
+    }
+
+    // waybar falls back to its own style.css, so bars still come up
+    Check::fail(
+        "stylesheet",
+        false,
+        format!("{} does not exist", path.display()),
+        "Create it or set [display] style_path".to_string(),
+    )
+}
+
+fn generated_dir_check(dir: &Path) -> Check {
+    match dir_writable(dir) {
+        Ok(()) => Check::pass("generated dir", dir.display().to_string()),
+        Err(e) => Check::fail(
+            "generated dir",
+            true,
+            format!("{}: {}", dir.display(), e),
+            "Fix the directory's ownership or permissions".to_string(),
+        ),
+    }
+}
+
+/// First executable named `program` in the directories of `path` (a
+/// `PATH`-style list)
+pub fn find_in_path(program: &str, path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| {
+            fs::metadata(candidate)
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        })
+}
+
+/// Check a file can be written in `dir` without creating it: with a probe
+/// file when it exists, else by the nearest existing ancestor being a
+/// writable directory it could be created in
+fn dir_writable(dir: &Path) -> std::io::Result<()> {
+    if dir.is_dir() {
+        let probe = dir.join(format!(".doctor-{}", std::process::id()));
+        fs::write(&probe, b"")?;
+        return fs::remove_file(&probe);
+    }
+
+    let ancestor = dir
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"));
+    if !ancestor.is_dir() {
+        return Err(std::io::Error::other(format!(
+            "{} is not a directory",
+            ancestor.display()
+        )));
+    }
+    access(ancestor, AccessFlags::W_OK | AccessFlags::X_OK).map_err(|e| {
+        std::io::Error::other(format!(
+            "cannot be created in {}: {}",
+            ancestor.display(),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_in_path_needs_an_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let waybar = bin.join("waybar");
+        fs::write(&waybar, "#!/bin/sh\n").unwrap();
+
+        let path = std::env::join_paths([dir.path().join("missing"), bin.clone()]).unwrap();
+        assert_eq!(find_in_path("waybar", &path), None);
+
+        fs::set_permissions(&waybar, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_in_path("waybar", &path), Some(waybar));
+        assert!(!waybar_check(&path).critical);
+        assert!(waybar_check(OsStr::new("")).critical);
+    }
+
+    #[test]
+    fn test_template_and_generated_dir_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("niri.jsonc");
+
+        let missing = template_check(&template);
+        assert!(!missing.ok && missing.critical);
+
+        fs::write(&template, "[\n  // TPL:FULL\n  {\"layer\": \"top\"}\n]").unwrap();
+        assert!(template_check(&template).ok);
+
+        fs::write(&template, "[ {").unwrap();
+        let broken = template_check(&template);
+        assert!(!broken.ok);
+        assert!(broken.hint.unwrap().contains("--print-template-markers"));
+
+        let generated = dir.path().join("generated");
+        assert!(generated_dir_check(&generated.join("nested")).ok);
+        assert!(!generated.exists());
+        fs::create_dir(&generated).unwrap();
+        assert!(generated_dir_check(&generated).ok);
+        assert_eq!(fs::read_dir(&generated).unwrap().count(), 0);
+
+        let blocked = generated_dir_check(&template.join("generated"));
+        assert!(!blocked.ok && blocked.critical);
+        assert!(blocked.detail.contains("not a directory"));
+
+        let checks = vec![style_check(&dir.path().join("missing.css"))];
+        assert!(!checks[0].ok);
+        assert!(!has_critical_failure(&checks));
+    }
+}
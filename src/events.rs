@@ -0,0 +1,119 @@
+use crate::daemon::Event;
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+/// Delay between reconnection attempts while the compositor is away
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Hyprland's event stream socket (socket2), if this session runs Hyprland
+pub fn hyprland_event_socket() -> Option<PathBuf> {
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+    Some(
+        PathBuf::from(runtime_dir)
+            .join("hypr")
+            .join(signature)
+            .join(".socket2.sock"),
+    )
+}
+
+/// Whether a socket2 line (`<event>>><data>`) reports an output being
+/// plugged in or removed; the `v2` variants count too
+fn is_monitor_event(line: &str) -> bool {
+    let name = line.split_once(">>").map_or(line, |(name, _)| name);
+    matches!(
+        name,
+        "monitoradded" | "monitoraddedv2" | "monitorremoved" | "monitorremovedv2"
+    )
+}
+
+/// Listen on Hyprland's event socket and send `MonitorsChanged` for every
+/// monitor event. When the socket drops (e.g. Hyprland restarting) the
+/// watcher keeps reconnecting and reports a change once it is back, since
+/// outputs may have changed meanwhile.
+pub fn spawn_hyprland_watcher(path: PathBuf, tx: Sender<Event>) {
+    thread::spawn(move || {
+        let mut reconnecting = false;
+
+        loop {
+            match UnixStream::connect(&path) {
+                Ok(stream) => {
+                    if reconnecting && tx.send(Event::MonitorsChanged).is_err() {
+                        return;
+                    }
+                    if !forward_monitor_events(stream, &tx) {
+                        return;
+                    }
+                }
+                Err(_) => thread::sleep(RECONNECT_DELAY),
+            }
+            reconnecting = true;
+        }
+    });
+}
+
+/// Forward monitor events until the stream ends; returns false once the
+/// receiving side is gone and the watcher should stop
+fn forward_monitor_events(stream: UnixStream, tx: &Sender<Event>) -> bool {
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if is_monitor_event(&line) && tx.send(Event::MonitorsChanged).is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Whether an event-driven watcher can be used at `path`
+pub fn socket_available(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixListener;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_only_monitor_events_match() {
+        assert!(is_monitor_event("monitoradded>>DP-1"));
+        assert!(is_monitor_event("monitoraddedv2>>1,DP-1,Dell U2720Q"));
+        assert!(is_monitor_event("monitorremoved>>HDMI-A-1"));
+        assert!(!is_monitor_event("workspace>>2"));
+        assert!(!is_monitor_event("focusedmon>>DP-1,2"));
+    }
+
+    #[test]
+    fn test_watcher_forwards_monitor_events_and_reconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".socket2.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        spawn_hyprland_watcher(path, tx);
+
+        // First connection: only the monitor event is forwarded
+        let (mut stream, _) = listener.accept().unwrap();
+        writeln!(stream, "workspace>>2").unwrap();
+        writeln!(stream, "monitoradded>>DP-1").unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            Event::MonitorsChanged
+        );
+        drop(stream);
+
+        // After the socket drops the watcher reconnects and reports a change
+        let (_stream, _) = listener.accept().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            Event::MonitorsChanged
+        );
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
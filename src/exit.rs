@@ -0,0 +1,87 @@
+use std::fmt;
+
+/// Exit code for any error without a more specific one
+pub const GENERAL_FAILURE: u8 = 1;
+
+/// Failures scripts and service managers may want to tell apart; each
+/// maps to its own process exit code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// None of the candidate window managers is running
+    NoWindowManager(String),
+    /// The window manager reported no connected monitors
+    NoMonitors,
+    /// Single mode and `preferred_monitor` is not connected
+    PreferredNotConnected(String),
+    /// An interactive prompt got an answer it could not use
+    InvalidInput(String),
+}
+
+impl Failure {
+    pub fn code(&self) -> u8 {
+        match self {
+            Failure::NoWindowManager(_) => 3,
+            Failure::NoMonitors => 4,
+            Failure::PreferredNotConnected(_) => 5,
+            Failure::InvalidInput(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::NoWindowManager(candidates) => write!(
+                f,
+                "No compatible window manager was detected ({})",
+                candidates
+            ),
+            Failure::NoMonitors => write!(f, "No connected monitors were detected"),
+            Failure::PreferredNotConnected(monitor) => {
+                write!(f, "Preferred monitor '{}' is not connected", monitor)
+            }
+            Failure::InvalidInput(input) => write!(f, "Invalid selection '{}'", input),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// The exit code for `err`: the code of the first `Failure` in its chain,
+/// so added context does not hide it
+pub fn code_for(err: &anyhow::Error) -> u8 {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<Failure>())
+        .map_or(GENERAL_FAILURE, Failure::code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_code_for_finds_failure_behind_context() {
+        let err = Err::<(), _>(Failure::NoMonitors)
+            .context("Launching Waybar")
+            .unwrap_err();
+        assert_eq!(code_for(&err), 4);
+
+        let other = anyhow::anyhow!("Config file is unreadable");
+        assert_eq!(code_for(&other), GENERAL_FAILURE);
+    }
+
+    #[test]
+    fn test_failure_codes_are_distinct() {
+        let codes = [
+            Failure::NoWindowManager(String::new()).code(),
+            Failure::NoMonitors.code(),
+            Failure::PreferredNotConnected(String::new()).code(),
+            Failure::InvalidInput(String::new()).code(),
+        ];
+        for (i, code) in codes.iter().enumerate() {
+            assert_ne!(*code, GENERAL_FAILURE);
+            assert!(!codes[i + 1..].contains(code));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::report::LaunchReport;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Snapshot written after every launch for external dashboards to poll
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Health {
+    /// Unix timestamp of the run
+    pub last_run: i64,
+    /// Detected window manager, absent when detection itself failed
+    pub wm: Option<String>,
+    pub monitor_count: usize,
+    /// Waybar processes running after the run
+    pub running_instances: usize,
+    pub last_error: Option<String>,
+}
+
+impl Health {
+    pub fn from_result(
+        result: &Result<LaunchReport>,
+        last_run: i64,
+        running_instances: usize,
+    ) -> Self {
+        match result {
+            Ok(report) => Health {
+                last_run,
+                wm: Some(report.wm.as_str().to_string()),
+                monitor_count: report.monitors.len(),
+                running_instances,
+                last_error: None,
+            },
+            Err(e) => Health {
+                last_run,
+                wm: None,
+                monitor_count: 0,
+                running_instances,
+                last_error: Some(format!("{:#}", e)),
+            },
+        }
+    }
+}
+
+pub fn get_health_path() -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().context("The data directory could not be retrieved")?;
+    Ok(data_dir.join("omynix/modules/waybar-manager/state/health.json"))
+}
+
+pub fn write_health(path: &Path, health: &Health) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Error creating the state directory")?;
+    }
+
+    // Write then rename so pollers never read a half-written file
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(health)?)
+        .context("Error writing the health file")?;
+    fs::rename(&tmp, path).context("Error writing the health file")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window_manager::WindowManager;
+    use serde_json::Value;
+
+    #[test]
+    fn test_health_file_schema_after_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state/health.json");
+
+        let mut report = LaunchReport::new(WindowManager::Niri);
+        report.monitors = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        write_health(&path, &Health::from_result(&Ok(report), 1_700_000_000, 2)).unwrap();
+
+        let value: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "last_run": 1_700_000_000,
+                "wm": "niri",
+                "monitor_count": 2,
+                "running_instances": 2,
+                "last_error": null,
+            })
+        );
+
+        let failed = Err(anyhow::anyhow!("No monitors were detected"));
+        write_health(&path, &Health::from_result(&failed, 1_700_000_060, 0)).unwrap();
+        let health: Health = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(health.wm, None);
+        assert_eq!(
+            health.last_error.as_deref(),
+            Some("No monitors were detected")
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{Result, anyhow};
+
+/// Line/column (both 1-based) of a character in the scanned source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    InString { escaped: bool },
+    InLineComment,
+    InBlockComment { start: Position },
+}
+
+/// `content` without the UTF-8 byte order mark some editors write at the
+/// start of a file, which serde rejects
+pub fn strip_bom(content: &str) -> &str {
+    content.strip_prefix('\u{feff}').unwrap_or(content)
+}
+
+/// Remove `//` and `/* */` comments from JSONC, leaving string contents
+/// untouched. Newlines are preserved so serde error positions still match
+/// the original file.
+pub fn strip_comments(content: &str) -> Result<String> {
+    let mut result = String::with_capacity(content.len());
+    let mut state = State::Normal;
+    let mut pos = Position { line: 1, column: 1 };
+    let mut chars = content.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        let here = pos;
+        if ch == '\n' {
+            pos.line += 1;
+            pos.column = 1;
+        } else {
+            pos.column += 1;
+        }
+
+        state = match state {
+            State::Normal => match ch {
+                '/' if chars.peek() == Some(&'/') => {
+                    chars.next();
+                    pos.column += 1;
+                    State::InLineComment
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    pos.column += 1;
+                    // Keep tokens on either side of the comment apart
+                    result.push(' ');
+                    State::InBlockComment { start: here }
+                }
+                '"' => {
+                    result.push(ch);
+                    State::InString { escaped: false }
+                }
+                _ => {
+                    result.push(ch);
+                    State::Normal
+                }
+            },
+            State::InString { escaped } => {
+                result.push(ch);
+                if escaped {
+                    State::InString { escaped: false }
+                } else if ch == '\\' {
+                    State::InString { escaped: true }
+                } else if ch == '"' {
+                    State::Normal
+                } else {
+                    State::InString { escaped: false }
+                }
+            }
+            State::InLineComment => {
+                if ch == '\n' {
+                    result.push('\n');
+                    State::Normal
+                } else {
+                    State::InLineComment
+                }
+            }
+            State::InBlockComment { start } => {
+                if ch == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    pos.column += 1;
+                    State::Normal
+                } else {
+                    if ch == '\n' {
+                        result.push('\n');
+                    }
+                    State::InBlockComment { start }
+                }
+            }
+        };
+    }
+
+    if let State::InBlockComment { start } = state {
+        return Err(anyhow!(
+            "Unterminated block comment starting at line {}, column {}",
+            start.line,
+            start.column
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Quote `{{name}}` placeholders written as bare JSON values (e.g.
+/// `"width": {{width}}`) so the template parses; placeholders inside
+/// strings are left alone. Run on comment-free content.
+pub fn quote_bare_placeholders(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = content;
+
+    while let Some(ch) = rest.chars().next() {
+        if !in_string
+            && rest.starts_with("{{")
+            && let Some(end) = rest.find("}}")
+            && end > 2
+            && rest[2..end]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            result.push('"');
+            result.push_str(&rest[..end + 2]);
+            result.push('"');
+            rest = &rest[end + 2..];
+            continue;
+        }
+
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_string = false;
+            }
+        } else if ch == '"' {
+            in_string = true;
+        }
+
+        result.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_strip_line_and_block_comments() {
+        let src = "[\n  // TPL:FULL\n  {\"a\": 1 /* inline */, \"b\": 2}\n]";
+        let stripped = strip_comments(src).unwrap();
+        let value: Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(value, serde_json::json!([{"a": 1, "b": 2}]));
+        assert_eq!(stripped.lines().count(), src.lines().count());
+    }
+
+    #[test]
+    fn test_comment_after_escaped_quote() {
+        // `\"` inside a string must not end it, and the `//` after the
+        // closing quote is a real comment
+        let src = r#"["say \"hi\""// trailing
+]"#;
+        let value: Value = serde_json::from_str(&strip_comments(src).unwrap()).unwrap();
+        assert_eq!(value[0], "say \"hi\"");
+    }
+
+    #[test]
+    fn test_escaped_backslash_at_string_end() {
+        // `\\"` closes the string: the backslash is itself escaped
+        let src = r#"["C:\\", "// not a comment", "/* nor this */"] // comment"#;
+        let value: Value = serde_json::from_str(&strip_comments(src).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!(["C:\\", "// not a comment", "/* nor this */"])
+        );
+    }
+
+    #[test]
+    fn test_bare_placeholders_are_quoted() {
+        let src = r#"{"width": {{width}}, "format": "{{monitor}} {}", "list": [{{scale}}]}"#;
+        let value: Value = serde_json::from_str(&quote_bare_placeholders(src)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"width": "{{width}}", "format": "{{monitor}} {}", "list": ["{{scale}}"]})
+        );
+
+        // Ordinary JSON objects are not mistaken for placeholders
+        let src = r#"{"a": {"b": {}}}"#;
+        assert_eq!(quote_bare_placeholders(src), src);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment_reports_position() {
+        let err = strip_comments("[\n  1, /* oops\n]").unwrap_err();
+        assert!(err.to_string().contains("line 2, column 6"), "{}", err);
+    }
+
+    /// Property test: for random strings built from the characters the
+    /// scanner cares about, stripping comments around them never alters
+    /// the decoded string contents.
+    #[test]
+    fn test_strip_never_alters_strings() {
+        const ALPHABET: &[char] = &['"', '\\', '/', '*', '\n', 'a', ' ', '{', '}'];
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..500 {
+            let strings: Vec<String> = (0..(next() % 5))
+                .map(|_| {
+                    (0..(next() % 12))
+                        .map(|_| ALPHABET[(next() % ALPHABET.len() as u64) as usize])
+                        .collect()
+                })
+                .collect();
+
+            let mut src = String::from("[ // open\n");
+            for (i, s) in strings.iter().enumerate() {
+                if i > 0 {
+                    src.push_str(", /* sep */ ");
+                }
+                src.push_str(&serde_json::to_string(s).unwrap());
+                src.push_str("// after\n");
+            }
+            src.push(']');
+
+            let stripped = strip_comments(&src).unwrap();
+            let decoded: Vec<String> = serde_json::from_str(&stripped).unwrap();
+            assert_eq!(decoded, strings, "source: {:?}", src);
+        }
+    }
+}
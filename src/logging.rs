@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set for `launch --oneline`: progress printed with `say!` is dropped,
+/// while warnings and errors are still printed
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for progress output, which `set_quiet` silences
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::logging::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Size at which the log file is rotated to `manager.log.1`
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Writes log records to stderr and, with `--log-file`, appends them to a
+/// size-capped file. Filtering goes through `log::max_level`, so commands
+/// can raise the level later (e.g. `launch --verbose`).
+struct Logger {
+    file: Option<Mutex<LogFile>>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Error creating log directory: {}", dir.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Error opening log file: {}", path.display()))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    /// Append `line`, first moving a full file to `<name>.1` (replacing the
+    /// previous one)
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size + line.len() as u64 > MAX_LOG_SIZE && self.size > 0 {
+            fs::rename(&self.path, rotated_path(&self.path))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = 0;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "[{} {}] {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            record.level(),
+            record.args()
+        );
+        eprint!("{}", line);
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            // A full disk must not take the manager down with it
+            let _ = file.write_line(&line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Install the logger; `file` mirrors every record to that path
+pub fn init(level: LevelFilter, file: Option<&Path>) -> Result<()> {
+    let file = file.map(LogFile::open).transpose()?.map(Mutex::new);
+    log::set_boxed_logger(Box::new(Logger { file })).context("Logger already installed")?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Log at `level` or more detail from now on
+pub fn raise_level(level: LevelFilter) {
+    if level > log::max_level() {
+        log::set_max_level(level);
+    }
+}
+
+/// Drop progress output printed with `say!` from now on
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Default `--log-file` location, next to the generation cache
+pub fn default_log_path() -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().context("The data directory could not be retrieved")?;
+    Ok(data_dir.join("omynix/modules/waybar-manager/manager.log"))
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file_rotates_at_size_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/manager.log");
+        let mut log = LogFile::open(&path).unwrap();
+
+        let line = format!("{}\n", "x".repeat(1023));
+        for _ in 0..1024 {
+            log.write_line(&line).unwrap();
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len(), MAX_LOG_SIZE);
+        assert!(!rotated_path(&path).exists());
+
+        log.write_line("next\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "next\n");
+        assert_eq!(
+            fs::metadata(rotated_path(&path)).unwrap().len(),
+            MAX_LOG_SIZE
+        );
+    }
+}
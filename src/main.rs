@@ -1,29 +1,112 @@
 mod cache;
 mod config;
+mod daemon;
+mod doctor;
+mod events;
+mod exit;
+mod health;
+mod jsonc;
+mod logging;
 mod monitor;
+mod notify;
+mod process;
+mod report;
+mod status;
 mod templates;
 mod window_manager;
 use std::fs;
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use colored::*;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
+use cache::CacheStore;
+use exit::Failure;
+
 #[derive(Parser)]
 #[command(
     name = "waybar-manager",
     about = "Waybar Manager - Intelligent waybar manager for multiple monitors and Windows Manager - Niri,
-    Hyprland and MangoWc",
+    Hyprland, MangoWc, Sway and River",
     version
 )]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Directory holding the generation cache (defaults to $XDG_DATA_HOME)
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+    /// Disable all desktop notifications
+    #[arg(long, global = true)]
+    no_notify: bool,
+    /// Fail on a malformed config file instead of backing it up and using defaults
+    #[arg(long, global = true)]
+    no_recover: bool,
+    /// Output format for commands that support machine-readable output (monitors, status)
+    #[arg(long, alias = "format", global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    /// When to color the output
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Config file to use instead of the default (also OMYNIX_WAYBAR_CONFIG)
+    #[arg(long, global = true, alias = "config-file", value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Directory holding `<wm>.jsonc` templates (overrides `[paths] templates_dir`)
+    #[arg(long, global = true)]
+    templates_dir: Option<PathBuf>,
+    /// Print the environment and process probes used for window manager detection
+    #[arg(long)]
+    dump_env: bool,
+    /// Print each `// TPL:` marker of the template file and the object it was matched to
+    #[arg(long)]
+    print_template_markers: bool,
+    /// Diagnostics written to stderr (--verbose raises it to debug)
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Warn)]
+    log_level: LogLevel,
+    /// Also append diagnostics to ~/.local/share/omynix/modules/waybar-manager/manager.log
+    #[arg(long, global = true)]
+    log_file: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
 }
 
 #[derive(Subcommand)]
@@ -33,72 +116,630 @@ enum Commands {
     Init,
     /// Check current configuration
     #[command(alias = "ch", visible_alias = "--check")]
-    Check,
+    Check {
+        /// Apply a synchronized configuration to the running bars
+        #[arg(long)]
+        apply: bool,
+    },
     /// Launch waybar on detected monitors
     #[command(alias = "l", visible_alias = "--launch")]
     Launch {
         /// Force configuration update without asking
         #[arg(short, long)]
         force_update: bool,
+        /// Accept the monitor-sync prompt without asking (for scripts and
+        /// autostart); same as --force-update
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Regenerate the config files even if the cache says they are current
+        #[arg(long)]
+        force_regenerate: bool,
+        /// Verbose mode for debugging
+        #[arg(short, long)]
+        verbose: bool,
+        /// Do nothing if waybar was launched recently and nothing changed
+        #[arg(long)]
+        since_boot: bool,
+        /// Briefly launch waybar on each generated config to validate it first (slower)
+        #[arg(long)]
+        check_templates_against_waybar: bool,
+        /// Use a built-in minimal template when the template file has none (for template editing)
+        #[arg(long)]
+        allow_empty_templates: bool,
+        /// Use the focused monitor as preferred for this launch (not saved)
+        #[arg(long)]
+        preferred_from_focus: bool,
+        /// Print every cache check and its inputs before deciding whether to regenerate
+        #[arg(long)]
+        explain_cache: bool,
+        /// Assign a template to a monitor for this run, e.g. `DP-1=full` (repeatable)
+        #[arg(long, value_name = "MONITOR=TEMPLATE", value_parser = parse_assignment)]
+        assign: Vec<(String, String)>,
+        /// Also launch bars on outputs the compositor reports as disabled
+        #[arg(long)]
+        include_disabled: bool,
+        /// After launching, check that every monitor has exactly one bar
+        #[arg(long)]
+        verify_after: bool,
+        /// Stop duplicate and unexpected bars found by --verify-after
+        #[arg(long, requires = "verify_after")]
+        auto_correct: bool,
+        /// Abort instead of warning when a generated config lacks expected keys
+        #[arg(long)]
+        strict: bool,
+        /// Print only a one-line summary (for compositor startup logs)
+        #[arg(long)]
+        oneline: bool,
+        /// Show what would be generated and launched without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Keep using generated configs that were edited by hand instead of
+        /// regenerating them
+        #[arg(long)]
+        no_regen_on_drift: bool,
+        /// Detect, generate and cache now, leaving the running bars alone
+        /// until `launch --commit`
+        #[arg(long, conflicts_with_all = ["commit", "dry_run"])]
+        prepare: bool,
+        /// Replace the running bars with the ones `launch --prepare` generated,
+        /// without detecting or generating again
+        #[arg(long, conflicts_with_all = [
+            "force_update", "yes", "force_regenerate", "since_boot",
+            "check_templates_against_waybar", "allow_empty_templates",
+            "preferred_from_focus", "explain_cache", "assign", "include_disabled",
+            "verify_after", "auto_correct", "strict", "oneline", "dry_run",
+            "no_regen_on_drift",
+        ])]
+        commit: bool,
+    },
+    /// Relaunch waybar on the cached generated configs without regenerating them
+    Restart {
         /// Verbose mode for debugging
         #[arg(short, long)]
         verbose: bool,
     },
+    /// Sync available_monitors, regenerate if needed and update the cache
+    /// without touching running bars or prompting
+    Refresh,
+    /// Apply config changes to the running bars with SIGUSR2 (no flicker);
+    /// relaunches only when the monitors changed
+    Reload,
+    /// Stop every running waybar instance
+    Stop {
+        /// Send SIGKILL instead of SIGTERM
+        #[arg(long)]
+        force: bool,
+    },
     /// Show detected monitors
     #[command(alias = "m", visible_alias = "--monitors")]
-    Monitors,
+    Monitors {
+        /// Stream a JSON line each time the set of connected monitors changes
+        #[arg(long)]
+        watch: bool,
+        /// Seconds between monitor polls with --watch (Hyprland uses its events)
+        #[arg(long, default_value_t = 3, requires = "watch")]
+        interval: u64,
+    },
+    /// Show running waybar instances, the monitors they serve and the cache state
+    Status,
     /// Configure monitors and behavior interactively
-    #[command(alias = "c", visible_alias = "--config")]
+    #[command(alias = "c")]
     Config,
+    /// Keep bars in sync with monitor, config and control-socket events
+    Daemon {
+        /// Seconds between monitor/config polls
+        #[arg(long, default_value_t = 3)]
+        interval: u64,
+        /// Milliseconds to wait for more events before reconciling
+        #[arg(long, default_value_t = 500)]
+        debounce_ms: u64,
+        /// Relaunch the bar of a monitor whose waybar exited, leaving the others running
+        #[arg(long)]
+        supervise: bool,
+        /// Restarts of one bar (backing off between them) before giving up on it
+        #[arg(long, default_value_t = 5, requires = "supervise")]
+        max_restarts: u32,
+        /// Seconds before restarting an exited bar, doubling after each restart
+        #[arg(long, default_value_t = 2, requires = "supervise")]
+        backoff: u64,
+    },
+    /// Relaunch bars whenever the set of connected monitors changes
+    Watch {
+        /// Seconds between monitor polls
+        #[arg(long, default_value_t = 3)]
+        interval: u64,
+        /// Milliseconds to wait for more changes before relaunching
+        #[arg(long, default_value_t = 500)]
+        debounce_ms: u64,
+    },
+    /// Switch the active theme (or show it when no name is given)
+    Theme {
+        /// Theme directory name under ~/.config/waybar/themes, or "default"
+        name: Option<String>,
+    },
+    /// Render every profile against its own monitors and report pass/fail (for CI)
+    ApplyAllProfiles {
+        /// Window manager whose templates to render (default: detected)
+        #[arg(long)]
+        wm: Option<String>,
+    },
+    /// Generate and validate configs for simulated monitors in a temp dir
+    Selftest,
+    /// List, switch or create display profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Inspect the generation cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// List the modules each template places left, center and right
+    Modules {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check waybar, the compositor, templates, stylesheet and config, with
+    /// a hint for each problem
+    Doctor,
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate the script for
+        shell: clap_complete::Shell,
+    },
     /// Show versión information
     #[command(alias = "v", visible_alias = "--version")]
     Version,
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// Show the configured profiles, marking the active one
+    List,
+    /// Make a profile active ("default" goes back to [display])
+    Use { name: String },
+    /// Create a profile from the current display settings
+    Create { name: String },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Print the cached template hash, monitors, preferred monitor and timestamp
+    Show {
+        /// Print as JSON instead of TOML
+        #[arg(long)]
+        json: bool,
+    },
+    /// Summarize the cache in a readable form
+    Info,
+    /// Delete the cache so the next launch regenerates everything
+    Clear {
+        /// Also delete ~/.config/waybar/generated
+        #[arg(long)]
+        generated: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(exit::code_for(&err))
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.color {
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+        ColorChoice::Auto => {}
+    }
+    if cli.output == OutputFormat::Json {
+        colored::control::set_override(false);
+    }
+    let log_file = if cli.log_file {
+        Some(logging::default_log_path()?)
+    } else {
+        None
+    };
+    logging::init(cli.log_level.into(), log_file.as_deref())?;
+    if cli.dump_env {
+        println!("{}", window_manager::dump_env());
+        return Ok(());
+    }
+    // What every command passes on to a launch
+    let base = LaunchOptions {
+        no_notify: cli.no_notify,
+        no_recover: cli.no_recover,
+        config_path: config::get_config_path(cli.config.as_deref())?,
+        templates_dir: cli.templates_dir.clone(),
+        ..Default::default()
+    };
+    if cli.print_template_markers {
+        return print_template_markers(&base);
+    }
+    let cache_store = cache::FileCache::open(cli.cache_dir.as_deref());
+    let detection = monitor::Detection::default();
 
     match cli.command {
         Some(Commands::Init) => {
             println!("{}", "Initializing configuration...".green().bold());
-            config::init_config()?;
+            config::init_config(&base.config_path)?;
             println!("{}", "✓ Configuration created successfully".green());
         }
-        Some(Commands::Check) => {
-            check_configuration()?;
+        Some(Commands::Check { apply }) => {
+            check_configuration(apply, &base, &detection, &cache_store)?;
         }
         Some(Commands::Launch {
             force_update,
+            yes,
+            force_regenerate,
             verbose,
+            since_boot,
+            check_templates_against_waybar,
+            allow_empty_templates,
+            preferred_from_focus,
+            assign,
+            explain_cache,
+            include_disabled,
+            verify_after,
+            auto_correct,
+            strict,
+            oneline,
+            dry_run,
+            no_regen_on_drift,
+            prepare,
+            commit,
         }) => {
-            launch_waybar(force_update, verbose)?;
+            if verbose {
+                logging::raise_level(log::LevelFilter::Debug);
+            }
+            if commit {
+                return commit_prepared(&base, &cache_store);
+            }
+            let opts = LaunchOptions {
+                force_update: force_update || yes,
+                force_regenerate,
+                since_boot,
+                check_templates_against_waybar,
+                allow_empty_templates,
+                preferred_from_focus,
+                assign,
+                explain_cache,
+                include_disabled,
+                verify_after,
+                auto_correct,
+                strict,
+                dry_run,
+                prepare_only: prepare,
+                no_regen_on_drift,
+                ..base.clone()
+            };
+            if oneline {
+                logging::set_quiet(true);
+                let report = launch_waybar(&opts, &detection, &cache_store)?;
+                println!("{}", report.summary());
+            } else {
+                let report = launch_waybar(&opts, &detection, &cache_store)?;
+                if verbose {
+                    println!("{}", report.summary().dimmed());
+                }
+            }
+        }
+        Some(Commands::Restart { verbose }) => {
+            if verbose {
+                logging::raise_level(log::LevelFilter::Debug);
+            }
+            restart_waybar(&base, &detection, &cache_store)?;
+        }
+        Some(Commands::Refresh) => {
+            // `launch --prepare --yes`
+            let opts = LaunchOptions {
+                force_update: true,
+                prepare_only: true,
+                ..base.clone()
+            };
+            let report = launch_waybar(&opts, &detection, &cache_store)?;
+            println!("{}", report.summary());
+        }
+        Some(Commands::Reload) => {
+            let opts = LaunchOptions {
+                reload_only: true,
+                ..base.clone()
+            };
+            launch_waybar(&opts, &detection, &cache_store)?;
         }
-        Some(Commands::Monitors) => {
-            show_monitors()?;
+        Some(Commands::Stop { force }) => {
+            if !monitor::is_waybar_running() {
+                println!("{}", "Waybar is not running".yellow());
+            } else {
+                let stopped = if force {
+                    monitor::force_kill_waybar()?
+                } else {
+                    monitor::kill_waybar()?
+                };
+                println!("{} Stopped {} waybar instance(s)", "✓".green(), stopped);
+            }
+        }
+        Some(Commands::Monitors { watch, interval }) => {
+            if watch {
+                stream_monitors(interval, &base)?;
+            } else {
+                show_monitors(cli.output, &base, &detection)?;
+            }
+        }
+        Some(Commands::Status) => {
+            show_status(cli.output, &base, &detection, &cache_store)?;
         }
         Some(Commands::Config) => {
-            interactive_config()?;
+            interactive_config(&base, &detection)?;
+        }
+        Some(Commands::Daemon {
+            interval,
+            debounce_ms,
+            supervise,
+            max_restarts,
+            backoff,
+        }) => {
+            let supervisor = supervise
+                .then(|| daemon::Supervisor::new(max_restarts, Duration::from_secs(backoff)));
+            run_daemon(interval, debounce_ms, &base, supervisor, &cache_store)?;
+        }
+        Some(Commands::Watch {
+            interval,
+            debounce_ms,
+        }) => {
+            run_watch(interval, debounce_ms, &base, &cache_store)?;
+        }
+        Some(Commands::Theme { name: None }) => {
+            let cfg = config::load_config(&base.config_path, !base.no_recover)?;
+            println!(
+                "Active theme: {}",
+                cfg.display.theme.as_deref().unwrap_or("default").cyan()
+            );
+        }
+        Some(Commands::Theme { name: Some(name) }) => {
+            // Let a running daemon apply it so its state stays consistent
+            let command = format!("switch-theme {}", name);
+            if let Ok(reply) = daemon::send_control(&daemon::get_socket_path()?, &command) {
+                println!("Daemon: {}", reply);
+            } else {
+                switch_theme(&name, &base, &detection, &cache_store)?;
+            }
+        }
+        Some(Commands::ApplyAllProfiles { wm }) => {
+            apply_all_profiles(wm.as_deref(), &base)?;
+        }
+        Some(Commands::Selftest) => {
+            let dir =
+                std::env::temp_dir().join(format!("omynix-waybar-selftest-{}", std::process::id()));
+            fs::create_dir_all(&dir)?;
+            let result = templates::selftest(&dir);
+            fs::remove_dir_all(&dir).ok();
+
+            for path in result? {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                println!("  {} {}", "✓".green(), name);
+            }
+            println!("{} Self-test passed", "✓".green());
+        }
+        Some(Commands::Profile { action }) => {
+            manage_profiles(action, &base)?;
+        }
+        Some(Commands::Cache { action }) => match action {
+            CacheAction::Show { json } => {
+                let entry = cache_store.load()?;
+                println!("{}", cache::format_cache(entry.as_ref(), json)?);
+            }
+            CacheAction::Info => {
+                let entry = cache_store.load()?;
+                println!("{}", cache::format_cache_info(entry.as_ref()));
+            }
+            CacheAction::Clear { generated } => {
+                let cached = cache::cached_files(cache_store.load()?.as_ref());
+                cache_store.clear()?;
+                println!("{} Cache cleared", "✓".green());
+                if generated {
+                    let dir = templates::get_generated_dir();
+                    let (removed, kept) = templates::remove_generated(&dir, &cached)?;
+                    println!(
+                        "{} Removed {} generated file(s) from {}",
+                        "✓".green(),
+                        removed.len(),
+                        dir.display()
+                    );
+                    for path in kept {
+                        println!(
+                            "{}",
+                            format!("⚠ Kept {}, which the manager did not write", path.display())
+                                .yellow()
+                        );
+                    }
+                }
+            }
+        },
+        Some(Commands::Modules { json }) => {
+            show_modules(json || cli.output == OutputFormat::Json, &base)?;
+        }
+        Some(Commands::Doctor) => {
+            run_doctor(&base)?;
+        }
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                env!("CARGO_BIN_NAME"),
+                &mut io::stdout(),
+            );
         }
         Some(Commands::Version) => {
             println!("waybar-manager v{}", env!("CARGO_PKG_VERSION"));
         }
         None => {
             // Default behavior: launch waybar
-            launch_waybar(false, false)?;
+            launch_waybar(&base, &detection, &cache_store)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Default, Clone)]
+struct LaunchOptions {
+    force_update: bool,
+    force_regenerate: bool,
+    since_boot: bool,
+    no_notify: bool,
+    no_recover: bool,
+    check_templates_against_waybar: bool,
+    allow_empty_templates: bool,
+    preferred_from_focus: bool,
+    /// `--assign` entries as (monitor, template name)
+    assign: Vec<(String, String)>,
+    explain_cache: bool,
+    /// Send SIGUSR2 to running bars instead of killing and relaunching them
+    reload_only: bool,
+    /// Sync, regenerate and update the cache, but leave the bars alone
+    prepare_only: bool,
+    include_disabled: bool,
+    verify_after: bool,
+    auto_correct: bool,
+    strict: bool,
+    /// Plan only: no files written, no bars stopped or started
+    dry_run: bool,
+    /// Don't regenerate configs whose contents changed since they were written
+    no_regen_on_drift: bool,
+    /// Config file read and written by this run
+    config_path: PathBuf,
+    /// `--templates-dir`, taking precedence over `[paths] templates_dir`
+    templates_dir: Option<PathBuf>,
+}
+
+fn parse_assignment(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((monitor, template)) if !monitor.is_empty() && !template.is_empty() => {
+            Ok((monitor.to_string(), template.to_string()))
+        }
+        _ => Err(format!("expected MONITOR=TEMPLATE, got '{}'", value)),
+    }
+}
+
+fn manage_profiles(action: ProfileAction, opts: &LaunchOptions) -> Result<()> {
+    let resolved = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let mut cfg = resolved.unresolved();
+
+    match action {
+        ProfileAction::List => {
+            let active = cfg.active_profile.as_deref();
+            let marker = |selected: bool| if selected { "*".green() } else { " ".normal() };
+            println!("{} default", marker(active.is_none()));
+            for name in cfg.profiles.keys() {
+                println!("{} {}", marker(active == Some(name.as_str())), name);
+            }
+        }
+        ProfileAction::Use { name } => {
+            if name == "default" && !cfg.profiles.contains_key(&name) {
+                cfg.active_profile = None;
+            } else if cfg.profiles.contains_key(&name) {
+                cfg.active_profile = Some(name.clone());
+            } else {
+                anyhow::bail!(
+                    "No profile named '{}' (create it with `profile create {}`)",
+                    name,
+                    name
+                );
+            }
+            config::save_config(&opts.config_path, &cfg)?;
+            println!("{} Active profile: {}", "✓".green(), name.cyan());
+        }
+        ProfileAction::Create { name } => {
+            if cfg.profiles.contains_key(&name) {
+                anyhow::bail!("Profile '{}' already exists", name);
+            }
+            // The display settings in effect, i.e. the active profile's
+            cfg.profiles.insert(name.clone(), resolved.display);
+            config::save_config(&opts.config_path, &cfg)?;
+            println!(
+                "{} Created profile {} from the current display settings",
+                "✓".green(),
+                name.cyan()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_all_profiles(wm: Option<&str>, opts: &LaunchOptions) -> Result<()> {
+    let cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = match wm {
+        Some(name) => window_manager::WindowManager::from_name(name)
+            .with_context(|| format!("Unknown window manager: {}", name))?,
+        None => window_manager::detect_window_manager(&cfg.detection.order)?,
+    };
+
+    if cfg.profiles.is_empty() {
+        println!(
+            "{}",
+            "⚠ No [profiles.<name>] sections are configured".yellow()
+        );
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for name in cfg.profiles.keys() {
+        let profile = cfg.with_profile(name).unwrap();
+
+        // Simulate the monitors the profile was set up for
+        let mut monitors = profile.display.available_monitors.clone();
+        if monitors.is_empty() && !profile.display.preferred_monitor.is_empty() {
+            monitors.push(profile.display.preferred_monitor.clone());
+        }
+
+        let result = if monitors.is_empty() {
+            Err(anyhow::anyhow!("no monitors to simulate"))
+        } else {
+            templates::resolve_templates(&profile, &wm, opts.templates_dir.as_deref(), false)
+                .and_then(|file| templates::render_all(&profile, &file, &monitors))
+        };
+
+        match result {
+            Ok(bars) => println!("  {} {} ({} bars)", "✓".green(), name.cyan(), bars.len()),
+            Err(e) => {
+                failed += 1;
+                println!("  {} {}: {:#}", "✗".red(), name.cyan(), e);
+            }
         }
     }
 
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} profiles failed",
+            failed,
+            cfg.profiles.len()
+        ));
+    }
+
     Ok(())
 }
 
-fn check_configuration() -> Result<()> {
+fn check_configuration(
+    apply: bool,
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
     println!("{}", "─────────────────────────────────".cyan());
     println!("{}", "Checking configuration".cyan());
     println!();
 
-    let mut cfg = config::load_config()?;
-    let wm = window_manager::detect_window_manager()?;
-    let connected = monitor::get_connected_monitors(&wm)?;
+    let mut cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let connected = detection.monitors(&wm)?;
 
     println!("{}", "Current configuration:".yellow().bold());
     println!(
@@ -119,7 +760,11 @@ fn check_configuration() -> Result<()> {
     }
     println!();
 
-    let matches = monitor::find_matches(&cfg.display.available_monitors, &connected);
+    let matches = monitor::find_matches(
+        &cfg.display.available_monitors,
+        &connected,
+        cfg.display.case_insensitive_match,
+    );
     println!("{}", "Coincidences:".yellow().bold());
     if matches.is_empty() {
         println!("  {} There are no coincidences.", "⚠".yellow());
@@ -136,82 +781,835 @@ fn check_configuration() -> Result<()> {
         println!("Mode: {}", "Multiple Monitors".cyan());
     }
 
-    println!();
+    println!();
+
+    // Check for differences and offer to synchronize
+    let needs_update = if cfg.display.mode == "single" {
+        // In single mode, just verify that your preferred monitor is connected.
+        monitor::find_monitor(
+            &connected,
+            &cfg.display.preferred_monitor,
+            cfg.display.case_insensitive_match,
+        )
+        .is_none()
+    } else {
+        // In multiple mode, verify that the lists match.
+        !monitor::lists_match(
+            &cfg.display.available_monitors,
+            &connected,
+            cfg.display.case_insensitive_match,
+        )
+    };
+
+    if needs_update {
+        println!("{}", "─────────────────────────────────".yellow());
+        println!("{}", "║  ⚠  Differences were detected  ".yellow());
+        println!();
+
+        if cfg.display.mode == "single" {
+            println!("{}", "⚠ In 'single' mode, you must have the 'preferred_monitor' option configured to disable this alert. The first monitor detected will be used.".yellow());
+            println!(
+                "{}",
+                "  Run 'omynix-waybar-manager config' to reconfigure".cyan()
+            );
+            println!();
+            return Err(
+                Failure::PreferredNotConnected(cfg.display.preferred_monitor.clone()).into(),
+            );
+        } else if ask_update_config_sync()? {
+            print_sync_diff(&cfg.display.available_monitors, &connected, &cfg);
+            let preferred = cfg.display.preferred_monitor.clone();
+            config::sync_available_monitors(&opts.config_path, &mut cfg, &connected, &preferred)?;
+            println!("{} Configuration successfully synchronized\n", "✓".green());
+
+            if apply {
+                apply_synced_config(&mut cfg, &wm, &connected, opts, detection, cache_store)?;
+            }
+        } else {
+            println!("{} Outdated configuration\n", "⚠".yellow());
+        }
+    } else {
+        println!("{} The configuration is synchronized\n", "✓".green());
+    }
+
+    Ok(())
+}
+
+/// Show how the markers of the current template file map to its objects
+fn print_template_markers(opts: &LaunchOptions) -> Result<()> {
+    let cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let path = templates::get_templates_path(&cfg, &wm, opts.templates_dir.as_deref());
+    let (markers, objects) = templates::template_markers(&templates::read_template(&path)?)?;
+
+    println!(
+        "{} {} ({} objects)",
+        "Template markers in".green().bold(),
+        path.display(),
+        objects
+    );
+    for marker in &markers {
+        let target = match marker.object {
+            Some(index) => format!("object {}", index),
+            None => "no object (unused)".yellow().to_string(),
+        };
+        println!(
+            "  line {:>4}: {} → {}",
+            marker.line,
+            marker.template_type.name().cyan(),
+            target
+        );
+    }
+    for index in markers.len()..objects {
+        println!(
+            "  {} object {} has no marker, using {}",
+            "⚠".yellow(),
+            index,
+            templates::default_template_type(index).name().cyan()
+        );
+    }
+
+    Ok(())
+}
+
+/// Give killed bars up to 3s to exit before new ones are started
+fn wait_for_waybar_exit() {
+    if !monitor::wait_for_waybar_exit(Duration::from_secs(3)) {
+        println!(
+            "{}",
+            "⚠ Waybar is still running after 3s, starting the new bars anyway".yellow()
+        );
+    }
+}
+
+/// Show how syncing changes `available_monitors`
+fn print_sync_diff(old: &[String], new: &[String], cfg: &config::Config) {
+    for line in monitor::sync_diff(old, new, cfg.display.case_insensitive_match) {
+        match line.chars().next() {
+            Some('+') => say!("  {}", line.green()),
+            Some('-') => say!("  {}", line.red()),
+            _ => say!("  {}", line.dimmed()),
+        }
+    }
+}
+
+/// What a launch of `monitors` would generate, for comparing against the
+/// cache. `launch`, `status` and `check --apply` all decide from this.
+///
+/// Fills in what is only known at launch time first (the
+/// `[hooks] assign_command` output, the monitors standing in portrait and
+/// their geometry), so `cfg` generates what was hashed.
+fn generation_inputs(
+    cfg: &mut config::Config,
+    template_content: &str,
+    monitors: &[String],
+    wm: &window_manager::WindowManager,
+    detection: &monitor::Detection,
+    cache_entry: Option<&cache::CacheEntry>,
+    opts: &LaunchOptions,
+) -> Result<cache::GenerationInputs> {
+    if let Some(command) = &cfg.hooks.assign_command {
+        cfg.display.hook_assign = templates::run_assign_hook(command, monitors)?;
+    }
+    let info = detection.info(wm)?;
+    if cfg.display.vertical_template.is_some() {
+        cfg.display.portrait = monitor::portrait_monitors(&info);
+    }
+    cfg.display.geometry = info
+        .into_iter()
+        .filter(|m| monitors.contains(&m.name))
+        .collect();
+
+    let drifted_files = match cache_entry {
+        Some(entry) if !opts.no_regen_on_drift => cache::drifted_files(entry),
+        _ => Vec::new(),
+    };
+
+    Ok(cache::GenerationInputs {
+        template_hash: cache::generation_hash(cfg, template_content),
+        style_hash: cache::stylesheet_hash(cfg),
+        bars_hash: cache::bars_hash(&templates::launch_plan(cfg, monitors, wm)),
+        monitors: monitors.to_vec(),
+        preferred_monitor: cfg.display.preferred_monitor.clone(),
+        generated_files_exist: cache::check_generated_files_exist(cfg, monitors, wm),
+        mismatched_outputs: cache::mismatched_outputs(cfg, monitors, wm),
+        drifted_files,
+        force: opts.force_regenerate,
+        assignments: templates::determine_config_assignments(cfg, monitors)
+            .into_iter()
+            .map(|(monitor, template)| (monitor, template.name().to_string()))
+            .collect(),
+    })
+}
+
+/// Apply a just-synchronized config: reload when the generated configs are
+/// still valid (e.g. only the stored order changed), relaunch otherwise.
+fn apply_synced_config(
+    cfg: &mut config::Config,
+    wm: &window_manager::WindowManager,
+    connected: &[String],
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    let template_content = templates::read_template(&templates::get_templates_path(
+        cfg,
+        wm,
+        opts.templates_dir.as_deref(),
+    ))?;
+    let monitors = monitor::select_monitors(cfg, connected, wm)?.monitors;
+    let cache_entry = cache_store.load()?;
+    let inputs = generation_inputs(
+        cfg,
+        &template_content,
+        &monitors,
+        wm,
+        detection,
+        cache_entry.as_ref(),
+        opts,
+    )?;
+    let strategy = cache::choose_apply_strategy(cache_entry.as_ref(), &inputs);
+
+    if strategy != cache::ApplyStrategy::RegenerateAndRelaunch && monitor::is_waybar_running() {
+        println!(
+            "{}",
+            "Generated configs are unchanged, reloading waybar ..".yellow()
+        );
+        templates::write_monitor_styles(cfg, &monitors, wm)?;
+        monitor::reload_waybar()?;
+
+        if let Some(mut entry) = cache_entry {
+            entry.monitors = monitors;
+            entry.style_hash = inputs.style_hash;
+            cache_store.save(&entry)?;
+        }
+
+        println!("{} Waybar reloaded\n", "✓".green());
+        Ok(())
+    } else {
+        launch_waybar(opts, detection, cache_store)?;
+        Ok(())
+    }
+}
+
+fn run_daemon(
+    interval: u64,
+    debounce_ms: u64,
+    base: &LaunchOptions,
+    mut supervisor: Option<daemon::Supervisor>,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    let cfg = config::load_config(&base.config_path, !base.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let (tx, rx) = mpsc::channel();
+    daemon::spawn_signal_watcher(tx.clone())?;
+    let source = spawn_monitor_watcher(wm, interval, tx.clone());
+    let interval = Duration::from_secs(interval);
+
+    if supervisor.is_some() && cfg.display.combined_config {
+        println!(
+            "{}",
+            "⚠ --supervise restarts single bars and is ignored with combined_config".yellow()
+        );
+        supervisor = None;
+    }
+    if supervisor.is_some() {
+        daemon::spawn_ticker(interval, daemon::Event::SuperviseTick, tx.clone());
+        println!(
+            "{} Supervising bars every {}s",
+            "✓".green(),
+            interval.as_secs()
+        );
+    }
+
+    let config_path = base.config_path.clone();
+    daemon::spawn_poller(
+        interval,
+        daemon::Event::ConfigChanged,
+        tx.clone(),
+        move || daemon::modified_time(&config_path),
+    );
+
+    let socket_path = daemon::get_socket_path()?;
+    daemon::spawn_control_socket(&socket_path, tx)?;
+
+    println!(
+        "{} Watching monitors ({}) and config, control socket: {}",
+        "✓".green(),
+        source,
+        socket_path.display()
+    );
+
+    let opts = LaunchOptions {
+        force_update: true,
+        ..base.clone()
+    };
+
+    // Config as of the last apply, so our own sync writes don't retrigger
+    let mut last_applied = None;
+
+    daemon::run_reconcile_loop(&rx, Duration::from_millis(debounce_ms), |reconcile| {
+        if let Some(supervisor) = supervisor.as_mut() {
+            if reconcile.only_supervise_tick() {
+                return supervise_bars(supervisor, &opts, cache_store);
+            }
+            // Every bar gets a fresh start from a full reconcile
+            supervisor.reset();
+        }
+
+        let current = fs::read_to_string(&base.config_path).ok();
+        let config_only = reconcile.config_changed
+            && !reconcile.monitors_changed
+            && !reconcile.reload_requested
+            && reconcile.theme.is_none();
+        if config_only && current == last_applied {
+            return Ok(());
+        }
+
+        // Fresh detection on every reconcile
+        let detection = monitor::Detection::default();
+        if let Some(theme) = &reconcile.theme {
+            switch_theme(theme, &opts, &detection, cache_store)?;
+        } else {
+            launch_waybar(&opts, &detection, cache_store)?;
+        }
+        last_applied = fs::read_to_string(&base.config_path).ok();
+        Ok(())
+    });
+
+    match fs::remove_file(&socket_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).context("Error removing the control socket")?
+        }
+        _ => {}
+    }
+    println!("{} Daemon stopped", "✓".green());
+    Ok(())
+}
+
+/// Restart the bars of launched monitors that are no longer running, each
+/// with its own restart count and backoff, and give up on (and notify
+/// about) those exceeding the retry cap
+fn supervise_bars(
+    supervisor: &mut daemon::Supervisor,
+    opts: &LaunchOptions,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    supervisor.reap();
+    let Some(entry) = cache_store.load()? else {
+        return Ok(());
+    };
+    let cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let now = std::time::Instant::now();
+
+    let instances = status::running_instances()?;
+    let missing: Vec<String> = status::verify_bars(
+        &entry.monitors,
+        &instances,
+        cfg.display.case_insensitive_match,
+    )
+    .into_iter()
+    .filter_map(|discrepancy| match discrepancy {
+        status::Discrepancy::Missing(monitor) => Some(monitor),
+        _ => None,
+    })
+    .collect();
+
+    for monitor in entry.monitors.iter().filter(|m| !missing.contains(m)) {
+        supervisor.on_alive(monitor, now);
+    }
+
+    // Assign templates as they were when the configs were generated
+    let cfg = config::Config {
+        display: config::Display {
+            preferred_monitor: entry.preferred_monitor.clone(),
+            ..cfg.display.clone()
+        },
+        ..cfg
+    };
+    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+
+    for monitor in &missing {
+        match supervisor.on_dead(monitor, now) {
+            daemon::Decision::Restart => {
+                println!(
+                    "[{}] Waybar on {} exited, restarting it (attempt {})",
+                    timestamp,
+                    monitor.cyan(),
+                    supervisor.restarts(monitor)
+                );
+                let child = templates::relaunch_instance(&cfg, &entry.monitors, monitor, &wm)?;
+                supervisor.adopt(child);
+            }
+            daemon::Decision::GiveUp => {
+                let restarts = supervisor.restarts(monitor);
+                println!(
+                    "[{}] {}",
+                    timestamp,
+                    format!(
+                        "✗ Waybar on {} failed after {} restarts, leaving it down",
+                        monitor, restarts
+                    )
+                    .red()
+                );
+                let notifier = notify::Notifier::new(
+                    !opts.no_notify && cfg.behavior.notifications,
+                    &cfg.notifications,
+                )?;
+                notifier.instance_failed(monitor, restarts)?;
+            }
+            daemon::Decision::Wait | daemon::Decision::Failed => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Send `MonitorsChanged` on hotplugs: Hyprland announces them on its
+/// event socket, others are polled every `interval` seconds. Returns a
+/// description of the source.
+fn spawn_monitor_watcher(
+    wm: window_manager::WindowManager,
+    interval: u64,
+    tx: mpsc::Sender<daemon::Event>,
+) -> String {
+    let event_socket = events::hyprland_event_socket().filter(|path| {
+        wm == window_manager::WindowManager::Hyprland && events::socket_available(path)
+    });
+    if let Some(path) = event_socket {
+        events::spawn_hyprland_watcher(path, tx);
+        return "Hyprland events".to_string();
+    }
+
+    daemon::spawn_poller(
+        Duration::from_secs(interval),
+        daemon::Event::MonitorsChanged,
+        tx,
+        move || {
+            monitor::get_connected_monitor_names(&wm).ok().map(|mut m| {
+                m.sort();
+                m
+            })
+        },
+    );
+    format!("every {}s", interval)
+}
+
+/// `monitors --watch`: one JSON line for the current monitors, then one per
+/// added or removed monitor. Read-only; nothing is generated or launched.
+fn stream_monitors(interval: u64, opts: &LaunchOptions) -> Result<()> {
+    let cfg = config::load_config_or_default(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let (tx, rx) = mpsc::channel();
+    spawn_monitor_watcher(wm, interval, tx);
+
+    let emit = |event: &monitor::MonitorEvent| -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{}", serde_json::to_string(event)?)?;
+        stdout.flush()?;
+        Ok(())
+    };
+
+    let mut last_seen =
+        monitor::get_connected_monitor_names(&wm).context("Error detecting monitors")?;
+    emit(&monitor::MonitorEvent::snapshot(&last_seen))?;
+
+    while rx.recv().is_ok() {
+        // Transient failures (e.g. the compositor restarting) are skipped
+        let Ok(current) = monitor::get_connected_monitor_names(&wm) else {
+            continue;
+        };
+        for event in monitor::monitor_events(&last_seen, &current) {
+            emit(&event)?;
+        }
+        last_seen = current;
+    }
+
+    Ok(())
+}
+
+/// Relaunch the bars when the set of connected monitors changes, following
+/// Hyprland's event socket or polling other compositors. Failed polls are
+/// skipped, so a compositor restart doesn't end the watch.
+fn run_watch(
+    interval: u64,
+    debounce_ms: u64,
+    base: &LaunchOptions,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    let cfg = config::load_config(&base.config_path, !base.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let case_insensitive = cfg.display.case_insensitive_match;
+    let (tx, rx) = mpsc::channel();
+    daemon::spawn_signal_watcher(tx.clone())?;
+    let source = spawn_monitor_watcher(wm, interval, tx);
+
+    let mut last_seen =
+        monitor::get_connected_monitor_names(&wm).context("Error detecting monitors")?;
+    println!(
+        "{} Watching monitors ({}): {}",
+        "✓".green(),
+        source,
+        last_seen.join(", ")
+    );
+
+    let opts = LaunchOptions {
+        force_update: true,
+        ..base.clone()
+    };
+
+    daemon::run_reconcile_loop(&rx, Duration::from_millis(debounce_ms), |_| {
+        let current = monitor::get_connected_monitor_names(&wm)?;
+        // The change may have been reverted within the debounce window
+        if monitor::lists_match(&last_seen, &current, case_insensitive) {
+            return Ok(());
+        }
+
+        println!(
+            "[{}] Monitors changed: {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            daemon::describe_transition(&last_seen, &current)
+        );
+        last_seen = current;
+
+        launch_waybar(&opts, &monitor::Detection::default(), cache_store)?;
+        Ok(())
+    });
+
+    println!("{} Stopped watching monitors", "✓".green());
+    Ok(())
+}
+
+/// Persist `theme` as the active theme and apply it. Bars are only reloaded
+/// when the stylesheet path is unchanged, since waybar cannot switch to a
+/// different stylesheet without a restart.
+fn switch_theme(
+    theme: &str,
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    let mut cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+
+    if theme != "default" && !templates::get_theme_dir(theme).is_dir() {
+        return Err(anyhow::anyhow!(
+            "Theme '{}' was not found in: {}",
+            theme,
+            templates::get_theme_dir(theme).display()
+        ));
+    }
+
+    let old_style = templates::get_style_path(&cfg);
+    cfg.display.theme = (theme != "default").then(|| theme.to_string());
+    config::save_config(&opts.config_path, &cfg)?;
+    println!("{} Active theme: {}", "✓".green(), theme.cyan());
+
+    let opts = LaunchOptions {
+        reload_only: templates::get_style_path(&cfg) == old_style,
+        ..opts.clone()
+    };
+    launch_waybar(&opts, detection, cache_store)?;
+    Ok(())
+}
+
+fn show_monitors(
+    output: OutputFormat,
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+) -> Result<()> {
+    let cfg = config::load_config_or_default(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let info = detection.info(&wm)?;
+    // The monitors a launch would consider, in both formats
+    let (usable, skipped) = monitor::usable_monitors(&cfg, &info, &wm, false);
+    if output == OutputFormat::Json {
+        let usable: Vec<monitor::MonitorInfo> = info
+            .into_iter()
+            .filter(|m| usable.contains(&m.name))
+            .collect();
+        println!("{}", monitor::monitors_json(&usable)?);
+        return Ok(());
+    }
+
+    println!("{}", "Monitors detected:".green().bold());
+    for (i, mon) in usable.iter().enumerate() {
+        println!("  {}. {}", i + 1, mon.cyan());
+    }
+    print_skipped(&skipped);
+
+    Ok(())
+}
 
-    // Check for differences and offer to synchronize
-    let needs_update = if cfg.display.mode == "single" {
-        // In single mode, just verify that your preferred monitor is connected.
-        !connected.contains(&cfg.display.preferred_monitor)
-    } else {
-        // In multiple mode, verify that the lists match.
-        !monitor::lists_match(&cfg.display.available_monitors, &connected)
+fn show_status(
+    output: OutputFormat,
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    let mut cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let connected = detection.monitors(&wm)?;
+    if connected.is_empty() {
+        return Err(Failure::NoMonitors.into());
+    }
+
+    // The monitors a launch would use right now; none when strict_single
+    // keeps it from starting anything
+    let monitors = monitor::select_monitors(&cfg, &connected, &wm)
+        .map(|selection| selection.monitors)
+        .unwrap_or_default();
+
+    let template_content = templates::read_template(&templates::get_templates_path(
+        &cfg,
+        &wm,
+        opts.templates_dir.as_deref(),
+    ))
+    .unwrap_or_default();
+    let cache_entry = cache_store.load()?;
+    let inputs = generation_inputs(
+        &mut cfg,
+        &template_content,
+        &monitors,
+        &wm,
+        detection,
+        cache_entry.as_ref(),
+        opts,
+    )?;
+    let reason = cache::regeneration_reason(cache_entry.as_ref(), &inputs);
+
+    let status = status::Status {
+        wm: wm.as_str().to_string(),
+        instances: status::running_instances()?,
+        cache_timestamp: cache_entry.map(|entry| entry.timestamp),
+        regenerate_reason: reason.map(|reason| reason.to_string()),
     };
 
-    if needs_update {
-        println!("{}", "─────────────────────────────────".yellow());
-        println!("{}", "║  ⚠  Differences were detected  ".yellow());
-        println!();
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
 
-        if cfg.display.mode == "single" {
-            println!("{}", "⚠ In 'single' mode, you must have the 'preferred_monitor' option configured to disable this alert. The first monitor detected will be used.".yellow());
+    println!("Window manager: {}", status.wm.cyan());
+    if status.instances.is_empty() {
+        println!("{}", "No waybar instances running".yellow());
+    } else {
+        println!("{}", "Running waybar instances:".green().bold());
+        for instance in &status.instances {
             println!(
-                "{}",
-                "  Run 'omynix-waybar-manager config' to reconfigure".cyan()
+                "  {} {} {}",
+                instance.pid.to_string().cyan(),
+                instance.monitors.join(", "),
+                instance.config.as_deref().unwrap_or("(default config)")
             );
-            println!();
-        } else if ask_update_config_sync()? {
-            cfg.display.available_monitors = connected.clone();
-            config::save_config(&cfg)?;
-            println!("{} Configuration successfully synchronized\n", "✓".green());
-        } else {
-            println!("{} Outdated configuration\n", "⚠".yellow());
         }
-    } else {
-        println!("{} The configuration is synchronized\n", "✓".green());
+    }
+    match status.cache_timestamp {
+        Some(timestamp) => println!("Cache generated: {}", cache::format_timestamp(timestamp)),
+        None => println!("Cache generated: {}", "never".yellow()),
+    }
+    match &status.regenerate_reason {
+        Some(reason) => println!("Next launch: {} ({})", "regenerate".yellow(), reason),
+        None => println!("Next launch: {}", "use cache".green()),
     }
 
     Ok(())
 }
 
-fn show_monitors() -> Result<()> {
-    let wm = window_manager::detect_window_manager()?;
-    let connected = monitor::get_connected_monitors(&wm)?;
+/// Relaunch the bars from the cached generated configs, falling back to a
+/// full launch when there is nothing usable in the cache
+fn restart_waybar(
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+) -> Result<()> {
+    let cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+
+    let cached = cache_store
+        .load()?
+        .filter(|entry| cache::check_generated_files_exist(&cfg, &entry.monitors, &wm));
+    let Some(entry) = cached else {
+        println!(
+            "{}",
+            "⚠ No cached configs were found, running a full launch".yellow()
+        );
+        launch_waybar(opts, detection, cache_store)?;
+        return Ok(());
+    };
 
-    println!("{}", "Monitors detected:".green().bold());
-    for (i, mon) in connected.iter().enumerate() {
-        println!("  {}. {}", i + 1, mon.cyan());
-    }
+    let monitors = launch_cached(cfg, &wm, entry, cache_store)?;
+    println!(
+        "{} Restarted waybar on: {}",
+        "✓".green(),
+        monitors.join(", ").cyan()
+    );
 
     Ok(())
 }
 
-fn launch_waybar(force_update: bool, verbose: bool) -> Result<()> {
-    println!("{}", "─────────────────────────────────".green());
-    println!("{}", "- Starting Waybar setup ..    ".green());
-    println!();
+/// Second half of `launch --prepare`: replace the running bars with the
+/// prepared configs, failing when there are none
+fn commit_prepared(opts: &LaunchOptions, cache_store: &dyn CacheStore) -> Result<()> {
+    let cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let entry = cache::prepared_generation(cache_store, |entry| {
+        let prepared = cache::prepared_config(cfg.clone(), entry);
+        cache::check_generated_files_exist(&prepared, &entry.monitors, &wm)
+    })?;
+
+    let monitors = launch_cached(cfg, &wm, entry, cache_store)?;
+    println!(
+        "{} Launched the prepared bars on: {}",
+        "✓".green(),
+        monitors.join(", ").cyan()
+    );
+
+    Ok(())
+}
+
+/// Stop the running bars and start the ones in the generated configs
+/// `entry` describes; returns the monitors they were started on
+fn launch_cached(
+    cfg: config::Config,
+    wm: &window_manager::WindowManager,
+    mut entry: cache::CacheEntry,
+    cache_store: &dyn CacheStore,
+) -> Result<Vec<String>> {
+    if monitor::is_waybar_running() {
+        println!("{}", "Closing existing waybar ..".yellow());
+        monitor::kill_waybar()?;
+        wait_for_waybar_exit();
+    }
+
+    // Assign templates as they were when the configs were generated
+    let cfg = cache::prepared_config(cfg, &entry);
+    templates::launch_waybar_instances(&cfg, &entry.monitors, wm)?;
+
+    entry.last_launch = cache::get_current_timestamp();
+    cache_store.save(&entry)?;
+
+    Ok(entry.monitors)
+}
+
+fn launch_waybar(
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+) -> Result<report::LaunchReport> {
+    let mut health_file = false;
+    let result = run_launch(opts, detection, cache_store, &mut health_file);
+
+    if health_file && !opts.dry_run {
+        let running = monitor::waybar_pids().map(|p| p.len()).unwrap_or(0);
+        let health = health::Health::from_result(&result, cache::get_current_timestamp(), running);
+        if let Err(e) = health::get_health_path().and_then(|p| health::write_health(&p, &health)) {
+            eprintln!("{} {:#}", "⚠ Could not write the health file:".yellow(), e);
+        }
+    }
+
+    result
+}
+
+/// `health_file` is set to `[behavior] health_file` once the config is
+/// loaded, so the caller can record the outcome even when the launch fails
+fn run_launch(
+    opts: &LaunchOptions,
+    detection: &monitor::Detection,
+    cache_store: &dyn CacheStore,
+    health_file: &mut bool,
+) -> Result<report::LaunchReport> {
+    say!("{}", "─────────────────────────────────".green());
+    say!("{}", "- Starting Waybar setup ..    ".green());
+    say!();
+
+    // Load configuration
+    let mut cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    *health_file = cfg.behavior.health_file;
+
+    // Configs from older versions were written as "<name>. json"
+    let renamed = if opts.dry_run {
+        0
+    } else {
+        templates::migrate_legacy_file_names(&templates::get_generated_dir())?
+    };
+    if renamed > 0 {
+        log::info!(
+            "Renamed {} generated config(s) from the old '. json' naming",
+            renamed
+        );
+    }
 
     // Detect window manager
-    let wm = window_manager::detect_window_manager()?;
-    println!(
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    say!(
         "{} Window manager detected: {}",
         "✓".green(),
         format!("{:?}", wm).cyan()
     );
+    let mut report = report::LaunchReport::new(wm);
 
     // Get connected monitors
-    let connected = monitor::get_connected_monitors(&wm)?;
-    println!(
+    let info = detection.info(&wm)?;
+    if info.is_empty() {
+        return Err(Failure::NoMonitors.into());
+    }
+    let (connected, skipped) = monitor::usable_monitors(&cfg, &info, &wm, opts.include_disabled);
+    print_skipped(&skipped);
+    say!(
         "{} Monitors detected: {}",
         "✓".green(),
         connected.len().to_string().cyan()
     );
-    println!();
+    say!();
 
-    // Load configuration
-    let mut cfg = config::load_config()?;
+    if let Some(kept) =
+        report::below_min_monitors(wm, &cfg.behavior, &connected, monitor::is_waybar_running)
+    {
+        println!(
+            "{}",
+            format!(
+                "⚠ Only {} monitor(s) detected, fewer than min_monitors = {}; keeping the running bars",
+                connected.len(),
+                cfg.behavior.min_monitors
+            )
+            .yellow()
+        );
+        return Ok(kept);
+    }
+
+    // One-shot override: saved configs keep the configured preferred monitor
+    let configured_preferred = cfg.display.preferred_monitor.clone();
+    if opts.preferred_from_focus {
+        let focused = monitor::get_focused_monitor(&wm).unwrap_or(None);
+        match monitor::focused_as_preferred(
+            focused.as_deref(),
+            &connected,
+            cfg.display.case_insensitive_match,
+        ) {
+            Some(focused) => {
+                say!(
+                    "{} Using the focused monitor as preferred: {}",
+                    "✓".green(),
+                    focused.cyan()
+                );
+                cfg.display.preferred_monitor = focused;
+            }
+            None => {
+                println!(
+                    "{}",
+                    "⚠ The focused monitor could not be detected, keeping the configured one"
+                        .yellow()
+                );
+                report
+                    .warnings
+                    .push("focused monitor not detected".to_string());
+            }
+        }
+    }
 
     for mon in &cfg.display.available_monitors {
-        println!("--CONFIGURATION  {} {}", "-".magenta(), mon);
+        say!("--CONFIGURATION  {} {}", "-".magenta(), mon);
     }
 
     // Show detailed information
@@ -220,124 +1618,277 @@ fn launch_waybar(force_update: bool, verbose: bool) -> Result<()> {
     // Check if an update is needed
     let mut needs_update = if cfg.display.mode == "single" {
         // In single mode, just verify that your preferred monitor is connected.
-        !connected.contains(&cfg.display.preferred_monitor)
+        monitor::find_monitor(
+            &connected,
+            &cfg.display.preferred_monitor,
+            cfg.display.case_insensitive_match,
+        )
+        .is_none()
     } else {
         // In multiple mode, verify that the lists match.
-        !monitor::lists_match(&cfg.display.available_monitors, &connected)
+        !monitor::lists_match(
+            &cfg.display.available_monitors,
+            &connected,
+            cfg.display.case_insensitive_match,
+        )
     };
 
     if needs_update {
         if cfg.display.mode == "single" {
             println!("{}", "⚠ The configured monitor is not connected".yellow());
-            println!(
+            say!(
                 "{}",
                 "  Run 'omynix-waybar-manager config' to reconfigure".cyan()
             );
-            println!();
-        } else if force_update || ask_update_config()? {
-            cfg.display.available_monitors = connected.clone();
-            config::save_config(&cfg)?;
+            say!();
+        } else if opts.dry_run {
+            say!("{}", "Would update available_monitors:".yellow());
+            print_sync_diff(&cfg.display.available_monitors, &connected, &cfg);
+            say!();
+        } else if opts.force_update || ask_update_config(cfg.behavior.prompt_timeout_secs)? {
+            print_sync_diff(&cfg.display.available_monitors, &connected, &cfg);
+            config::sync_available_monitors(
+                &opts.config_path,
+                &mut cfg,
+                &connected,
+                &configured_preferred,
+            )?;
             needs_update = false;
-            println!("{} Configuration updated successfully\n", "✓".green());
+            say!("{} Configuration updated successfully\n", "✓".green());
         } else {
             println!("{} Outdated configuration\n", "⚠".yellow());
         }
-    } else if verbose {
-        println!("{} The settings are now updated\n", "✓".green());
+    } else {
+        log::debug!("The configured monitors match the connected ones");
     }
 
-    let monitors_to_use = if cfg.display.mode == "single" {
-        // Single mode: Only use the preferred monitor if it is connected.
-        if connected.contains(&cfg.display.preferred_monitor) {
-            vec![cfg.display.preferred_monitor.clone()]
-        } else {
-            println!(
-                "{}",
-                "⚠ Preferred monitor not available, using the first one detected".yellow()
-            );
-            vec![connected[0].clone()]
-        }
-    } else {
-        // Multiple mode: Use all connected devices
-        connected.clone()
-    };
+    // Single mode: only the preferred monitor, or a fallback when it is
+    // not connected. Multiple mode: every connected monitor.
+    let selection = monitor::select_monitors(&cfg, &connected, &wm).inspect_err(|_| {
+        println!(
+            "{}",
+            "⚠ strict_single is set: not launching on another monitor".yellow()
+        );
+        say!(
+            "{}",
+            "  Connect the preferred monitor or run 'omynix-waybar-manager config' to pick another"
+                .cyan()
+        );
+    })?;
+    if let Some(source) = selection.fallback {
+        println!(
+            "{}",
+            format!(
+                "⚠ Preferred monitor not available, using {}: {}",
+                source, selection.monitors[0]
+            )
+            .yellow()
+        );
+        report
+            .warnings
+            .push(format!("preferred monitor not available, using {}", source));
+    }
+    let monitors_to_use = selection.monitors;
 
     // Verify cache and decide whether to regenerate
-    let template_path = templates::get_templates_path(&wm);
-    let template_content =
-        fs::read_to_string(&template_path).context("Error reading template file")?;
-    let template_hash = cache::calculate_template_hash(&template_content);
-
-    let cache_entry = cache::load_cache()?;
-    let generated_files_exist = cache::check_generated_files_exist(&monitors_to_use, &wm);
-
-    let should_regenerate = cache::should_regenerate(
-        cache_entry.as_ref(),
-        &template_hash,
+    let template_file = templates::resolve_templates(
+        &cfg,
+        &wm,
+        opts.templates_dir.as_deref(),
+        opts.allow_empty_templates,
+    )?;
+    if !opts.assign.is_empty() {
+        cfg.display.assign = opts.assign.iter().cloned().collect();
+        templates::validate_assignments(&cfg, &connected, &template_file.types())?;
+    }
+    let cache_entry = cache_store.load()?;
+    let inputs = generation_inputs(
+        &mut cfg,
+        &template_file.content,
         &monitors_to_use,
-        &cfg.display.preferred_monitor,
-        generated_files_exist,
-    );
-
-    if should_regenerate {
-        println!("{}", "─────────────────────────────────".cyan());
-        println!("{}", "GENERATING CONFIGURATIONS        ".cyan());
-        println!();
-
-        templates::generate_configs(&cfg, &connected, &wm, verbose)?;
+        &wm,
+        detection,
+        cache_entry.as_ref(),
+        opts,
+    )?;
+    templates::validate_hook_assignments(&cfg, &monitors_to_use, &template_file.types())?;
+    let cache::Decision {
+        reason: regeneration_reason,
+        trace,
+    } = cache::explain_regeneration(cache_entry.as_ref(), &inputs);
+    if opts.explain_cache {
+        say!("{}", "Cache decision:".yellow().bold());
+        for line in &trace {
+            say!("  {}", line);
+        }
+        say!();
+    }
+    let strategy = cache::ApplyStrategy::from_reason(regeneration_reason);
+    let should_regenerate = strategy == cache::ApplyStrategy::RegenerateAndRelaunch;
+    // Bars to start, stop or point at another config or stylesheet (new
+    // monitors, changed assignments) need a relaunch, which a reload
+    // cannot do
+    let bars_changed = cache_entry
+        .as_ref()
+        .is_none_or(|entry| entry.bars_hash != inputs.bars_hash);
+
+    report.monitors = monitors_to_use.clone();
+    report.assignments = templates::determine_config_assignments(&cfg, &monitors_to_use);
+    report.regenerated = should_regenerate;
+    report.reason = regeneration_reason.map(|reason| reason.to_string());
+
+    if opts.dry_run {
+        print_launch_plan(
+            &cfg,
+            &monitors_to_use,
+            &wm,
+            &template_file.path,
+            strategy,
+            regeneration_reason,
+        );
+        if !opts.explain_cache {
+            for line in &trace {
+                log::debug!("{}", line);
+            }
+        }
+        report.regenerated = false;
+        return Ok(report);
+    }
 
-        // Save cache after generating
-        let new_cache = cache::CacheEntry {
-            template_hash,
-            monitors: monitors_to_use.clone(),
-            preferred_monitor: cfg.display.preferred_monitor.clone(),
-            timestamp: cache::get_current_timestamp(),
-        };
-        cache::save_cache(&new_cache)?;
+    // Avoid relaunching twice when invoked from both autostart and a shell rc
+    if opts.since_boot
+        && strategy == cache::ApplyStrategy::NoOp
+        && !needs_update
+        && cache::launched_recently(
+            cache_entry.as_ref(),
+            cache::get_current_timestamp(),
+            cfg.behavior.relaunch_guard_secs,
+        )
+        && monitor::is_waybar_running()
+    {
+        say!(
+            "{} Waybar was launched less than {}s ago and nothing changed, skipping.",
+            "✓".green(),
+            cfg.behavior.relaunch_guard_secs
+        );
+        report.reason = Some("launched recently".to_string());
+        return Ok(report);
+    }
 
-        if verbose {
-            println!("{} Cache updated", "✓".green());
-        }
+    if should_regenerate {
+        say!("{}", "─────────────────────────────────".cyan());
+        say!("{}", "GENERATING CONFIGURATIONS        ".cyan());
+        say!();
+
+        templates::generate_configs(
+            &cfg,
+            &template_file,
+            &monitors_to_use,
+            &wm,
+            opts.strict,
+            &cache::cached_files(cache_entry.as_ref()),
+        )?;
+
+        log::debug!("Configs generated");
     } else {
-        println!("{}", "─────────────────────────────────".cyan());
-        println!("{}", "- USING CACHE CONFIGURATIONS ..  ".cyan());
-        println!();
-        println!(
+        say!("{}", "─────────────────────────────────".cyan());
+        say!("{}", "- USING CACHE CONFIGURATIONS ..  ".cyan());
+        say!();
+        say!(
             "{} The settings are now up to date, using cache.",
             "✓".green()
         );
 
-        if let Some(cache) = cache_entry {
-            if verbose {
-                use chrono::{DateTime, TimeZone, Utc};
-                let dt: DateTime<Utc> = Utc.timestamp_opt(cache.timestamp as i64, 0).unwrap();
+        if let Some(cache) = &cache_entry {
+            log::debug!(
+                "Latest generation: {}",
+                cache::format_timestamp(cache.timestamp)
+            );
+        }
+        // A style-only change is applied by reloading the bars below
+        if strategy == cache::ApplyStrategy::ReloadOnly {
+            say!("{}", "Only the stylesheet changed ..".yellow());
+        }
+        say!();
+    }
+
+    cache::record_apply(
+        cache_store,
+        cache_entry.as_ref(),
+        &inputs,
+        strategy,
+        &cache::generated_files(&cfg, &monitors_to_use, &wm),
+        cfg.launch.file_mode()?,
+        !opts.prepare_only,
+    )?;
+    log::debug!("Cache updated");
+
+    if opts.check_templates_against_waybar {
+        say!("{}", "Validating generated configs with waybar ..".yellow());
+        let check = templates::check_against_waybar(&cfg, &monitors_to_use, &wm)?;
+        for mon in &check.inconclusive {
+            println!(
+                "  {}",
+                format!(
+                    "⚠ waybar exited before the config for {} could be checked",
+                    mon
+                )
+                .yellow()
+            );
+        }
+        if !check.rejected.is_empty() {
+            for mon in &check.rejected {
                 println!(
-                    "  Latest generation: {}",
-                    dt.format("%Y-%m-%d %H:%M:%S UTC")
+                    "  {} waybar rejected the config for {}",
+                    "✗".red(),
+                    mon.cyan()
                 );
             }
+            return Err(anyhow::anyhow!(
+                "waybar rejected {} generated config(s), keeping the running bars",
+                check.rejected.len()
+            ));
         }
-        println!();
+        if check.inconclusive.is_empty() {
+            say!("{} waybar accepted every generated config\n", "✓".green());
+        }
+    }
+
+    if opts.prepare_only {
+        say!(
+            "{} Configs and cache are up to date; running bars were left alone",
+            "✓".green()
+        );
+        return Ok(report);
+    }
+
+    let reload =
+        (opts.reload_only && !bars_changed) || strategy == cache::ApplyStrategy::ReloadOnly;
+    if reload && monitor::is_waybar_running() {
+        templates::write_monitor_styles(&cfg, &monitors_to_use, &wm)?;
+        monitor::reload_waybar()?;
+        say!("{} Waybar reloaded", "✓".green());
+        return Ok(report);
     }
 
     // Close existing waybar
     // monitor::kill_waybar()?;
     if monitor::is_waybar_running() {
-        println!("{}", "Closing existing waybar ..".yellow());
+        say!("{}", "Closing existing waybar ..".yellow());
         monitor::kill_waybar()?;
-        std::thread::sleep(std::time::Duration::from_millis(500));
+        wait_for_waybar_exit();
     } else {
-        println!("{}", "continue because Waybar is not present ..".yellow());
+        say!("{}", "continue because Waybar is not present ..".yellow());
     }
 
     // Launch waybar
-    println!();
-    println!("{}", "─────────────────────────────────".cyan());
-    println!("{}", "- INITIALIZING WAYBAR ..         ".cyan());
-    println!();
+    say!();
+    say!("{}", "─────────────────────────────────".cyan());
+    say!("{}", "- INITIALIZING WAYBAR ..         ".cyan());
+    say!();
 
     if cfg.display.mode == "single" {
-        println!(
+        say!(
             "{}",
             format!(
                 "Mode: {} (only in {})",
@@ -347,7 +1898,7 @@ fn launch_waybar(force_update: bool, verbose: bool) -> Result<()> {
             .dimmed()
         );
     } else {
-        println!(
+        say!(
             "{}",
             format!(
                 "Mode: {} ({} monitors)",
@@ -357,67 +1908,268 @@ fn launch_waybar(force_update: bool, verbose: bool) -> Result<()> {
             .dimmed()
         );
     }
-    println!();
+    say!();
 
-    templates::launch_waybar_instances(&cfg, &monitors_to_use, &wm, verbose)?;
+    report.launched_pids = templates::launch_waybar_instances(&cfg, &monitors_to_use, &wm)?;
 
-    println!();
-    println!("{}", "─────────────────────────────────".cyan());
-    println!("{}", "✓ Waybar started successfully    ".green());
+    // Record the launch time for the --since-boot guard
+    if let Some(mut entry) = cache_store.load()? {
+        entry.last_launch = cache::get_current_timestamp();
+        cache_store.save(&entry)?;
+    }
+
+    say!();
+    say!("{}", "─────────────────────────────────".cyan());
+    say!("{}", "✓ Waybar started successfully    ".green());
+
+    if opts.verify_after {
+        verify_bars(&cfg, &monitors_to_use, opts.auto_correct, &mut report)?;
+    }
 
     if needs_update {
-        send_config_diff_notification()?;
+        report
+            .warnings
+            .push("configuration differs from the connected monitors".to_string());
+        let notifier = notify::Notifier::new(
+            !opts.no_notify && cfg.behavior.notifications,
+            &cfg.notifications,
+        )?;
+        notifier.config_diff()?;
+    }
+
+    Ok(report)
+}
+
+/// Warn about the connected monitors `usable_monitors` left out
+fn print_skipped(skipped: &monitor::Skipped) {
+    if !skipped.disabled.is_empty() {
+        println!(
+            "{} Skipping disabled monitors: {}",
+            "⚠".yellow(),
+            skipped.disabled.join(", ")
+        );
+    }
+    if !skipped.zero_resolution.is_empty() {
+        println!(
+            "{} Skipping monitors with a 0x0 resolution (standby): {}",
+            "⚠".yellow(),
+            skipped.zero_resolution.join(", ")
+        );
+    }
+    for group in &skipped.mirrors {
+        println!(
+            "{} {} mirrors {}; launching a single bar on {}",
+            "⚠".yellow(),
+            group.mirrors.join(", "),
+            group.kept,
+            group.kept
+        );
+    }
+}
+
+/// What `launch` would do, for `--dry-run`
+fn print_launch_plan(
+    cfg: &config::Config,
+    monitors: &[String],
+    wm: &window_manager::WindowManager,
+    template_path: &Path,
+    strategy: cache::ApplyStrategy,
+    reason: Option<cache::Reason>,
+) {
+    say!("{}", "─────────────────────────────────".cyan());
+    say!("{}", "- DRY RUN (nothing is changed) ..".cyan());
+    say!();
+
+    match strategy {
+        cache::ApplyStrategy::NoOp => say!("{} Cache is current", "✓".green()),
+        cache::ApplyStrategy::ReloadOnly => println!(
+            "{} Stylesheet changed: bars would be reloaded",
+            "⚠".yellow()
+        ),
+        cache::ApplyStrategy::RegenerateAndRelaunch => println!(
+            "{} Configs would be regenerated ({})",
+            "⚠".yellow(),
+            reason.map_or("forced", cache::Reason::as_str)
+        ),
+    }
+    say!("  Templates:  {}", template_path.display());
+    say!("  Stylesheet: {}", templates::get_style_path(cfg).display());
+    say!();
+
+    let running = monitor::waybar_pids().map(|p| p.len()).unwrap_or(0);
+    if running > 0 && strategy != cache::ApplyStrategy::ReloadOnly {
+        say!("Would stop {} running waybar instance(s)", running);
+    }
+    say!("Would start:");
+    for bar in templates::launch_plan(cfg, monitors, wm) {
+        say!("  {} {}", "→".cyan(), bar.label.cyan());
+        say!("    {}", bar.command().dimmed());
+    }
+}
+
+/// Give the new bars a moment to start (or crash), then check that every
+/// monitor has exactly one, optionally stopping the extra ones
+fn verify_bars(
+    cfg: &config::Config,
+    expected: &[String],
+    auto_correct: bool,
+    report: &mut report::LaunchReport,
+) -> Result<()> {
+    for dead in process::wait_and_check_alive(&report.launched_pids, Duration::from_secs(1)) {
+        let message = format!(
+            "waybar {} {}",
+            dead.pid,
+            dead.reason.as_deref().unwrap_or("exited")
+        );
+        println!("  {} {}", "✗".red(), message);
+        report.warnings.push(message);
+    }
+
+    let instances = status::running_instances()?;
+    let discrepancies =
+        status::verify_bars(expected, &instances, cfg.display.case_insensitive_match);
+    if discrepancies.is_empty() {
+        say!("{} Every monitor has exactly one bar", "✓".green());
+        return Ok(());
+    }
+
+    for discrepancy in &discrepancies {
+        println!("  {} {}", "✗".red(), discrepancy);
+        report.warnings.push(discrepancy.to_string());
+    }
+
+    if auto_correct {
+        let extra: Vec<u32> = discrepancies
+            .iter()
+            .flat_map(|d| d.extra_pids(&report.launched_pids))
+            .collect();
+        if !extra.is_empty() {
+            let stopped = monitor::terminate(&extra);
+            say!(
+                "{} Stopped {} extra waybar instance(s)",
+                "✓".green(),
+                stopped
+            );
+        }
     }
 
     Ok(())
 }
 
 fn print_monitor_info(cfg: &config::Config, connected: &[String]) {
-    println!("{}", "─────────────────────────────────".cyan());
-    println!("{}", "- CONFIGURED MONITORS (from TOML file):".cyan());
+    say!("{}", "─────────────────────────────────".cyan());
+    say!("{}", "- CONFIGURED MONITORS (from TOML file):".cyan());
 
     if cfg.display.available_monitors.is_empty() {
-        println!("  {}", "(None configured)".yellow());
+        say!("  {}", "(None configured)".yellow());
     } else {
         for mon in &cfg.display.available_monitors {
-            println!("  {} {}", "-".magenta(), mon);
+            say!("  {} {}", "-".magenta(), mon);
         }
     }
-    println!();
+    say!();
 
-    println!("{}", "─────────────────────────────────".cyan());
-    println!("{}", "MONITORS CONNECTED (detected by the script)".cyan());
+    say!("{}", "─────────────────────────────────".cyan());
+    say!("{}", "MONITORS CONNECTED (detected by the script)".cyan());
 
     for mon in connected {
-        println!("  {} {}", "-".green(), mon);
+        say!("  {} {}", "-".green(), mon);
     }
-    println!();
+    say!();
 
-    let matches = monitor::find_matches(&cfg.display.available_monitors, connected);
-    println!("{}", "─────────────────────────────────".cyan());
-    println!("{}", "MATCHES (monitors on both lists) ".cyan());
+    let matches = monitor::find_matches(
+        &cfg.display.available_monitors,
+        connected,
+        cfg.display.case_insensitive_match,
+    );
+    say!("{}", "─────────────────────────────────".cyan());
+    say!("{}", "MATCHES (monitors on both lists) ".cyan());
 
     if matches.is_empty() {
         println!("  {} There are no coincidences.", "⚠".yellow());
     } else {
         for mon in &matches {
-            println!("  {} {}", "✓".green(), mon);
+            say!("  {} {}", "✓".green(), mon);
         }
     }
-    println!();
+    say!();
 
-    println!(
+    say!(
         "{} Preferred monitor (configuration): {}",
         "✓".green(),
         cfg.display.preferred_monitor.cyan()
     );
+    say!();
+}
+
+fn show_modules(json: bool, opts: &LaunchOptions) -> Result<()> {
+    let cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let file = templates::resolve_templates(&cfg, &wm, opts.templates_dir.as_deref(), false)?;
+    let path = &file.path;
+    let summaries = templates::module_summaries(&file.templates);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+        return Ok(());
+    }
+
+    println!("Templates in: {}", path.display().to_string().cyan());
+    for summary in &summaries {
+        println!();
+        println!("{}", summary.template.to_uppercase().green().bold());
+        for (section, modules) in [
+            ("left", &summary.left),
+            ("center", &summary.center),
+            ("right", &summary.right),
+        ] {
+            println!("  {:<7} {}", format!("{}:", section), modules.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+fn run_doctor(opts: &LaunchOptions) -> Result<()> {
+    let checks = doctor::run_checks(&opts.config_path, opts.templates_dir.as_deref());
+    for check in &checks {
+        let mark = match (check.ok, check.critical) {
+            (true, _) => "✓".green(),
+            (false, true) => "✗".red(),
+            (false, false) => "⚠".yellow(),
+        };
+        println!("{} {}: {}", mark, check.name.bold(), check.detail);
+        if let Some(hint) = &check.hint {
+            println!("    {}", hint.cyan());
+        }
+    }
     println!();
+
+    if doctor::has_critical_failure(&checks) {
+        return Err(anyhow::anyhow!(
+            "Waybar cannot be launched until the ✗ checks pass"
+        ));
+    }
+    println!(
+        "{} Everything needed to launch waybar is in place",
+        "✓".green()
+    );
+    Ok(())
 }
 
-fn ask_update_config() -> Result<bool> {
+fn ask_update_config(timeout_secs: u64) -> Result<bool> {
     println!("{}", "Differences were detected in the monitors".yellow());
     println!();
 
+    // Nobody can answer (e.g. session autostart), so don't wait for it
+    if !io::stdin().is_terminal() {
+        println!(
+            "{}",
+            "⚠ Not updating 'available_monitors': stdin is not a terminal (use --yes)".yellow()
+        );
+        return Ok(false);
+    }
+
     println!(
         "{}",
         "¿Do you want to update the configuration with the detected monitors?".cyan()
@@ -428,7 +2180,10 @@ fn ask_update_config() -> Result<bool> {
     );
     println!();
 
-    print!("{}", "Update settings? [y/n] (4 seconds): ".green());
+    print!(
+        "{}",
+        format!("Update settings? [y/n] ({} seconds): ", timeout_secs).green()
+    );
     io::stdout().flush()?;
 
     // Create a channel for communication between threads
@@ -442,8 +2197,7 @@ fn ask_update_config() -> Result<bool> {
         }
     });
 
-    // Wait 4 seconds for a response
-    match rx.recv_timeout(Duration::from_secs(4)) {
+    match rx.recv_timeout(Duration::from_secs(timeout_secs)) {
         Ok(input) => {
             let input = input.trim().to_lowercase();
             Ok(input == "y" || input == "yes")
@@ -473,32 +2227,19 @@ fn ask_update_config_sync() -> Result<bool> {
     Ok(input.is_empty() || input == "y" || input == "yes")
 }
 
-fn send_config_diff_notification() -> Result<()> {
-    use notify_rust::Notification;
-
-    Notification::new()
-        .summary("Omynix Waybar Manager")
-        .body("There are configuration differences. Run 'waybar-manager check' from the terminal to synchronize changes.")
-        .icon("dialog-warning")
-        .timeout(8000) // 8 seconds
-        .show()
-        .context("Error sending notification")?;
-
-    Ok(())
-}
-
-fn interactive_config() -> Result<()> {
+fn interactive_config(opts: &LaunchOptions, detection: &monitor::Detection) -> Result<()> {
     println!("{}", "─────────────────────────────────".cyan());
     println!("{}", "Interactive Monitor Configuration".cyan());
     println!();
 
+    let mut cfg = config::load_config(&opts.config_path, !opts.no_recover)?;
+
     // Detect window and monitor manager
-    let wm = window_manager::detect_window_manager()?;
-    let connected = monitor::get_connected_monitors(&wm)?;
+    let wm = window_manager::detect_window_manager(&cfg.detection.order)?;
+    let connected = detection.monitors(&wm)?;
 
     if connected.is_empty() {
-        println!("{}", "⚠ No connected monitors were detected".red());
-        return Ok(());
+        return Err(Failure::NoMonitors.into());
     }
 
     println!("{}", "Monitors detected:".yellow().bold());
@@ -525,8 +2266,6 @@ fn interactive_config() -> Result<()> {
     io::stdin().read_line(&mut mode)?;
     let mode = mode.trim();
 
-    let mut cfg = config::load_config()?;
-
     match mode {
         "1" => {
             // Single monitor mode
@@ -536,14 +2275,11 @@ fn interactive_config() -> Result<()> {
             // Multi-monitor mode
             configure_multiple_monitors(&connected, &mut cfg)?;
         }
-        _ => {
-            println!("{}", "⚠ Opción no válida".yellow());
-            return Ok(());
-        }
+        _ => return Err(Failure::InvalidInput(mode.to_string()).into()),
     }
 
     // Save settings
-    config::save_config(&cfg)?;
+    config::save_config(&opts.config_path, &cfg)?;
 
     println!();
     println!("{}", "─────────────────────────────────".cyan());
@@ -588,21 +2324,17 @@ fn configure_single_monitor(connected: &[String], cfg: &mut config::Config) -> R
         let mut choice = String::new();
         io::stdin().read_line(&mut choice)?;
 
-        if let Ok(idx) = choice.trim().parse::<usize>() {
-            if idx > 0 && idx <= connected.len() {
-                let selected = &connected[idx - 1];
-                cfg.display.preferred_monitor = selected.clone();
-                cfg.display.available_monitors = vec![selected.clone()];
-                cfg.display.mode = "single".to_string();
+        let choice = choice.trim();
+        let selected = match choice.parse::<usize>() {
+            Ok(idx) if idx > 0 && idx <= connected.len() => &connected[idx - 1],
+            _ => return Err(Failure::InvalidInput(choice.to_string()).into()),
+        };
+        cfg.display.preferred_monitor = selected.clone();
+        cfg.display.available_monitors = vec![selected.clone()];
+        cfg.display.mode = "single".to_string();
 
-                println!();
-                println!("{}", format!("✓ Selected monitor: {}", selected).green());
-            } else {
-                println!("{}", "⚠ Invalid number".yellow());
-            }
-        } else {
-            println!("{}", "⚠ Invalid entry".yellow());
-        }
+        println!();
+        println!("{}", format!("✓ Selected monitor: {}", selected).green());
     }
 
     Ok(())
@@ -689,12 +2421,13 @@ fn configure_multiple_monitors(connected: &[String], cfg: &mut config::Config) -
         selected.push(connected[preferred_idx].clone()); // Always include the main one
 
         for num_str in selection.split(',') {
-            if let Ok(idx) = num_str.trim().parse::<usize>() {
-                if idx > 0 && idx <= connected.len() {
-                    let mon = &connected[idx - 1];
-                    if !selected.contains(mon) {
-                        selected.push(mon.clone());
-                    }
+            if let Ok(idx) = num_str.trim().parse::<usize>()
+                && idx > 0
+                && idx <= connected.len()
+            {
+                let mon = &connected[idx - 1];
+                if !selected.contains(mon) {
+                    selected.push(mon.clone());
                 }
             }
         }
@@ -1,69 +1,375 @@
 use crate::window_manager::WindowManager;
 use anyhow::{Context, Result, anyhow};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
 use regex::Regex;
+use serde::Serialize;
+use std::cell::OnceCell;
 use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 
-pub fn get_connected_monitors(wm: &WindowManager) -> Result<Vec<String>> {
-    let output = match wm {
-        WindowManager::Hyprland => {
-            let output = Command::new("hyprctl")
-                .arg("monitors")
-                .output()
-                .context("Error running hyprctl monitors")?;
+/// Memoizes monitor detection for the duration of a single run, so flows
+/// that need the connected monitors more than once only query the
+/// compositor once. Long-running loops should create one per cycle.
+#[derive(Default)]
+pub struct Detection {
+    monitors: OnceCell<Vec<MonitorInfo>>,
+}
 
-            String::from_utf8(output.stdout).context("Error decoding the output of hyprctl")?
-        }
-        WindowManager::Mango => {
-            let output = Command::new("mmsg")
-                .arg("-g")
-                .output()
-                .context("Error executing mmsg -g")?;
+impl Detection {
+    /// Names of the connected monitors
+    pub fn monitors(&self, wm: &WindowManager) -> Result<Vec<String>> {
+        Ok(self.info(wm)?.into_iter().map(|m| m.name).collect())
+    }
 
-            String::from_utf8(output.stdout).context("Error decoding mmsg output")?
+    /// Geometry of the connected monitors
+    pub fn info(&self, wm: &WindowManager) -> Result<Vec<MonitorInfo>> {
+        self.info_with(wm, get_connected_monitors)
+    }
+
+    fn info_with(
+        &self,
+        wm: &WindowManager,
+        detect: impl FnOnce(&WindowManager) -> Result<Vec<MonitorInfo>>,
+    ) -> Result<Vec<MonitorInfo>> {
+        if let Some(monitors) = self.monitors.get() {
+            return Ok(monitors.clone());
         }
-        WindowManager::Niri => {
-            let output = Command::new("niri")
-                .args(["msg", "outputs"])
-                .output()
-                .context("Error executing niri msg outputs")?;
 
-            String::from_utf8(output.stdout).context("Error decoding niri output")?
+        let monitors = detect(wm)?;
+        Ok(self.monitors.get_or_init(|| monitors).clone())
+    }
+}
+
+/// Geometry of a connected output as reported by the compositor. Fields
+/// the compositor does not report (e.g. everything but the name on Mango)
+/// stay at their zero defaults.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in Hz
+    pub refresh: f64,
+    pub scale: f64,
+    /// Top-left corner in the global layout
+    pub position: (i32, i32),
+    /// Rotation in degrees (0, 90, 180 or 270), flips ignored
+    pub transform: u16,
+    /// Turned off in the compositor (e.g. the panel of a closed laptop lid)
+    pub disabled: bool,
+}
+
+impl MonitorInfo {
+    fn named(name: &str) -> Self {
+        MonitorInfo {
+            name: name.to_string(),
+            scale: 1.0,
+            ..Default::default()
         }
+    }
+
+    /// Whether the compositor reported a resolution; a 0x0 monitor has no
+    /// geometry to go by (e.g. on Mango)
+    pub fn has_geometry(&self) -> bool {
+        self.width > 0 && self.height > 0
+    }
+
+    /// Rotated a quarter turn, i.e. standing in portrait orientation
+    pub fn is_portrait(&self) -> bool {
+        self.transform % 180 == 90
+    }
+}
+
+/// Rotation from a textual transform such as `90`, `flipped-270` or
+/// `90° counter-clockwise`; anything unrecognized counts as unrotated
+fn parse_transform(value: &str) -> u16 {
+    let degrees = Regex::new(r"(90|180|270)").unwrap();
+    degrees
+        .captures(value)
+        .and_then(|caps| caps[1].parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn get_connected_monitors(wm: &WindowManager) -> Result<Vec<MonitorInfo>> {
+    parse_monitors(wm, &query_outputs(wm)?)
+}
+
+/// Names of the connected monitors
+pub fn get_connected_monitor_names(wm: &WindowManager) -> Result<Vec<String>> {
+    Ok(get_connected_monitors(wm)?
+        .into_iter()
+        .map(|m| m.name)
+        .collect())
+}
+
+/// The command that lists the monitors on `wm`; `None` for Wayfire, which
+/// is queried over its IPC socket
+pub fn query_command(wm: &WindowManager) -> Option<Command> {
+    let (program, args): (&str, &[&str]) = match wm {
+        WindowManager::Hyprland => ("hyprctl", &["monitors"]),
+        WindowManager::Mango => ("mmsg", &["-g"]),
+        WindowManager::Niri => ("niri", &["msg", "outputs"]),
+        WindowManager::Sway => ("swaymsg", &["-t", "get_outputs", "-r"]),
+        // These have no output query of their own
+        WindowManager::River | WindowManager::Labwc | WindowManager::Dwl => ("wlr-randr", &[]),
+        WindowManager::Wayfire => return None,
+    };
+
+    let mut command = Command::new(program);
+    command.args(args);
+    Some(command)
+}
+
+/// Raw output listing from the compositor, as parsed by `parse_monitors`
+fn query_outputs(wm: &WindowManager) -> Result<String> {
+    let Some(mut command) = query_command(wm) else {
+        return match std::env::var_os("WAYFIRE_SOCKET") {
+            Some(socket) => match wayfire_ipc(Path::new(&socket), "window-rules/list-outputs") {
+                Ok(reply) => Ok(reply),
+                Err(ipc) => wlr_randr_json().map_err(|e| {
+                    anyhow!(
+                        "Wayfire IPC failed ({:#}), and so did wlr-randr: {:#}",
+                        ipc,
+                        e
+                    )
+                }),
+            },
+            // The ipc plugin is not loaded
+            None => wlr_randr_json(),
+        };
     };
 
-    parse_monitors(wm, &output)
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            anyhow!(
+                "{} is not installed; it is required to detect monitors on {:?}",
+                program,
+                wm
+            )
+        } else {
+            anyhow!("Error executing {}: {}", program, e)
+        }
+    })?;
+
+    String::from_utf8(output.stdout).context(format!("Error decoding {} output", program))
+}
+
+/// `wlr-randr --json`, for Wayfire sessions without the ipc plugin
+fn wlr_randr_json() -> Result<String> {
+    let output = Command::new("wlr-randr")
+        .arg("--json")
+        .output()
+        .context("Error executing wlr-randr --json")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "wlr-randr --json failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout).context("Error decoding wlr-randr output")
 }
 
-fn parse_monitors(wm: &WindowManager, output: &str) -> Result<Vec<String>> {
-    let mut monitors = Vec::new();
+/// Largest Wayfire IPC reply accepted; an output list is a few KiB
+const MAX_WAYFIRE_REPLY: usize = 1 << 20;
+
+/// Call `method` over Wayfire's IPC socket. Messages in both directions are
+/// JSON preceded by their length as a little-endian u32.
+fn wayfire_ipc(socket: &Path, method: &str) -> Result<String> {
+    let mut stream =
+        UnixStream::connect(socket).context(format!("Error connecting to {}", socket.display()))?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(2)))?;
+
+    let request = serde_json::json!({ "method": method, "data": {} }).to_string();
+    stream.write_all(&(request.len() as u32).to_le_bytes())?;
+    stream.write_all(request.as_bytes())?;
+
+    let mut len = [0u8; 4];
+    stream
+        .read_exact(&mut len)
+        .context("Error reading the Wayfire IPC reply")?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_WAYFIRE_REPLY {
+        return Err(anyhow!(
+            "Wayfire IPC reply of {} bytes exceeds the {} byte limit",
+            len,
+            MAX_WAYFIRE_REPLY
+        ));
+    }
+    let mut reply = vec![0u8; len];
+    stream
+        .read_exact(&mut reply)
+        .context("Error reading the Wayfire IPC reply")?;
+
+    String::from_utf8(reply).context("Error decoding the Wayfire IPC reply")
+}
+
+fn parse_monitors(wm: &WindowManager, output: &str) -> Result<Vec<MonitorInfo>> {
+    let mut monitors: Vec<MonitorInfo> = Vec::new();
 
     match wm {
         WindowManager::Hyprland => {
-            // Search for lines that begin with "Monitor"
-            let re = Regex::new(r"^Monitor\s+(\S+)").unwrap();
+            // "Monitor <name> (ID n):" followed by indented details, e.g.
+            // "\t1920x1080@60.00000 at 0x0" and "\tscale: 1.00"
+            let header = Regex::new(r"^Monitor\s+(\S+)").unwrap();
+            let mode = Regex::new(r"^\s+(\d+)x(\d+)@([\d.]+)\s+at\s+(-?\d+)x(-?\d+)").unwrap();
             for line in output.lines() {
-                if let Some(caps) = re.captures(line) {
-                    monitors.push(caps[1].to_string());
+                if let Some(caps) = header.captures(line) {
+                    monitors.push(MonitorInfo::named(&caps[1]));
+                } else if let Some(current) = monitors.last_mut() {
+                    if let Some(caps) = mode.captures(line) {
+                        current.width = caps[1].parse().unwrap_or(0);
+                        current.height = caps[2].parse().unwrap_or(0);
+                        current.refresh = caps[3].parse().unwrap_or(0.0);
+                        current.position =
+                            (caps[4].parse().unwrap_or(0), caps[5].parse().unwrap_or(0));
+                    } else if let Some(scale) = line.trim().strip_prefix("scale:") {
+                        current.scale = scale.trim().parse().unwrap_or(1.0);
+                    } else if line.trim() == "disabled: true" {
+                        current.disabled = true;
+                    } else if let Some(transform) = line.trim().strip_prefix("transform:") {
+                        // wl_output transform: 0-3 rotate, 4-7 flip then rotate
+                        let transform: u16 = transform.trim().parse().unwrap_or(0);
+                        current.transform = (transform % 4) * 90;
+                    }
                 }
             }
         }
         WindowManager::Mango => {
-            // Search for lines containing "selmon"
+            // mmsg reports no geometry
+            monitors.extend(
+                parse_mango_outputs(output)
+                    .into_iter()
+                    .map(|(name, _)| MonitorInfo::named(&name)),
+            );
+        }
+        WindowManager::Niri => {
+            // `Output "<description>" (<name>)` followed by indented details
+            let header = Regex::new(r#"^Output\s+"[^"]*"\s+\(([^)]+)\)"#).unwrap();
+            let mode = Regex::new(r"Current mode:\s*(\d+)x(\d+)\s*@\s*([\d.]+)").unwrap();
+            let position = Regex::new(r"Logical position:\s*(-?\d+),\s*(-?\d+)").unwrap();
             for line in output.lines() {
-                if line.contains("selmon") {
-                    if let Some(monitor) = line.split_whitespace().next() {
-                        monitors.push(monitor.to_string());
+                if let Some(caps) = header.captures(line) {
+                    monitors.push(MonitorInfo::named(&caps[1]));
+                } else if let Some(current) = monitors.last_mut() {
+                    if let Some(caps) = mode.captures(line) {
+                        current.width = caps[1].parse().unwrap_or(0);
+                        current.height = caps[2].parse().unwrap_or(0);
+                        current.refresh = caps[3].parse().unwrap_or(0.0);
+                    } else if let Some(caps) = position.captures(line) {
+                        current.position =
+                            (caps[1].parse().unwrap_or(0), caps[2].parse().unwrap_or(0));
+                    } else if let Some(scale) = line.trim().strip_prefix("Scale:") {
+                        current.scale = scale.trim().parse().unwrap_or(1.0);
+                    } else if let Some(transform) = line.trim().strip_prefix("Transform:") {
+                        current.transform = parse_transform(transform);
+                    } else if line.trim() == "Disabled" {
+                        current.disabled = true;
                     }
                 }
             }
         }
-        WindowManager::Niri => {
-            // Search for the monitor in parentheses on lines that begin with "Output"
-            let re = Regex::new(r#"^Output\s+"[^"]*"\s+\(([^)]+)\)"#).unwrap();
+        WindowManager::Sway => {
+            // JSON array of outputs, each with a "name", its "rect" and "active"
+            let outputs: Vec<serde_json::Value> =
+                serde_json::from_str(output).context("Error parsing swaymsg get_outputs JSON")?;
+            for output in &outputs {
+                let Some(name) = output["name"].as_str() else {
+                    continue;
+                };
+                let rect = &output["rect"];
+                // "rect" is the scaled, rotated size in the layout; the
+                // resolution is the one of the current mode
+                let mode = &output["current_mode"];
+                let mut info = MonitorInfo::named(name);
+                info.width = mode["width"].as_u64().unwrap_or(0) as u32;
+                info.height = mode["height"].as_u64().unwrap_or(0) as u32;
+                info.position = (
+                    rect["x"].as_i64().unwrap_or(0) as i32,
+                    rect["y"].as_i64().unwrap_or(0) as i32,
+                );
+                // Inactive outputs report a scale of -1
+                if let Some(scale) = output["scale"].as_f64().filter(|scale| *scale > 0.0) {
+                    info.scale = scale;
+                }
+                // Reported in mHz
+                info.refresh = mode["refresh"].as_f64().unwrap_or(0.0) / 1000.0;
+                info.transform = parse_transform(output["transform"].as_str().unwrap_or(""));
+                info.disabled = output["active"] == false;
+                monitors.push(info);
+            }
+            // Outputs come in the order sway created them; list them as laid out
+            monitors.sort_by_key(|m| m.position);
+        }
+        WindowManager::Wayfire => {
+            // JSON array from the IPC (outputs with a "geometry") or from
+            // `wlr-randr --json` (outputs with "modes", "position", "enabled")
+            let outputs: Vec<serde_json::Value> =
+                serde_json::from_str(output).context("Error parsing Wayfire outputs JSON")?;
+            for output in &outputs {
+                let Some(name) = output["name"].as_str() else {
+                    continue;
+                };
+                let mut info = MonitorInfo::named(name);
+                let coord = |value: &serde_json::Value| value.as_i64().unwrap_or(0) as i32;
+                let size = |value: &serde_json::Value| value.as_u64().unwrap_or(0) as u32;
+
+                if let Some(geometry) = output.get("geometry") {
+                    info.width = size(&geometry["width"]);
+                    info.height = size(&geometry["height"]);
+                    info.position = (coord(&geometry["x"]), coord(&geometry["y"]));
+                } else {
+                    let current = output["modes"]
+                        .as_array()
+                        .and_then(|modes| modes.iter().find(|m| m["current"] == true));
+                    if let Some(mode) = current {
+                        info.width = size(&mode["width"]);
+                        info.height = size(&mode["height"]);
+                        info.refresh = mode["refresh"].as_f64().unwrap_or(0.0);
+                    }
+                    info.position = (
+                        coord(&output["position"]["x"]),
+                        coord(&output["position"]["y"]),
+                    );
+                    info.scale = output["scale"].as_f64().unwrap_or(1.0);
+                    info.transform = parse_transform(output["transform"].as_str().unwrap_or(""));
+                    info.disabled = output["enabled"] == false;
+                }
+                monitors.push(info);
+            }
+        }
+        WindowManager::River | WindowManager::Labwc | WindowManager::Dwl => {
+            // wlr-randr: unindented lines start an output, e.g. `DP-1 "Dell ..."`,
+            // the current mode is marked "current" among the indented modes
+            let mode = Regex::new(r"^\s+(\d+)x(\d+) px, ([\d.]+) Hz.*current").unwrap();
+            let position = Regex::new(r"^\s+Position:\s*(-?\d+),(-?\d+)").unwrap();
             for line in output.lines() {
-                if let Some(caps) = re.captures(line) {
-                    monitors.push(caps[1].to_string());
+                if !line.starts_with(char::is_whitespace) {
+                    if let Some(monitor) = line.split_whitespace().next() {
+                        monitors.push(MonitorInfo::named(monitor));
+                    }
+                } else if let Some(current) = monitors.last_mut() {
+                    if let Some(caps) = mode.captures(line) {
+                        current.width = caps[1].parse().unwrap_or(0);
+                        current.height = caps[2].parse().unwrap_or(0);
+                        current.refresh = caps[3].parse().unwrap_or(0.0);
+                    } else if let Some(caps) = position.captures(line) {
+                        current.position =
+                            (caps[1].parse().unwrap_or(0), caps[2].parse().unwrap_or(0));
+                    } else if let Some(scale) = line.trim().strip_prefix("Scale:") {
+                        current.scale = scale.trim().parse().unwrap_or(1.0);
+                    } else if let Some(transform) = line.trim().strip_prefix("Transform:") {
+                        current.transform = parse_transform(transform);
+                    } else if line.trim() == "Enabled: no" {
+                        current.disabled = true;
+                    }
                 }
             }
         }
@@ -76,102 +382,1108 @@ fn parse_monitors(wm: &WindowManager, output: &str) -> Result<Vec<String>> {
     Ok(monitors)
 }
 
-pub fn find_matches(configured: &[String], connected: &[String]) -> Vec<String> {
-    let configured_set: HashSet<_> = configured.iter().collect();
-    let connected_set: HashSet<_> = connected.iter().collect();
+/// Machine-readable monitor list for `--output json`: every field of
+/// `MonitorInfo` plus `connected`
+pub fn monitors_json(monitors: &[MonitorInfo]) -> Result<String> {
+    let entries = monitors
+        .iter()
+        .map(|monitor| {
+            let mut entry = serde_json::to_value(monitor)?;
+            entry["connected"] = serde_json::Value::Bool(true);
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    serde_json::to_string_pretty(&entries).context("Error serializing monitors")
+}
 
-    configured_set
-        .intersection(&connected_set)
-        .map(|s| (*s).clone())
+/// Connected monitors the compositor has turned off
+pub fn disabled_monitors(monitors: &[MonitorInfo]) -> Vec<String> {
+    monitors
+        .iter()
+        .filter(|m| m.disabled)
+        .map(|m| m.name.clone())
         .collect()
 }
 
-pub fn lists_match(list1: &[String], list2: &[String]) -> bool {
-    if list1.len() != list2.len() {
-        return false;
+/// Connected monitors currently rotated into portrait orientation
+pub fn portrait_monitors(monitors: &[MonitorInfo]) -> Vec<String> {
+    monitors
+        .iter()
+        .filter(|m| m.is_portrait())
+        .map(|m| m.name.clone())
+        .collect()
+}
+
+/// Connected monitors showing the same part of the layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorGroup {
+    /// The monitor that keeps its bar
+    pub kept: String,
+    pub mirrors: Vec<String>,
+}
+
+/// Monitors of `candidates` mirroring each other (same position and
+/// resolution), so a monitor already left out (e.g. disabled) never keeps
+/// a group's bar; each group keeps the monitor that comes first in
+/// `priority`
+pub fn mirrored_monitors(
+    monitors: &[MonitorInfo],
+    candidates: &[String],
+    priority: &[String],
+    case_insensitive: bool,
+) -> Vec<MirrorGroup> {
+    let rank = |name: &str| {
+        priority
+            .iter()
+            .position(|p| names_equal(p, name, case_insensitive))
+            .unwrap_or(priority.len())
+    };
+
+    let mut groups: Vec<Vec<&MonitorInfo>> = Vec::new();
+    // Without geometry (e.g. Mango) every monitor would look mirrored
+    for monitor in monitors.iter().filter(|m| {
+        m.has_geometry()
+            && !m.disabled
+            && find_monitor(candidates, &m.name, case_insensitive).is_some()
+    }) {
+        let same = |other: &&MonitorInfo| {
+            other.position == monitor.position
+                && other.width == monitor.width
+                && other.height == monitor.height
+        };
+        match groups.iter_mut().find(|group| same(&group[0])) {
+            Some(group) => group.push(monitor),
+            None => groups.push(vec![monitor]),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            // Stable, so unranked monitors keep the compositor's order
+            group.sort_by_key(|m| rank(&m.name));
+            MirrorGroup {
+                kept: group[0].name.clone(),
+                mirrors: group[1..].iter().map(|m| m.name.clone()).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Connected monitors a launch leaves out, and why
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Skipped {
+    pub disabled: Vec<String>,
+    /// Reporting a 0x0 resolution, e.g. in DPMS standby
+    pub zero_resolution: Vec<String>,
+    /// Groups of mirrors of which only `kept` gets a bar
+    pub mirrors: Vec<MirrorGroup>,
+}
+
+/// The connected monitors a launch considers: `monitors` without the
+/// disabled ones (unless `include_disabled`), the 0x0 ones (unless
+/// `include_zero_resolution`) and all but one of each mirror group (with
+/// `mirror_policy = "one"`). A filter that would leave nothing is skipped.
+pub fn usable_monitors(
+    cfg: &crate::config::Config,
+    monitors: &[MonitorInfo],
+    wm: &WindowManager,
+    include_disabled: bool,
+) -> (Vec<String>, Skipped) {
+    let display = &cfg.display;
+    let ci = display.case_insensitive_match;
+    let mut usable: Vec<String> = monitors.iter().map(|m| m.name.clone()).collect();
+    let mut skipped = Skipped::default();
+
+    if !include_disabled {
+        let disabled = disabled_monitors(monitors);
+        let enabled = without(&usable, &disabled, ci);
+        if !disabled.is_empty() && !enabled.is_empty() {
+            usable = enabled;
+            skipped.disabled = disabled;
+        }
+    }
+    if !display.include_zero_resolution {
+        let zero = zero_resolution_monitors(wm, monitors);
+        let active = without(&usable, &zero, ci);
+        if !zero.is_empty() && !active.is_empty() {
+            usable = active;
+            skipped.zero_resolution = zero;
+        }
+    }
+    if display.mirror_policy == crate::config::MirrorPolicy::One {
+        let priority: Vec<String> = display
+            .mirror_keep
+            .iter()
+            .chain(std::iter::once(&display.preferred_monitor))
+            .chain(&display.available_monitors)
+            .cloned()
+            .collect();
+        skipped.mirrors = mirrored_monitors(monitors, &usable, &priority, ci);
+        for group in &skipped.mirrors {
+            usable = without(&usable, &group.mirrors, ci);
+        }
     }
 
-    let set1: HashSet<_> = list1.iter().collect();
-    let set2: HashSet<_> = list2.iter().collect();
+    (usable, skipped)
+}
+
+/// Every output in `mmsg -g` output, in order, and whether it is the
+/// selected one. Each line is `<output> <key> <value...>` (`selmon`,
+/// `tags`, `layout`, `title`, ...), with one `selmon 0|1` line per output.
+fn parse_mango_outputs(output: &str) -> Vec<(String, bool)> {
+    let mut outputs: Vec<(String, bool)> = Vec::new();
+    for line in output.lines() {
+        let mut parts = line.split_whitespace();
+        let Some(name) = parts.next() else {
+            continue;
+        };
+        let selected = parts.next() == Some("selmon") && parts.next() == Some("1");
 
-    set1 == set2
+        match outputs.iter_mut().find(|(known, _)| known == name) {
+            Some(entry) => entry.1 |= selected,
+            None => outputs.push((name.to_string(), selected)),
+        }
+    }
+    outputs
 }
 
-pub fn is_waybar_running() -> bool {
-    match Command::new("pgrep").arg("waybar").output() {
-        Ok(output) => {
-            if !output.status.success() {
-                return false;
+/// Connected monitors the compositor reports with a 0x0 resolution, which
+/// happens for outputs in DPMS standby
+pub fn zero_resolution_monitors(wm: &WindowManager, monitors: &[MonitorInfo]) -> Vec<String> {
+    // mmsg reports no resolution at all, so 0x0 means nothing there
+    if *wm == WindowManager::Mango {
+        return Vec::new();
+    }
+
+    monitors
+        .iter()
+        .filter(|m| m.width == 0 || m.height == 0)
+        .map(|m| m.name.clone())
+        .collect()
+}
+
+/// Currently focused output, or `None` when the compositor does not report one
+pub fn get_focused_monitor(wm: &WindowManager) -> Result<Option<String>> {
+    let (program, args): (&str, &[&str]) = match wm {
+        WindowManager::Hyprland => ("hyprctl", &["monitors"]),
+        WindowManager::Mango => ("mmsg", &["-g"]),
+        WindowManager::Niri => ("niri", &["msg", "focused-output"]),
+        WindowManager::Sway => ("swaymsg", &["-t", "get_outputs", "-r"]),
+        // wlr-randr has no notion of focus
+        WindowManager::River
+        | WindowManager::Labwc
+        | WindowManager::Dwl
+        | WindowManager::Wayfire => return Ok(None),
+    };
+
+    let output = Command::new(program).args(args).output().context(format!(
+        "Error running {} {}",
+        program,
+        args.join(" ")
+    ))?;
+
+    Ok(parse_focused_monitor(
+        wm,
+        &String::from_utf8_lossy(&output.stdout),
+    ))
+}
+
+fn parse_focused_monitor(wm: &WindowManager, output: &str) -> Option<String> {
+    match wm {
+        WindowManager::Hyprland => {
+            // The "focused: yes" line belongs to the last "Monitor" header
+            let re = Regex::new(r"^Monitor\s+(\S+)").unwrap();
+            let mut current = None;
+            for line in output.lines() {
+                if let Some(caps) = re.captures(line) {
+                    current = Some(caps[1].to_string());
+                } else if line.trim() == "focused: yes" {
+                    return current;
+                }
             }
+            None
+        }
+        WindowManager::Mango => parse_mango_outputs(output)
+            .into_iter()
+            .find_map(|(name, selected)| selected.then_some(name)),
+        WindowManager::Niri => {
+            let re = Regex::new(r#"^Output\s+"[^"]*"\s+\(([^)]+)\)"#).unwrap();
+            output
+                .lines()
+                .find_map(|line| re.captures(line).map(|caps| caps[1].to_string()))
+        }
+        WindowManager::Sway => {
+            let outputs: Vec<serde_json::Value> = serde_json::from_str(output).ok()?;
+            outputs
+                .iter()
+                .find(|o| o["focused"] == true)
+                .and_then(|o| o["name"].as_str())
+                .map(str::to_string)
+        }
+        WindowManager::River
+        | WindowManager::Labwc
+        | WindowManager::Dwl
+        | WindowManager::Wayfire => None,
+    }
+}
 
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let pids: Vec<&str> = stdout.trim().lines().collect();
+/// The focused monitor to use as preferred for this run, if it is connected
+pub fn focused_as_preferred(
+    focused: Option<&str>,
+    connected: &[String],
+    case_insensitive: bool,
+) -> Option<String> {
+    find_monitor(connected, focused?, case_insensitive).cloned()
+}
 
-            // Obtain the PID of the current process
-            let current_pid = std::process::id();
+/// Where a single-mode bar goes when the preferred monitor is not
+/// connected: the focused monitor, else the first one detected
+pub fn single_mode_fallback(
+    focused: Option<&str>,
+    connected: &[String],
+    case_insensitive: bool,
+) -> Option<String> {
+    focused_as_preferred(focused, connected, case_insensitive)
+        .or_else(|| connected.first().cloned())
+}
 
-            // Filter PIDs, excluding the current process
-            let waybar_pids: Vec<&str> = pids
-                .into_iter()
-                .filter(|pid| {
-                    if let Ok(pid_num) = pid.parse::<u32>() {
-                        pid_num != current_pid
-                    } else {
-                        true
-                    }
-                })
-                .collect();
+/// Monitors a launch starts bars on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub monitors: Vec<String>,
+    /// Set when single mode fell back from a disconnected
+    /// `preferred_monitor`: "the focused one" or "the first one detected"
+    pub fallback: Option<&'static str>,
+}
+
+/// The monitors of `connected` a launch puts bars on: all of them in
+/// multiple mode, `preferred_monitor` (or a fallback) in single mode
+pub fn select_monitors(
+    cfg: &crate::config::Config,
+    connected: &[String],
+    wm: &WindowManager,
+) -> Result<Selection> {
+    select_monitors_with(cfg, connected, || get_focused_monitor(wm).unwrap_or(None))
+}
+
+/// `select_monitors`, asking `focused` for the focused monitor only when
+/// single mode needs a fallback
+pub fn select_monitors_with(
+    cfg: &crate::config::Config,
+    connected: &[String],
+    focused: impl FnOnce() -> Option<String>,
+) -> Result<Selection> {
+    let display = &cfg.display;
+    let ci = display.case_insensitive_match;
+    if display.mode != "single" {
+        return Ok(Selection {
+            monitors: connected.to_vec(),
+            fallback: None,
+        });
+    }
+
+    if let Some(preferred) = find_monitor(connected, &display.preferred_monitor, ci) {
+        return Ok(Selection {
+            monitors: vec![preferred.clone()],
+            fallback: None,
+        });
+    }
+
+    // strict_single forbids using another monitor
+    if display.strict_single {
+        return Err(
+            crate::exit::Failure::PreferredNotConnected(display.preferred_monitor.clone()).into(),
+        );
+    }
+    let focused = focused();
+    let Some(fallback) = single_mode_fallback(focused.as_deref(), connected, ci) else {
+        return Ok(Selection {
+            monitors: Vec::new(),
+            fallback: None,
+        });
+    };
+    let source = if focused.is_some_and(|focused| names_equal(&focused, &fallback, ci)) {
+        "the focused one"
+    } else {
+        "the first one detected"
+    };
+    Ok(Selection {
+        monitors: vec![fallback],
+        fallback: Some(source),
+    })
+}
+
+/// Whether the connector is a built-in laptop panel (eDP, LVDS, DSI)
+pub fn is_internal(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["edp", "lvds", "dsi"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Connector family of a monitor name, lowercased: `eDP-1` → `edp`,
+/// `HDMI-A-1` → `hdmi`, `DP-2` → `dp`
+pub fn connector_family(name: &str) -> String {
+    name.split('-').next().unwrap_or(name).to_ascii_lowercase()
+}
 
-            !waybar_pids.is_empty()
+/// Compare monitor names, optionally ignoring ASCII case
+pub fn names_equal(a: &str, b: &str, case_insensitive: bool) -> bool {
+    if case_insensitive {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// Find `name` in `monitors`, returning the entry with its original casing
+pub fn find_monitor<'a>(
+    monitors: &'a [String],
+    name: &str,
+    case_insensitive: bool,
+) -> Option<&'a String> {
+    monitors
+        .iter()
+        .find(|m| names_equal(m, name, case_insensitive))
+}
+
+/// Monitors present in both lists, using the connected casing
+pub fn find_matches(
+    configured: &[String],
+    connected: &[String],
+    case_insensitive: bool,
+) -> Vec<String> {
+    let mut matches: Vec<String> = Vec::new();
+
+    for mon in connected {
+        if find_monitor(configured, mon, case_insensitive).is_some()
+            && find_monitor(&matches, mon, case_insensitive).is_none()
+        {
+            matches.push(mon.clone());
         }
-        Err(_) => false,
     }
+
+    matches
 }
 
-pub fn kill_waybar() -> Result<()> {
-    // Get the PIDs from Waybar
-    let output = Command::new("pidof")
-        .arg("waybar")
-        .output()
-        .context("Error retrieving PIDs from Waybar")?;
+/// `monitors` minus every entry of `excluded`
+pub fn without(monitors: &[String], excluded: &[String], case_insensitive: bool) -> Vec<String> {
+    monitors
+        .iter()
+        .filter(|m| find_monitor(excluded, m, case_insensitive).is_none())
+        .cloned()
+        .collect()
+}
+
+/// One line of the `monitors --watch` stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MonitorEvent {
+    /// `snapshot` (first line), `added` or `removed`
+    pub event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+    /// Every monitor connected after the change
+    pub monitors: Vec<String>,
+}
+
+impl MonitorEvent {
+    pub fn snapshot(monitors: &[String]) -> Self {
+        MonitorEvent {
+            event: "snapshot",
+            monitor: None,
+            monitors: monitors.to_vec(),
+        }
+    }
+}
+
+/// Events for the monitor set going from `old` to `new`: removals first,
+/// then additions
+pub fn monitor_events(old: &[String], new: &[String]) -> Vec<MonitorEvent> {
+    let event = |event, monitor: &String| MonitorEvent {
+        event,
+        monitor: Some(monitor.clone()),
+        monitors: new.to_vec(),
+    };
+
+    without(old, new, false)
+        .iter()
+        .map(|m| event("removed", m))
+        .chain(without(new, old, false).iter().map(|m| event("added", m)))
+        .collect()
+}
 
-    if !output.status.success() || output.stdout.is_empty() {
-        // There are no waybar processes running.
-        return Ok(());
+/// What syncing `old` to `new` changes, one `+ name`/`- name` line per
+/// added/removed monitor; a pure reordering is reported as such
+pub fn sync_diff(old: &[String], new: &[String], case_insensitive: bool) -> Vec<String> {
+    let added = without(new, old, case_insensitive);
+    let removed = without(old, new, case_insensitive);
+    if added.is_empty() && removed.is_empty() {
+        return if old == new {
+            Vec::new()
+        } else {
+            vec![format!("~ order: {}", new.join(", "))]
+        };
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let pids: Vec<&str> = stdout.trim().split_whitespace().collect();
+    added
+        .iter()
+        .map(|m| format!("+ {}", m))
+        .chain(removed.iter().map(|m| format!("- {}", m)))
+        .collect()
+}
+
+pub fn lists_match(list1: &[String], list2: &[String], case_insensitive: bool) -> bool {
+    if list1.len() != list2.len() {
+        return false;
+    }
+
+    let normalize = |list: &[String]| -> HashSet<String> {
+        list.iter()
+            .map(|m| {
+                if case_insensitive {
+                    m.to_ascii_lowercase()
+                } else {
+                    m.clone()
+                }
+            })
+            .collect()
+    };
+
+    normalize(list1) == normalize(list2)
+}
+
+pub fn is_waybar_running() -> bool {
+    waybar_pids().is_ok_and(|pids| !pids.is_empty())
+}
+
+/// PIDs of running waybar instances, excluding the current process
+pub fn waybar_pids() -> Result<Vec<u32>> {
+    // `pidof` may be missing on minimal systems; /proc has the same answer
+    let pids = match Command::new("pidof").arg("waybar").output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .filter_map(|pid| pid.parse::<u32>().ok())
+            .collect(),
+        Err(_) => pids_from_proc(Path::new("/proc"), "waybar")
+            .context("Error retrieving PIDs from Waybar")?,
+    };
 
     // Get the PID of the current process (waybar-manager)
     let current_pid = std::process::id();
 
-    // Filter and kill only the PIDs that are NOT the current process
-    for pid_str in pids {
-        if let Ok(pid_num) = pid_str.parse::<u32>() {
-            // Do not kill the current process or its direct parents/children
-            if pid_num != current_pid {
-                Command::new("kill").arg(pid_str).output().ok(); // Ignoring individual mistakes
-            }
+    // Do not touch the current process or its direct parents/children
+    Ok(pids.into_iter().filter(|pid| *pid != current_pid).collect())
+}
+
+/// PIDs whose `<proc>/<pid>/comm` is `name`
+fn pids_from_proc(proc_dir: &Path, name: &str) -> Result<Vec<u32>> {
+    let mut pids = Vec::new();
+    for entry in std::fs::read_dir(proc_dir)?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|p| p.parse().ok()) else {
+            continue;
+        };
+        // Processes may exit while we scan
+        if std::fs::read_to_string(entry.path().join("comm"))
+            .is_ok_and(|comm| comm.trim_end() == name)
+        {
+            pids.push(pid);
         }
     }
 
-    Ok(())
+    pids.sort();
+    Ok(pids)
+}
+
+/// Send `signal` to `pid`; false when it is gone or not ours
+fn send_signal(pid: u32, signal: Signal) -> bool {
+    kill(Pid::from_raw(pid as i32), signal).is_ok()
+}
+
+/// Send `signal` to every waybar instance, returning how many were signalled
+fn signal_waybar(signal: Signal) -> Result<usize> {
+    Ok(waybar_pids()?
+        .into_iter()
+        .filter(|pid| send_signal(*pid, signal)) // Ignoring individual mistakes
+        .count())
+}
+
+/// Send SIGTERM to specific instances, returning how many were signalled
+pub fn terminate(pids: &[u32]) -> usize {
+    pids.iter()
+        .filter(|pid| send_signal(**pid, Signal::SIGTERM))
+        .count()
+}
+
+pub fn kill_waybar() -> Result<usize> {
+    signal_waybar(Signal::SIGTERM)
+}
+
+/// Kill every waybar instance with SIGKILL, for bars that ignore SIGTERM
+pub fn force_kill_waybar() -> Result<usize> {
+    signal_waybar(Signal::SIGKILL)
+}
+
+/// Wait up to `timeout` for every waybar to exit, e.g. after
+/// `kill_waybar`, so new bars don't race the old ones for their layer
+/// surfaces. Returns whether they all did.
+pub fn wait_for_waybar_exit(timeout: Duration) -> bool {
+    // Our own killed children are reaped here, not left as zombies
+    let pids = waybar_pids().unwrap_or_default();
+    crate::process::wait_and_check_alive(&pids, timeout).len() == pids.len()
+}
+
+/// Ask running waybar instances to reload their config (SIGUSR2)
+pub fn reload_waybar() -> Result<usize> {
+    signal_waybar(Signal::SIGUSR2)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn names(monitors: &[MonitorInfo]) -> Vec<&str> {
+        monitors.iter().map(|m| m.name.as_str()).collect()
+    }
+
     #[test]
     fn test_parse_hyprland_monitors() {
         let output = r#"Monitor eDP-1 (ID 0):
 	1366x768@60.00500 at 1366x0
+	scale: 1.25
+Monitor HDMI-A-1 (ID 1):
+	1920x1080@60.00 at 0x0"#;
+
+        let monitors = parse_monitors(&WindowManager::Hyprland, output).unwrap();
+        assert_eq!(names(&monitors), vec!["eDP-1", "HDMI-A-1"]);
+        assert_eq!(
+            monitors[0],
+            MonitorInfo {
+                name: "eDP-1".to_string(),
+                width: 1366,
+                height: 768,
+                refresh: 60.005,
+                scale: 1.25,
+                position: (1366, 0),
+                transform: 0,
+                disabled: false,
+            }
+        );
+        assert_eq!(monitors[1].scale, 1.0);
+    }
+
+    #[test]
+    fn test_mirrored_monitors_get_one_bar() {
+        let at = |name: &str, x: i32| MonitorInfo {
+            width: 1920,
+            height: 1080,
+            position: (x, 0),
+            ..MonitorInfo::named(name)
+        };
+        let monitors = vec![at("eDP-1", 0), at("HDMI-A-1", 0), at("DP-1", 1920)];
+        let names: Vec<String> = monitors.iter().map(|m| m.name.clone()).collect();
+
+        let groups = mirrored_monitors(&monitors, &names, &["HDMI-A-1".to_string()], false);
+        assert_eq!(
+            groups,
+            vec![MirrorGroup {
+                kept: "HDMI-A-1".to_string(),
+                mirrors: vec!["eDP-1".to_string()],
+            }]
+        );
+        assert_eq!(
+            without(&names, &groups[0].mirrors, false),
+            vec!["HDMI-A-1", "DP-1"]
+        );
+
+        // A monitor already left out neither keeps the bar nor counts
+        let without_hdmi = without(&names, &["HDMI-A-1".to_string()], false);
+        assert!(
+            mirrored_monitors(&monitors, &without_hdmi, &["HDMI-A-1".to_string()], false)
+                .is_empty()
+        );
+
+        // Monitors without geometry are never treated as mirrors
+        let unknown = vec![MonitorInfo::named("DP-1"), MonitorInfo::named("DP-2")];
+        let unknown_names = ["DP-1".to_string(), "DP-2".to_string()];
+        assert!(mirrored_monitors(&unknown, &unknown_names, &[], false).is_empty());
+    }
+
+    #[test]
+    fn test_usable_monitors_leave_out_disabled_standby_and_mirrors() {
+        let at = |name: &str, x: i32| MonitorInfo {
+            width: 1920,
+            height: 1080,
+            position: (x, 0),
+            ..MonitorInfo::named(name)
+        };
+        let monitors = vec![
+            MonitorInfo {
+                disabled: true,
+                ..at("eDP-1", 0)
+            },
+            at("HDMI-A-1", 0),
+            at("DP-1", 1920),
+            at("DP-2", 1920),
+            MonitorInfo::named("DP-3"),
+        ];
+        let mut cfg = crate::config::Config::default();
+        cfg.display.mirror_keep = vec!["DP-2".to_string()];
+
+        let (usable, skipped) = usable_monitors(&cfg, &monitors, &WindowManager::Niri, false);
+        assert_eq!(usable, ["HDMI-A-1", "DP-2"]);
+        assert_eq!(skipped.disabled, ["eDP-1"]);
+        assert_eq!(skipped.zero_resolution, ["DP-3"]);
+        // The disabled panel mirrors HDMI-A-1 but takes no part in grouping
+        assert_eq!(
+            skipped.mirrors,
+            vec![MirrorGroup {
+                kept: "DP-2".to_string(),
+                mirrors: vec!["DP-1".to_string()],
+            }]
+        );
+
+        // A filter leaving nothing is not applied
+        let standby = vec![MonitorInfo::named("DP-3")];
+        let (usable, skipped) = usable_monitors(&cfg, &standby, &WindowManager::Niri, false);
+        assert_eq!(usable, ["DP-3"]);
+        assert_eq!(skipped, Skipped::default());
+    }
+
+    #[test]
+    fn test_parse_niri_monitor_geometry() {
+        let output = r#"Output "Sharp Corporation 0x1453 Unknown" (eDP-1)
+  Current mode: 1920x1080 @ 60.020 Hz (preferred)
+  Variable refresh rate: not supported
+  Physical size: 290x170 mm
+  Logical position: -1920, 0
+  Logical size: 1536x864
+  Scale: 1.25
+  Transform: normal
+"#;
+
+        let monitors = parse_monitors(&WindowManager::Niri, output).unwrap();
+        assert_eq!(
+            monitors,
+            vec![MonitorInfo {
+                name: "eDP-1".to_string(),
+                width: 1920,
+                height: 1080,
+                refresh: 60.02,
+                scale: 1.25,
+                position: (-1920, 0),
+                transform: 0,
+                disabled: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_mango_monitors() {
+        let output = "\
+eDP-1 title kitty
+eDP-1 appid kitty
+eDP-1 fullscreen 0
+eDP-1 floating 0
+eDP-1 selmon 0
+eDP-1 tags 1 1 0 0
+eDP-1 layout []=
+HDMI-A-1 title
+HDMI-A-1 fullscreen 0
+HDMI-A-1 selmon 1
+HDMI-A-1 tags 2 2 0 0
+HDMI-A-1 layout []=
+";
+        let monitors = parse_monitors(&WindowManager::Mango, output).unwrap();
+        assert_eq!(names(&monitors), vec!["eDP-1", "HDMI-A-1"]);
+        assert_eq!(
+            parse_focused_monitor(&WindowManager::Mango, output).as_deref(),
+            Some("HDMI-A-1")
+        );
+    }
+
+    #[test]
+    fn test_focused_monitor_becomes_preferred() {
+        let hyprland =
+            "Monitor eDP-1 (ID 0):\n\tfocused: no\nMonitor HDMI-A-1 (ID 1):\n\tfocused: yes\n";
+        let mango = "eDP-1 selmon 0\nHDMI-A-1 selmon 1\n";
+        let niri = "Output \"Dell Inc. U2720Q\" (HDMI-A-1)\n  Current mode: 3840x2160\n";
+        for (wm, output) in [
+            (WindowManager::Hyprland, hyprland),
+            (WindowManager::Mango, mango),
+            (WindowManager::Niri, niri),
+        ] {
+            assert_eq!(
+                parse_focused_monitor(&wm, output).as_deref(),
+                Some("HDMI-A-1"),
+                "{:?}",
+                wm
+            );
+        }
+        assert_eq!(parse_focused_monitor(&WindowManager::Niri, ""), None);
+
+        let connected = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        assert_eq!(
+            focused_as_preferred(Some("hdmi-a-1"), &connected, true),
+            Some("HDMI-A-1".to_string())
+        );
+        // Unknown or missing focus falls back to the configured monitor
+        assert_eq!(focused_as_preferred(Some("DP-3"), &connected, true), None);
+        assert_eq!(focused_as_preferred(None, &connected, true), None);
+
+        // Single mode without its preferred monitor: focused, then first
+        assert_eq!(
+            single_mode_fallback(Some("HDMI-A-1"), &connected, false).as_deref(),
+            Some("HDMI-A-1")
+        );
+        assert_eq!(
+            single_mode_fallback(None, &connected, false).as_deref(),
+            Some(connected[0].as_str())
+        );
+        assert_eq!(single_mode_fallback(None, &[], false), None);
+    }
+
+    #[test]
+    fn test_select_monitors_by_mode() {
+        let connected = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        let mut cfg = crate::config::Config::default();
+        cfg.display.mode = "multiple".to_string();
+        let unfocused = || -> Option<String> { panic!("focus queried") };
+
+        let all = select_monitors_with(&cfg, &connected, unfocused).unwrap();
+        assert_eq!(all.monitors, connected);
+        assert_eq!(all.fallback, None);
+
+        cfg.display.mode = "single".to_string();
+        cfg.display.preferred_monitor = "hdmi-a-1".to_string();
+        cfg.display.case_insensitive_match = true;
+        let single = select_monitors_with(&cfg, &connected, unfocused).unwrap();
+        assert_eq!(single.monitors, ["HDMI-A-1"]);
+
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        let focused = || Some("hdmi-a-1".to_string());
+        let fallback = select_monitors_with(&cfg, &connected, focused).unwrap();
+        assert_eq!(fallback.monitors, ["HDMI-A-1"]);
+        assert_eq!(fallback.fallback, Some("the focused one"));
+        let first = select_monitors_with(&cfg, &connected, || None).unwrap();
+        assert_eq!(first.monitors, ["eDP-1"]);
+        assert_eq!(first.fallback, Some("the first one detected"));
+    }
+
+    #[test]
+    fn test_strict_single_refuses_fallback() {
+        let connected = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        let mut cfg = crate::config::Config::default();
+        cfg.display.mode = "single".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        cfg.display.strict_single = true;
+
+        let err = select_monitors_with(&cfg, &connected, || Some("eDP-1".to_string())).unwrap_err();
+        assert_eq!(err.to_string(), "Preferred monitor 'DP-1' is not connected");
+        assert_eq!(
+            crate::exit::code_for(&err),
+            crate::exit::Failure::PreferredNotConnected(String::new()).code()
+        );
+
+        // Connected, the preferred monitor is used as usual
+        cfg.display.preferred_monitor = "HDMI-A-1".to_string();
+        let selection = select_monitors_with(&cfg, &connected, || None).unwrap();
+        assert_eq!(selection.monitors, ["HDMI-A-1"]);
+    }
+
+    #[test]
+    fn test_parse_sway_monitors() {
+        let output = r#"[
+            {"id": 3, "type": "output", "name": "eDP-1", "active": true, "focused": false,
+             "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}},
+            {"id": 4, "type": "output", "name": "DP-2", "active": true, "focused": true,
+             "rect": {"x": 1920, "y": 0, "width": 2560, "height": 1440}}
+        ]"#;
+
+        let monitors = parse_monitors(&WindowManager::Sway, output).unwrap();
+        assert_eq!(names(&monitors), vec!["eDP-1", "DP-2"]);
+        assert_eq!(
+            parse_focused_monitor(&WindowManager::Sway, output).as_deref(),
+            Some("DP-2")
+        );
+    }
+
+    #[test]
+    fn test_sway_outputs_ordered_by_layout_with_scale() {
+        let output = r#"[
+            {"name": "eDP-1", "active": true, "scale": 1.5,
+             "rect": {"x": 2560, "y": 0, "width": 1280, "height": 800},
+             "current_mode": {"width": 1920, "height": 1200, "refresh": 60000}},
+            {"name": "DP-1", "active": true, "scale": 1.0,
+             "rect": {"x": 0, "y": 0, "width": 2560, "height": 1440},
+             "current_mode": {"width": 2560, "height": 1440, "refresh": 144000}},
+            {"name": "HDMI-A-1", "active": false, "scale": -1.0,
+             "rect": {"x": 0, "y": 0, "width": 0, "height": 0}}
+        ]"#;
+
+        let monitors = parse_monitors(&WindowManager::Sway, output).unwrap();
+        let active: Vec<&MonitorInfo> = monitors.iter().filter(|m| !m.disabled).collect();
+        assert_eq!(
+            active.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(),
+            vec!["DP-1", "eDP-1"]
+        );
+        assert_eq!(active[1].position, (2560, 0));
+        assert_eq!(active[1].scale, 1.5);
+        // The resolution, not the scaled size in the layout
+        assert_eq!((active[1].width, active[1].height), (1920, 1200));
+        assert_eq!(active[0].refresh, 144.0);
+        assert_eq!(disabled_monitors(&monitors), vec!["HDMI-A-1"]);
+        let inactive = monitors.iter().find(|m| m.disabled).unwrap();
+        assert_eq!(inactive.scale, 1.0);
+    }
+
+    #[test]
+    fn test_monitor_events_stream_in_order() {
+        let states = [
+            vec!["eDP-1".to_string()],
+            vec!["eDP-1".to_string(), "HDMI-A-1".to_string()],
+            vec!["eDP-1".to_string(), "HDMI-A-1".to_string()],
+            vec!["DP-1".to_string()],
+        ];
+
+        let mut lines = vec![serde_json::to_string(&MonitorEvent::snapshot(&states[0])).unwrap()];
+        for pair in states.windows(2) {
+            for event in monitor_events(&pair[0], &pair[1]) {
+                lines.push(serde_json::to_string(&event).unwrap());
+            }
+        }
+
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"event":"snapshot","monitors":["eDP-1"]}"#,
+                r#"{"event":"added","monitor":"HDMI-A-1","monitors":["eDP-1","HDMI-A-1"]}"#,
+                r#"{"event":"removed","monitor":"eDP-1","monitors":["DP-1"]}"#,
+                r#"{"event":"removed","monitor":"HDMI-A-1","monitors":["DP-1"]}"#,
+                r#"{"event":"added","monitor":"DP-1","monitors":["DP-1"]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sync_diff_lists_added_and_removed() {
+        let list = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let old = list(&["eDP-1", "HDMI-A-1"]);
+
+        assert_eq!(
+            sync_diff(&old, &list(&["eDP-1", "DP-1", "DP-2"]), false),
+            vec!["+ DP-1", "+ DP-2", "- HDMI-A-1"]
+        );
+        assert_eq!(
+            sync_diff(&old, &list(&["HDMI-A-1", "eDP-1"]), false),
+            vec!["~ order: HDMI-A-1, eDP-1"]
+        );
+        assert!(sync_diff(&old, &list(&["edp-1", "hdmi-a-1"]), true).len() == 1);
+        assert!(sync_diff(&old, &old, false).is_empty());
+    }
+
+    #[test]
+    fn test_disabled_outputs_are_detected() {
+        let hyprland = r#"Monitor eDP-1 (ID 0):
+	1920x1080@60.00 at 0x0
+	disabled: true
+Monitor HDMI-A-1 (ID 1):
+	1920x1080@60.00 at 1920x0
+	disabled: false"#;
+        let monitors = parse_monitors(&WindowManager::Hyprland, hyprland).unwrap();
+        assert_eq!(disabled_monitors(&monitors), vec!["eDP-1"]);
+
+        let niri = r#"Output "Sharp Corporation 0x1453 Unknown" (eDP-1)
+  Disabled
+
+Output "Dell Inc. U2720Q" (DP-1)
+  Current mode: 3840x2160 @ 59.997 Hz (preferred)
+  Transform: normal
+"#;
+        let monitors = parse_monitors(&WindowManager::Niri, niri).unwrap();
+        assert_eq!(names(&monitors), vec!["eDP-1", "DP-1"]);
+        assert_eq!(disabled_monitors(&monitors), vec!["eDP-1"]);
+
+        let sway = r#"[{"name": "eDP-1", "active": false}, {"name": "DP-1", "active": true}]"#;
+        let monitors = parse_monitors(&WindowManager::Sway, sway).unwrap();
+        assert_eq!(disabled_monitors(&monitors), vec!["eDP-1"]);
+    }
+
+    #[test]
+    fn test_monitors_json() {
+        let monitors = parse_monitors(
+            &WindowManager::Hyprland,
+            "Monitor eDP-1 (ID 0):\n\t1920x1080@60.00 at 0x0\n",
+        )
+        .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&monitors_json(&monitors).unwrap()).unwrap();
+        assert_eq!(json[0]["name"], "eDP-1");
+        assert_eq!(json[0]["connected"], true);
+        assert_eq!(json[0]["width"], 1920);
+        assert_eq!(json[0]["position"], serde_json::json!([0, 0]));
+    }
+
+    #[test]
+    fn test_rotated_monitor_is_portrait() {
+        let hyprland = "Monitor DP-1 (ID 0):\n\t1440x2560@60.00 at 0x0\n\ttransform: 1\nMonitor DP-2 (ID 1):\n\t2560x1440@60.00 at 1440x0\n\ttransform: 2\n";
+        let monitors = parse_monitors(&WindowManager::Hyprland, hyprland).unwrap();
+        assert_eq!(monitors[0].transform, 90);
+        assert_eq!(monitors[1].transform, 180);
+        assert_eq!(portrait_monitors(&monitors), vec!["DP-1"]);
+
+        let niri = "Output \"Foo\" (DP-1)\n  Transform: 90° counter-clockwise\n";
+        let monitors = parse_monitors(&WindowManager::Niri, niri).unwrap();
+        assert_eq!(portrait_monitors(&monitors), vec!["DP-1"]);
+
+        let sway = r#"[{"name": "DP-1", "transform": "flipped-270"}, {"name": "DP-2", "transform": "normal"}]"#;
+        let monitors = parse_monitors(&WindowManager::Sway, sway).unwrap();
+        assert_eq!(monitors[0].transform, 270);
+        assert_eq!(portrait_monitors(&monitors), vec!["DP-1"]);
+    }
+
+    #[test]
+    fn test_zero_resolution_monitor_is_excluded() {
+        let output = r#"Monitor eDP-1 (ID 0):
+	0x0@0.00000 at 0x0
 Monitor HDMI-A-1 (ID 1):
 	1920x1080@60.00 at 0x0"#;
 
         let monitors = parse_monitors(&WindowManager::Hyprland, output).unwrap();
-        assert_eq!(monitors, vec!["eDP-1", "HDMI-A-1"]);
+        let connected: Vec<String> = monitors.iter().map(|m| m.name.clone()).collect();
+        let zero = zero_resolution_monitors(&WindowManager::Hyprland, &monitors);
+        assert_eq!(zero, vec!["eDP-1"]);
+        assert_eq!(without(&connected, &zero, false), vec!["HDMI-A-1"]);
+
+        let niri = "Output \"Foo\" (DP-1)\n  Current mode: 0x0 @ 0.000 Hz\nOutput \"Bar\" (DP-2)\n  Current mode: 2560x1440 @ 144.000 Hz\n";
+        let monitors = parse_monitors(&WindowManager::Niri, niri).unwrap();
+        assert_eq!(
+            zero_resolution_monitors(&WindowManager::Niri, &monitors),
+            vec!["DP-1"]
+        );
+    }
+
+    #[test]
+    fn test_parse_wlr_randr() {
+        let output = r#"eDP-1 "Sharp Corporation 0x1453 (eDP-1)"
+  Make: Sharp Corporation
+  Modes:
+    1920x1080 px, 60.000000 Hz (preferred, current)
+  Position: 0,0
+  Enabled: yes
+HDMI-A-1 "Dell Inc. DELL U2415 (HDMI-A-1)"
+  Modes:
+    1920x1200 px, 59.950001 Hz (preferred, current)
+  Position: 1920,0
+  Enabled: yes
+"#;
+
+        let monitors = parse_monitors(&WindowManager::River, output).unwrap();
+        assert_eq!(names(&monitors), vec!["eDP-1", "HDMI-A-1"]);
+        assert_eq!((monitors[1].width, monitors[1].height), (1920, 1200));
+        assert_eq!(monitors[1].position, (1920, 0));
+
+        // labwc and dwl are read through wlr-randr as well
+        for wm in [WindowManager::Labwc, WindowManager::Dwl] {
+            assert_eq!(parse_monitors(&wm, output).unwrap(), monitors);
+        }
+    }
+
+    #[test]
+    fn test_parse_wayfire_outputs() {
+        let ipc = r#"[
+            {"id": 1, "name": "DP-1", "wset-index": 1,
+             "geometry": {"x": 0, "y": 0, "width": 2560, "height": 1440}},
+            {"id": 2, "name": "eDP-1", "wset-index": 2,
+             "geometry": {"x": 2560, "y": 0, "width": 1920, "height": 1200}}
+        ]"#;
+        let monitors = parse_monitors(&WindowManager::Wayfire, ipc).unwrap();
+        assert_eq!(names(&monitors), vec!["DP-1", "eDP-1"]);
+        assert_eq!(monitors[1].position, (2560, 0));
+
+        let randr = r#"[
+            {"name": "eDP-1", "enabled": true, "scale": 1.25, "transform": "90",
+             "position": {"x": 0, "y": 0},
+             "modes": [{"width": 1920, "height": 1200, "refresh": 60.0, "current": true},
+                       {"width": 1280, "height": 800, "refresh": 60.0, "current": false}]},
+            {"name": "HDMI-A-1", "enabled": false, "modes": []}
+        ]"#;
+        let monitors = parse_monitors(&WindowManager::Wayfire, randr).unwrap();
+        assert_eq!(names(&monitors), vec!["eDP-1", "HDMI-A-1"]);
+        assert_eq!((monitors[0].width, monitors[0].height), (1920, 1200));
+        assert_eq!(monitors[0].scale, 1.25);
+        assert!(monitors[0].is_portrait());
+        assert_eq!(disabled_monitors(&monitors), vec!["HDMI-A-1"]);
+    }
+
+    #[test]
+    fn test_wayfire_ipc_framing() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("wayfire-ipc.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            let mut request = vec![0u8; u32::from_le_bytes(len) as usize];
+            stream.read_exact(&mut request).unwrap();
+
+            let reply = br#"[{"name": "DP-1", "geometry": {"width": 1920, "height": 1080}}]"#;
+            stream
+                .write_all(&(reply.len() as u32).to_le_bytes())
+                .unwrap();
+            stream.write_all(reply).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let reply = wayfire_ipc(&socket, "window-rules/list-outputs").unwrap();
+        let request: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+        assert_eq!(request["method"], "window-rules/list-outputs");
+        let monitors = parse_monitors(&WindowManager::Wayfire, &reply).unwrap();
+        assert_eq!(names(&monitors), vec!["DP-1"]);
+    }
+
+    #[test]
+    fn test_wayfire_ipc_rejects_oversized_reply() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("wayfire-ipc.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            let mut request = vec![0u8; u32::from_le_bytes(len) as usize];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        });
+
+        let err = wayfire_ipc(&socket, "window-rules/list-outputs").unwrap_err();
+        server.join().unwrap();
+        assert!(err.to_string().contains("exceeds"), "{}", err);
+    }
+
+    #[test]
+    fn test_detection_is_memoized() {
+        let detection = Detection::default();
+        let calls = std::cell::Cell::new(0);
+        let detect = |_: &WindowManager| {
+            calls.set(calls.get() + 1);
+            Ok(vec![MonitorInfo::named("eDP-1")])
+        };
+
+        let first = detection.info_with(&WindowManager::Niri, detect).unwrap();
+        let second = detection.info_with(&WindowManager::Niri, detect).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(detection.monitors(&WindowManager::Niri).unwrap(), ["eDP-1"]);
+        assert_eq!(calls.get(), 1);
     }
 
     #[test]
@@ -179,19 +1491,61 @@ Monitor HDMI-A-1 (ID 1):
         let configured = vec!["eDP-1".to_string(), "HDMI-1".to_string()];
         let connected = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
 
-        let matches = find_matches(&configured, &connected);
+        let matches = find_matches(&configured, &connected, false);
         assert_eq!(matches.len(), 1);
         assert!(matches.contains(&"eDP-1".to_string()));
     }
 
+    #[test]
+    fn test_case_insensitive_match() {
+        let configured = vec!["hdmi-a-1".to_string(), "eDP-1".to_string()];
+        let connected = vec!["HDMI-A-1".to_string(), "edp-1".to_string()];
+
+        assert!(find_matches(&configured, &connected, false).is_empty());
+        assert!(!lists_match(&configured, &connected, false));
+
+        // Connected casing is preserved for writing `output`
+        assert_eq!(
+            find_matches(&configured, &connected, true),
+            vec!["HDMI-A-1", "edp-1"]
+        );
+        assert!(lists_match(&configured, &connected, true));
+        assert_eq!(
+            find_monitor(&connected, "hdmi-a-1", true),
+            Some(&"HDMI-A-1".to_string())
+        );
+    }
+
     #[test]
     fn test_lists_match() {
         let list1 = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
         let list2 = vec!["HDMI-A-1".to_string(), "eDP-1".to_string()];
 
-        assert!(lists_match(&list1, &list2));
+        assert!(lists_match(&list1, &list2, false));
 
         let list3 = vec!["eDP-1".to_string()];
-        assert!(!lists_match(&list1, &list3));
+        assert!(!lists_match(&list1, &list3, false));
+    }
+
+    #[test]
+    fn test_send_signal_terminates_process() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        assert!(send_signal(child.id(), Signal::SIGTERM));
+        let status = child.wait().unwrap();
+        assert_eq!(status.signal(), Some(Signal::SIGTERM as i32));
+    }
+
+    #[test]
+    fn test_pids_from_proc() {
+        let dir = tempfile::tempdir().unwrap();
+        for (pid, comm) in [("12", "waybar\n"), ("7", "waybar\n"), ("30", "niri\n")] {
+            std::fs::create_dir(dir.path().join(pid)).unwrap();
+            std::fs::write(dir.path().join(pid).join("comm"), comm).unwrap();
+        }
+        std::fs::create_dir(dir.path().join("self")).unwrap();
+
+        assert_eq!(pids_from_proc(dir.path(), "waybar").unwrap(), vec![7, 12]);
     }
 }
@@ -0,0 +1,128 @@
+use crate::config::NotificationSettings;
+use anyhow::{Context, Result, anyhow};
+use notify_rust::{Notification, Timeout, Urgency};
+
+/// Desktop notifications, short-circuited when disabled via `--no-notify`
+/// or `[behavior] notifications = false`.
+pub struct Notifier {
+    enabled: bool,
+    timeout_ms: u32,
+    urgency: Option<Urgency>,
+}
+
+impl Notifier {
+    pub fn new(enabled: bool, settings: &NotificationSettings) -> Result<Self> {
+        let urgency = settings
+            .urgency
+            .as_deref()
+            .map(|u| {
+                Urgency::try_from(u).map_err(|_| {
+                    anyhow!(
+                        "Invalid [notifications] urgency '{}' (expected low, normal or critical)",
+                        u
+                    )
+                })
+            })
+            .transpose()?;
+
+        Ok(Notifier {
+            enabled,
+            timeout_ms: settings.timeout_ms,
+            urgency,
+        })
+    }
+
+    pub fn config_diff(&self) -> Result<bool> {
+        self.send(|| {
+            self.config_diff_notification()
+                .show()
+                .context("Error sending notification")?;
+            Ok(())
+        })
+    }
+
+    /// A supervised bar kept dying and is no longer restarted
+    pub fn instance_failed(&self, monitor: &str, restarts: u32) -> Result<bool> {
+        self.send(|| {
+            self.notification(&format!(
+                "Waybar on {} keeps exiting and was not restarted again after {} attempts.",
+                monitor, restarts
+            ))
+            .show()
+            .context("Error sending notification")?;
+            Ok(())
+        })
+    }
+
+    fn config_diff_notification(&self) -> Notification {
+        self.notification("There are configuration differences. Run 'waybar-manager check' from the terminal to synchronize changes.")
+    }
+
+    fn notification(&self, body: &str) -> Notification {
+        let mut notification = Notification::new();
+        notification
+            .summary("Omynix Waybar Manager")
+            .body(body)
+            .icon("dialog-warning")
+            .timeout(Timeout::Milliseconds(self.timeout_ms));
+        if let Some(urgency) = self.urgency {
+            notification.urgency(urgency);
+        }
+        notification
+    }
+
+    /// Run `show` unless notifications are disabled; returns whether it ran
+    fn send(&self, show: impl FnOnce() -> Result<()>) -> Result<bool> {
+        if !self.enabled {
+            return Ok(false);
+        }
+
+        show()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_uses_configured_timeout_and_urgency() {
+        let defaults = Notifier::new(true, &NotificationSettings::default()).unwrap();
+        let notification = defaults.config_diff_notification();
+        assert_eq!(notification.timeout, Timeout::Milliseconds(8000));
+        assert!(notification.hints.is_empty());
+
+        let settings = NotificationSettings {
+            timeout_ms: 0,
+            urgency: Some("critical".to_string()),
+        };
+        let notification = Notifier::new(true, &settings)
+            .unwrap()
+            .config_diff_notification();
+        assert_eq!(notification.timeout, Timeout::Milliseconds(0));
+        assert!(
+            notification
+                .hints
+                .contains(&notify_rust::Hint::Urgency(Urgency::Critical))
+        );
+
+        let invalid = NotificationSettings {
+            urgency: Some("urgent".to_string()),
+            ..NotificationSettings::default()
+        };
+        assert!(Notifier::new(true, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_disabled_notifier_skips_send() {
+        let notifier = Notifier::new(false, &NotificationSettings::default()).unwrap();
+        let sent = notifier
+            .send(|| panic!("notification should be skipped"))
+            .unwrap();
+        assert!(!sent);
+
+        let notifier = Notifier::new(true, &NotificationSettings::default()).unwrap();
+        assert!(notifier.send(|| Ok(())).unwrap());
+    }
+}
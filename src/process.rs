@@ -0,0 +1,149 @@
+use nix::sys::signal::kill;
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::Pid;
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often `wait_and_check_alive` looks at the watched processes
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A process that exited during a grace period
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadInstance {
+    pub pid: u32,
+    /// Exit code, known only for our own children
+    pub exit_code: Option<i32>,
+    /// Why it ended (e.g. "exited with status 1"), when known
+    pub reason: Option<String>,
+}
+
+impl DeadInstance {
+    /// Exited on its own with status 0
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Whether `pid` is running; a zombie waiting to be reaped counts as dead
+pub fn is_alive(pid: u32) -> bool {
+    if kill(Pid::from_raw(pid as i32), None).is_err() {
+        return false;
+    }
+
+    // `/proc/<pid>/stat` is `pid (comm) state ...`; comm may contain spaces
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .is_none_or(|state| state != "Z"),
+        Err(_) => true,
+    }
+}
+
+/// Watch `pids` for `grace` and return the ones that died meanwhile,
+/// returning early once all of them have. Our own children are reaped, so
+/// their exit status is reported.
+pub fn wait_and_check_alive(pids: &[u32], grace: Duration) -> Vec<DeadInstance> {
+    let deadline = Instant::now() + grace;
+    let mut dead: Vec<DeadInstance> = Vec::new();
+
+    loop {
+        for &pid in pids {
+            if dead.iter().any(|d| d.pid == pid) {
+                continue;
+            }
+            // A child exiting right after `reap` looked is a zombie by the
+            // time `is_alive` does, so reap it again for its status
+            if let Some(instance) = reap(pid).or_else(|| {
+                (!is_alive(pid)).then(|| {
+                    reap(pid).unwrap_or(DeadInstance {
+                        pid,
+                        exit_code: None,
+                        reason: None,
+                    })
+                })
+            }) {
+                dead.push(instance);
+            }
+        }
+
+        if dead.len() == pids.len() || Instant::now() >= deadline {
+            return dead;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - Instant::now()));
+    }
+}
+
+/// Collect the exit status of `pid` if it is a child of ours that exited
+fn reap(pid: u32) -> Option<DeadInstance> {
+    let dead = |exit_code, reason| {
+        Some(DeadInstance {
+            pid,
+            exit_code,
+            reason: Some(reason),
+        })
+    };
+
+    match waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)) {
+        Ok(WaitStatus::Exited(_, code)) => dead(Some(code), format!("exited with status {}", code)),
+        Ok(WaitStatus::Signaled(_, signal, _)) => dead(None, format!("killed by {}", signal)),
+        // Still running, or not our child (ECHILD)
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_short_lived_child_is_reported_dead() {
+        // Reaped by wait_and_check_alive itself
+        let short = Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap()
+            .id();
+        let mut long = Command::new("sleep").arg("5").spawn().unwrap();
+
+        let dead = wait_and_check_alive(&[short, long.id()], Duration::from_millis(500));
+        assert_eq!(
+            dead,
+            vec![DeadInstance {
+                pid: short,
+                exit_code: Some(3),
+                reason: Some("exited with status 3".to_string()),
+            }]
+        );
+        assert!(is_alive(long.id()));
+
+        long.kill().unwrap();
+        long.wait().unwrap();
+    }
+
+    #[test]
+    fn test_returns_early_once_everything_died() {
+        let child = Command::new("true").spawn().unwrap().id();
+
+        let started = Instant::now();
+        let dead = wait_and_check_alive(&[child], Duration::from_secs(5));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(dead[0].succeeded());
+    }
+
+    #[test]
+    fn test_killed_child_is_reaped() {
+        let child = Command::new("sleep").arg("5").spawn().unwrap().id();
+        kill(
+            Pid::from_raw(child as i32),
+            nix::sys::signal::Signal::SIGKILL,
+        )
+        .unwrap();
+
+        let dead = wait_and_check_alive(&[child], Duration::from_secs(2));
+        assert_eq!(dead[0].reason.as_deref(), Some("killed by SIGKILL"));
+        assert!(!dead[0].succeeded());
+    }
+}
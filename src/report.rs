@@ -0,0 +1,135 @@
+use crate::config::Behavior;
+use crate::templates::TemplateType;
+use crate::window_manager::WindowManager;
+
+/// Outcome of a launch: `main` formats it for the terminal, embedders can
+/// consume the fields directly instead of scraping stdout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchReport {
+    pub wm: WindowManager,
+    /// Monitors that were meant to get a bar
+    pub monitors: Vec<String>,
+    /// Template assigned to each monitor, in launch order
+    pub assignments: Vec<(String, TemplateType)>,
+    pub regenerated: bool,
+    /// Why the configs were regenerated, or why the launch was skipped
+    pub reason: Option<String>,
+    pub launched_pids: Vec<u32>,
+    pub warnings: Vec<String>,
+}
+
+impl LaunchReport {
+    pub fn new(wm: WindowManager) -> Self {
+        LaunchReport {
+            wm,
+            monitors: Vec::new(),
+            assignments: Vec::new(),
+            regenerated: false,
+            reason: None,
+            launched_pids: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// One-line summary, e.g. `waybar-manager: niri, 2 monitors, regenerated=false, launched=2`
+    pub fn summary(&self) -> String {
+        format!(
+            "waybar-manager: {}, {} monitor{}, regenerated={}, launched={}",
+            self.wm.as_str(),
+            self.monitors.len(),
+            if self.monitors.len() == 1 { "" } else { "s" },
+            self.regenerated,
+            self.launched_pids.len()
+        )
+    }
+}
+
+/// The report of a launch aborted to keep the running bars, because fewer
+/// monitors than `min_monitors` were detected; `None` lets the launch go
+/// ahead. `waybar_running` is only asked when below the minimum.
+pub fn below_min_monitors(
+    wm: WindowManager,
+    behavior: &Behavior,
+    connected: &[String],
+    waybar_running: impl FnOnce() -> bool,
+) -> Option<LaunchReport> {
+    if !behavior.below_min_monitors(connected.len()) || !waybar_running() {
+        return None;
+    }
+
+    let mut report = LaunchReport::new(wm);
+    report.monitors = connected.to_vec();
+    report.reason = Some("fewer monitors than min_monitors".to_string());
+    report
+        .warnings
+        .push("fewer monitors than min_monitors detected".to_string());
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_for_known_run() {
+        let mut report = LaunchReport::new(WindowManager::Niri);
+        report.monitors = vec!["HDMI-A-1".to_string(), "eDP-1".to_string()];
+        report.assignments = vec![
+            ("HDMI-A-1".to_string(), TemplateType::Full),
+            ("eDP-1".to_string(), TemplateType::Simple),
+        ];
+        report.regenerated = true;
+        report.reason = Some("template changed".to_string());
+        report.launched_pids = vec![4242, 4243];
+
+        assert_eq!(
+            report.summary(),
+            "waybar-manager: niri, 2 monitors, regenerated=true, launched=2"
+        );
+    }
+
+    #[test]
+    fn test_summary_for_skipped_run() {
+        let mut report = LaunchReport::new(WindowManager::Hyprland);
+        report.reason = Some("fewer monitors than min_monitors".to_string());
+
+        assert_eq!(
+            report.summary(),
+            "waybar-manager: hyprland, 0 monitors, regenerated=false, launched=0"
+        );
+    }
+
+    #[test]
+    fn test_oneline_summary_for_cached_run() {
+        let mut report = LaunchReport::new(WindowManager::Sway);
+        report.monitors = vec!["DP-1".to_string()];
+        report.launched_pids = vec![100];
+
+        assert_eq!(
+            report.summary(),
+            "waybar-manager: sway, 1 monitor, regenerated=false, launched=1"
+        );
+    }
+
+    #[test]
+    fn test_below_min_monitors_aborts_the_relaunch() {
+        let behavior: Behavior = toml::from_str("min_monitors = 2").unwrap();
+        let one = vec!["eDP-1".to_string()];
+
+        let report = below_min_monitors(WindowManager::Niri, &behavior, &one, || true).unwrap();
+        assert!(report.launched_pids.is_empty());
+        assert!(!report.regenerated);
+        assert_eq!(report.monitors, one);
+        assert_eq!(
+            report.reason.as_deref(),
+            Some("fewer monitors than min_monitors")
+        );
+
+        // Nothing to keep, so the bars are launched on what is there
+        assert!(below_min_monitors(WindowManager::Niri, &behavior, &one, || false).is_none());
+
+        let two = vec!["eDP-1".to_string(), "DP-1".to_string()];
+        let running = || panic!("waybar is only looked for below the minimum");
+        assert!(below_min_monitors(WindowManager::Niri, &behavior, &two, running).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// A running waybar process and the bars it serves
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaybarInstance {
+    pub pid: u32,
+    /// Config passed with `-c`/`--config`, if any
+    pub config: Option<String>,
+    /// Outputs named in that config
+    pub monitors: Vec<String>,
+}
+
+/// Snapshot printed by `status`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+    pub wm: String,
+    pub instances: Vec<WaybarInstance>,
+    /// When the cached configs were generated (unix seconds)
+    pub cache_timestamp: Option<i64>,
+    /// Why a launch would regenerate the configs right now, if it would
+    pub regenerate_reason: Option<String>,
+}
+
+/// Mismatch between the bars a launch meant to start and what is running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// An expected monitor has no bar
+    Missing(String),
+    /// Several instances serve the same monitor
+    Duplicate { monitor: String, pids: Vec<u32> },
+    /// A bar on a monitor that should not have one
+    Unexpected { monitor: String, pids: Vec<u32> },
+    /// An instance whose config could not be attributed to any monitor
+    Unknown { pid: u32 },
+}
+
+impl Discrepancy {
+    /// Instances that would have to stop to resolve this
+    pub fn extra_pids(&self, keep: &[u32]) -> Vec<u32> {
+        match self {
+            Discrepancy::Missing(_) => Vec::new(),
+            Discrepancy::Duplicate { pids, .. } => {
+                // Keep one bar: the one we launched if any, else the oldest
+                let kept = pids
+                    .iter()
+                    .find(|pid| keep.contains(pid))
+                    .or(pids.iter().min())
+                    .copied();
+                pids.iter()
+                    .filter(|pid| Some(**pid) != kept)
+                    .copied()
+                    .collect()
+            }
+            Discrepancy::Unexpected { pids, .. } => pids.clone(),
+            Discrepancy::Unknown { pid } => vec![*pid],
+        }
+    }
+}
+
+impl std::fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |pids: &[u32]| {
+            pids.iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Discrepancy::Missing(monitor) => write!(f, "{} has no bar", monitor),
+            Discrepancy::Duplicate { monitor, pids } => write!(
+                f,
+                "{} has {} bars (PIDs {})",
+                monitor,
+                pids.len(),
+                list(pids)
+            ),
+            Discrepancy::Unexpected { monitor, pids } => {
+                write!(f, "unexpected bar on {} (PIDs {})", monitor, list(pids))
+            }
+            Discrepancy::Unknown { pid } => {
+                write!(f, "waybar {} runs a config for no known monitor", pid)
+            }
+        }
+    }
+}
+
+/// Check that every `expected` monitor has exactly one bar among
+/// `instances` and that no other bars are running
+pub fn verify_bars(
+    expected: &[String],
+    instances: &[WaybarInstance],
+    case_insensitive: bool,
+) -> Vec<Discrepancy> {
+    let mut discrepancies = Vec::new();
+    let pids_for = |monitor: &str| -> Vec<u32> {
+        instances
+            .iter()
+            .filter(|instance| {
+                instance
+                    .monitors
+                    .iter()
+                    .any(|m| crate::monitor::names_equal(m, monitor, case_insensitive))
+            })
+            .map(|instance| instance.pid)
+            .collect()
+    };
+
+    for monitor in expected {
+        let pids = pids_for(monitor);
+        match pids.len() {
+            0 => discrepancies.push(Discrepancy::Missing(monitor.clone())),
+            1 => {}
+            _ => discrepancies.push(Discrepancy::Duplicate {
+                monitor: monitor.clone(),
+                pids,
+            }),
+        }
+    }
+
+    let mut others: Vec<&String> = instances
+        .iter()
+        .flat_map(|instance| &instance.monitors)
+        .filter(|m| crate::monitor::find_monitor(expected, m, case_insensitive).is_none())
+        .collect();
+    others.sort();
+    others.dedup();
+    for monitor in others {
+        discrepancies.push(Discrepancy::Unexpected {
+            monitor: monitor.clone(),
+            pids: pids_for(monitor),
+        });
+    }
+
+    for instance in instances.iter().filter(|i| i.monitors.is_empty()) {
+        discrepancies.push(Discrepancy::Unknown { pid: instance.pid });
+    }
+
+    discrepancies
+}
+
+/// Running waybar instances, with their configs read from `/proc`
+pub fn running_instances() -> Result<Vec<WaybarInstance>> {
+    Ok(crate::monitor::waybar_pids()?
+        .into_iter()
+        .map(|pid| {
+            let config = fs::read(format!("/proc/{}/cmdline", pid))
+                .ok()
+                .and_then(|cmdline| config_arg(&cmdline));
+            let monitors = config
+                .as_deref()
+                .map(|config| config_outputs(Path::new(config)))
+                .unwrap_or_default();
+            WaybarInstance {
+                pid,
+                config,
+                monitors,
+            }
+        })
+        .collect())
+}
+
+/// Value of `-c <path>`, `--config <path>` or `--config=<path>` in a
+/// NUL-separated `/proc/<pid>/cmdline`
+fn config_arg(cmdline: &[u8]) -> Option<String> {
+    let args: Vec<String> = cmdline
+        .split(|byte| *byte == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == "-c" || arg == "--config" {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix("--config=").map(str::to_string)
+        }
+    })
+}
+
+/// `output` of every bar in a waybar config (one object or an array of them)
+fn config_outputs(path: &Path) -> Vec<String> {
+    let Some(config) = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+    else {
+        return Vec::new();
+    };
+
+    let bars = match config {
+        Value::Array(bars) => bars,
+        bar => vec![bar],
+    };
+    bars.iter()
+        .filter_map(|bar| bar["output"].as_str().map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_arg_from_cmdline() {
+        assert_eq!(
+            config_arg(b"waybar\0-c\0/tmp/niri_DP-1_full.json\0-s\0/tmp/style.css\0"),
+            Some("/tmp/niri_DP-1_full.json".to_string())
+        );
+        assert_eq!(
+            config_arg(b"waybar\0--config=/tmp/a.json\0"),
+            Some("/tmp/a.json".to_string())
+        );
+        assert_eq!(config_arg(b"waybar\0"), None);
+    }
+
+    fn instance(pid: u32, monitors: &[&str]) -> WaybarInstance {
+        WaybarInstance {
+            pid,
+            config: None,
+            monitors: monitors.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_verify_bars() {
+        let expected = vec!["DP-1".to_string(), "eDP-1".to_string()];
+
+        let healthy = [instance(10, &["DP-1"]), instance(11, &["eDP-1"])];
+        assert!(verify_bars(&expected, &healthy, false).is_empty());
+
+        // One combined instance serving both monitors is fine too
+        assert!(verify_bars(&expected, &[instance(12, &["DP-1", "eDP-1"])], false).is_empty());
+
+        let messy = [
+            instance(10, &["DP-1"]),
+            instance(20, &["DP-1"]),
+            instance(21, &["HDMI-A-1"]),
+            instance(22, &[]),
+        ];
+        let found = verify_bars(&expected, &messy, false);
+        assert_eq!(
+            found,
+            vec![
+                Discrepancy::Duplicate {
+                    monitor: "DP-1".to_string(),
+                    pids: vec![10, 20],
+                },
+                Discrepancy::Missing("eDP-1".to_string()),
+                Discrepancy::Unexpected {
+                    monitor: "HDMI-A-1".to_string(),
+                    pids: vec![21],
+                },
+                Discrepancy::Unknown { pid: 22 },
+            ]
+        );
+        assert_eq!(found[0].to_string(), "DP-1 has 2 bars (PIDs 10, 20)");
+
+        // Auto-correction keeps the bar this launch started
+        assert_eq!(found[0].extra_pids(&[20]), vec![10]);
+        assert_eq!(found[0].extra_pids(&[]), vec![20]);
+        assert!(found[1].extra_pids(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_config_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let single = dir.path().join("single.json");
+        fs::write(&single, r#"{"output": "DP-1", "layer": "top"}"#).unwrap();
+        let combined = dir.path().join("combined.json");
+        fs::write(&combined, r#"[{"output": "DP-1"}, {"output": "eDP-1"}]"#).unwrap();
+
+        assert_eq!(config_outputs(&single), vec!["DP-1"]);
+        assert_eq!(config_outputs(&combined), vec!["DP-1", "eDP-1"]);
+        assert!(config_outputs(&dir.path().join("missing.json")).is_empty());
+    }
+}
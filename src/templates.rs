@@ -1,12 +1,14 @@
-use crate::config::Config;
+use crate::config::{Config, expand_home};
+use crate::process::DeadInstance;
+use crate::say;
 use crate::window_manager::WindowManager;
 use anyhow::{Context, Result};
 use colored::*;
 use serde_json::Value;
-use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct TemplateConfig {
@@ -22,138 +24,576 @@ pub enum TemplateType {
 }
 
 impl TemplateType {
+    /// Parse a template name as written in the config (`full`, `simple`,
+    /// `custom:<name>` or a bare custom name)
+    pub fn from_name(name: &str) -> Self {
+        let name = name.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "full" => TemplateType::Full,
+            "simple" => TemplateType::Simple,
+            _ => TemplateType::Custom(name.strip_prefix("custom:").unwrap_or(name).to_string()),
+        }
+    }
+
+    /// Name as written in configs and generated filenames
+    pub fn name(&self) -> &str {
+        match self {
+            TemplateType::Full => "full",
+            TemplateType::Simple => "simple",
+            TemplateType::Custom(name) => name.as_str(),
+        }
+    }
+
     fn from_comment(comment: &str) -> Option<Self> {
         if comment.contains("TPL:FULL") {
             Some(TemplateType::Full)
         } else if comment.contains("TPL:SIMPLE") {
             Some(TemplateType::Simple)
-        } else if let Some(custom) = comment.strip_prefix("TPL:") {
-            Some(TemplateType::Custom(custom.trim().to_string()))
         } else {
-            None
+            comment
+                .trim_start_matches('/')
+                .trim_start()
+                .strip_prefix("TPL:")
+                .map(|custom| TemplateType::Custom(custom.trim().to_string()))
         }
     }
 }
 
-pub fn get_templates_path(wm: &WindowManager) -> PathBuf {
-    let home = dirs::home_dir().unwrap();
-    home.join(".config/waybar/templates")
-        .join(format!("{}.jsonc", wm.as_str()))
+pub fn get_theme_dir(theme: &str) -> PathBuf {
+    get_waybar_dir().join("themes").join(theme)
+}
+
+/// Waybar's config directory: `$XDG_CONFIG_HOME/waybar`, or
+/// `~/.config/waybar` when it is not set
+pub fn get_waybar_dir() -> PathBuf {
+    waybar_dir(dirs::config_dir().as_deref(), &dirs::home_dir().unwrap())
+}
+
+/// `waybar` under the user's config directory, `~/.config` without one
+fn waybar_dir(config_dir: Option<&Path>, home: &Path) -> PathBuf {
+    config_dir
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| home.join(".config"))
+        .join("waybar")
+}
+
+/// Template file for the window manager: the one `resolve_templates`
+/// picks, or when none parses the first that exists, so its error can be
+/// shown
+pub fn get_templates_path(
+    cfg: &Config,
+    wm: &WindowManager,
+    templates_dir: Option<&Path>,
+) -> PathBuf {
+    if let Ok(file) = resolve_templates(cfg, wm, templates_dir, false) {
+        return file.path;
+    }
+    let (candidates, usual) = get_template_candidates(cfg, wm, templates_dir);
+    candidates
+        .into_iter()
+        .find(|path| path.exists())
+        .unwrap_or(usual)
+}
+
+/// The template file of the window manager, read and parsed once
+#[derive(Debug)]
+pub struct TemplateFile {
+    pub path: PathBuf,
+    pub content: String,
+    pub templates: Vec<TemplateConfig>,
+    /// Candidates tried before `path` that exist but do not parse
+    pub skipped: Vec<PathBuf>,
+}
+
+impl TemplateFile {
+    /// Template types the file defines
+    pub fn types(&self) -> Vec<TemplateType> {
+        self.templates
+            .iter()
+            .map(|t| t.template_type.clone())
+            .collect()
+    }
+
+    /// Directory `include` paths of the templates are relative to
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+/// The first of `get_template_candidates` that exists and parses; with
+/// `allow_empty`, a file holding no templates counts as parsing
+pub fn resolve_templates(
+    cfg: &Config,
+    wm: &WindowManager,
+    templates_dir: Option<&Path>,
+    allow_empty: bool,
+) -> Result<TemplateFile> {
+    let (candidates, usual) = get_template_candidates(cfg, wm, templates_dir);
+    resolve_templates_in(&candidates, &usual, allow_empty)
+}
+
+/// With nothing usable, the error of the first file found is returned;
+/// with nothing at all, `usual` is named
+fn resolve_templates_in(
+    candidates: &[PathBuf],
+    usual: &Path,
+    allow_empty: bool,
+) -> Result<TemplateFile> {
+    let mut skipped = Vec::new();
+    let mut first_error = None;
+
+    for path in candidates.iter().filter(|path| path.exists()) {
+        let parsed = read_template(path).and_then(|content| {
+            parse_jsonc_templates(&content, allow_empty).map(|templates| (content, templates))
+        });
+        match parsed {
+            Ok((content, templates)) => {
+                return Ok(TemplateFile {
+                    path: path.clone(),
+                    content,
+                    templates,
+                    skipped,
+                });
+            }
+            Err(e) => {
+                first_error
+                    .get_or_insert(e.context(format!("Error in template file {}", path.display())));
+                skipped.push(path.clone());
+            }
+        }
+    }
+
+    Err(first_error
+        .unwrap_or_else(|| anyhow::anyhow!("No template file was found in: {}", usual.display())))
+}
+
+/// Content of the template file at `path`, without a leading BOM: re-saving
+/// a file with or without one changes neither parsing nor the cache
+pub fn read_template(path: &Path) -> Result<String> {
+    let content = fs::read_to_string(path)
+        .context(format!("Error reading template file {}", path.display()))?;
+    Ok(crate::jsonc::strip_bom(&content).to_string())
+}
+
+/// Every template file tried for the window manager, in order, and the
+/// usual one to name when none exists. `templates_dir` (`--templates-dir`)
+/// takes precedence over `[paths] templates_dir`.
+fn get_template_candidates(
+    cfg: &Config,
+    wm: &WindowManager,
+    templates_dir: Option<&Path>,
+) -> (Vec<PathBuf>, PathBuf) {
+    let waybar_dir = get_waybar_dir();
+    let templates_dir = match templates_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => self::templates_dir(
+            &dirs::home_dir().unwrap(),
+            &waybar_dir,
+            cfg.paths.templates_dir.as_deref(),
+        ),
+    };
+    let candidates = template_candidates(
+        &waybar_dir,
+        &templates_dir,
+        cfg.display.theme.as_deref(),
+        wm,
+    );
+    (
+        candidates,
+        templates_dir.join(format!("{}.jsonc", wm.as_str())),
+    )
+}
+
+/// Directory of the per-WM templates; `~/…` and relative paths are taken
+/// from the home directory
+fn templates_dir(home: &Path, waybar_dir: &Path, configured: Option<&str>) -> PathBuf {
+    match configured {
+        Some(configured) => expand_home(home, configured),
+        None => waybar_dir.join("templates"),
+    }
+}
+
+/// `<wm>.jsonc`, `<wm>.json` and `default.jsonc`, first in the active
+/// theme's directory, then in the templates directory
+fn template_candidates(
+    waybar_dir: &Path,
+    templates_dir: &Path,
+    theme: Option<&str>,
+    wm: &WindowManager,
+) -> Vec<PathBuf> {
+    let names = [
+        format!("{}.jsonc", wm.as_str()),
+        format!("{}.json", wm.as_str()),
+        "default.jsonc".to_string(),
+    ];
+
+    let mut dirs = Vec::new();
+    if let Some(theme) = theme {
+        dirs.push(waybar_dir.join("themes").join(theme));
+    }
+    dirs.push(templates_dir.to_path_buf());
+
+    dirs.iter()
+        .flat_map(|dir| names.iter().map(|name| dir.join(name)))
+        .collect()
+}
+
+/// Directory holding the generated configs
+pub fn get_generated_dir() -> PathBuf {
+    get_waybar_dir().join("generated")
 }
 
 pub fn get_generated_config_path(
+    cfg: &Config,
+    wm: &WindowManager,
+    monitor: &str,
+    template_type: &TemplateType,
+) -> PathBuf {
+    generated_config_path_in(&get_generated_dir(), cfg, wm, monitor, template_type)
+}
+
+pub fn generated_config_path_in(
+    dir: &Path,
+    cfg: &Config,
     wm: &WindowManager,
     monitor: &str,
     template_type: &TemplateType,
 ) -> PathBuf {
+    let type_str = cfg
+        .display
+        .file_names
+        .get(template_type.name())
+        .map(String::as_str)
+        .unwrap_or(template_type.name());
+
+    dir.join(format!("{}_{}_{}.json", wm.as_str(), monitor, type_str))
+}
+
+/// Rename configs generated by older versions as `<name>. json` (with a
+/// stray space) to `<name>.json`, stamping them with the generator marker.
+/// Those versions wrote no marker, so they are recognized by name alone.
+/// Returns how many files were renamed.
+pub fn migrate_legacy_file_names(dir: &Path) -> Result<usize> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(0);
+    };
+
+    let mut renamed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(stem) = name.strip_suffix(". json") else {
+            continue;
+        };
+        let target = dir.join(format!("{}.json", stem));
+        if !has_generated_name(&target) || (target.exists() && !is_generated_file(&target, &[])) {
+            continue;
+        }
+        fs::rename(entry.path(), &target).context(format!("Error renaming {}", name))?;
+        renamed += 1;
+
+        let parsed = fs::read_to_string(&target)
+            .ok()
+            .and_then(|content| crate::jsonc::strip_comments(&content).ok())
+            .and_then(|json| serde_json::from_str::<Value>(&json).ok());
+        if let Some(mut config) = parsed {
+            mark_generated(&mut config);
+            fs::write(&target, serde_json::to_string_pretty(&config)?)
+                .context(format!("Error writing {}", target.display()))?;
+        }
+    }
+
+    Ok(renamed)
+}
+
+/// Stylesheet passed to waybar: the active theme's when it provides one,
+/// then `[display] style_path`, then `~/.config/waybar/omynix_style.css`
+pub fn get_style_path(cfg: &Config) -> PathBuf {
     let home = dirs::home_dir().unwrap();
-    let type_str = match template_type {
-        TemplateType::Full => "full",
-        TemplateType::Simple => "simple",
-        TemplateType::Custom(name) => name.as_str(),
+    resolve_style_path(
+        &home,
+        &get_waybar_dir(),
+        cfg.display.theme.as_deref(),
+        cfg.display.style_path.as_deref(),
+    )
+}
+
+/// Base stylesheet for `monitor`'s bar: its `[display] monitor_styles`
+/// file, which must exist, or else `global`
+pub fn base_style_for(cfg: &Config, monitor: &str, global: &Path) -> Result<PathBuf> {
+    let home = dirs::home_dir().unwrap();
+    let Some(path) = monitor_style_override(&home, cfg, monitor) else {
+        return Ok(global.to_path_buf());
     };
 
-    home.join(".config/waybar/generated").join(format!(
-        "{}_{}_{}. json",
-        wm.as_str(),
-        monitor,
-        type_str
-    ))
+    if !path.exists() {
+        return Err(anyhow::anyhow!(
+            "The stylesheet for {} in [display] monitor_styles does not exist: {}",
+            monitor,
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
+/// `[display] monitor_styles` entry for `monitor`; `~/…` and relative
+/// paths are taken from the home directory
+fn monitor_style_override(home: &Path, cfg: &Config, monitor: &str) -> Option<PathBuf> {
+    cfg.display
+        .monitor_styles
+        .iter()
+        .find(|(name, _)| {
+            crate::monitor::names_equal(name, monitor, cfg.display.case_insensitive_match)
+        })
+        .map(|(_, path)| expand_home(home, path))
+}
+
+/// `[display] monitor_layers` entry for `monitor`
+fn monitor_layer(cfg: &Config, monitor: &str) -> Option<crate::config::Layer> {
+    cfg.display
+        .monitor_layers
+        .iter()
+        .find(|(name, _)| {
+            crate::monitor::names_equal(name, monitor, cfg.display.case_insensitive_match)
+        })
+        .map(|(_, layer)| *layer)
+}
+
+fn resolve_style_path(
+    home: &Path,
+    waybar_dir: &Path,
+    theme: Option<&str>,
+    configured: Option<&str>,
+) -> PathBuf {
+    if let Some(theme) = theme {
+        let themed = waybar_dir.join("themes").join(theme).join("style.css");
+        if themed.exists() {
+            return themed;
+        }
+    }
+
+    if let Some(configured) = configured {
+        return expand_home(home, configured);
+    }
+
+    waybar_dir.join("omynix_style.css")
+}
+
+/// Path of the single config used when `combined_config` is enabled
+pub fn get_combined_config_path(wm: &WindowManager) -> PathBuf {
+    get_generated_dir().join(format!("{}_combined.json", wm.as_str()))
+}
+
+/// Minimal bar used by `--allow-empty-templates` when the template file
+/// holds no templates, so bars still come up while it is being edited
+const FALLBACK_TEMPLATE: &str = r#"{
+    "layer": "top",
+    "position": "top",
+    "modules-left": ["clock"],
+    "modules-center": [],
+    "modules-right": []
+}"#;
+
+/// The built-in template, as both FULL and SIMPLE
+fn fallback_templates() -> Result<Vec<TemplateConfig>> {
+    let fallback: Value = serde_json::from_str(FALLBACK_TEMPLATE)?;
+    Ok([TemplateType::Full, TemplateType::Simple]
+        .into_iter()
+        .map(|template_type| TemplateConfig {
+            template_type,
+            config: fallback.clone(),
+        })
+        .collect())
 }
 
-pub fn load_templates(wm: &WindowManager) -> Result<Vec<TemplateConfig>> {
-    let template_path = get_templates_path(wm);
+/// Run the generate → validate pipeline on the built-in template and two
+/// simulated monitors, writing only into `dir`. Returns the files written.
+pub fn selftest(dir: &Path) -> Result<Vec<PathBuf>> {
+    let cfg = Config {
+        display: crate::config::Display {
+            preferred_monitor: "DP-1".to_string(),
+            mode: "multiple".to_string(),
+            ..Config::default().display
+        },
+        ..Config::default()
+    };
+    let wm = WindowManager::Niri;
+    let monitors = ["DP-1".to_string(), "eDP-1".to_string()];
+    let assignments = determine_config_assignments(&cfg, &monitors);
+
+    let mut written = Vec::new();
+    for (monitor, config) in render_with(&cfg, &fallback_templates()?, &monitors, dir)? {
+        let path = generated_config_path_in(
+            dir,
+            &cfg,
+            &wm,
+            &monitor,
+            assigned(&assignments, &monitor).unwrap(),
+        );
+        let mut config = config;
+        mark_generated(&mut config);
+        write_generated(&path, &serde_json::to_string_pretty(&config)?, None, &[])?;
+        written.push(path);
+    }
 
-    println!("Looking for templates in: {}", template_path.display());
+    // Read everything back as waybar would
+    for (path, monitor) in written.iter().zip(&monitors) {
+        let config: Value = serde_json::from_str(&fs::read_to_string(path)?)
+            .context(format!("{} is not valid JSON", path.display()))?;
+        if config["output"] != monitor.as_str() {
+            return Err(anyhow::anyhow!(
+                "{} has output {} instead of {}",
+                path.display(),
+                config["output"],
+                monitor
+            ));
+        }
+    }
 
-    if !template_path.exists() {
+    if written.len() != monitors.len() {
         return Err(anyhow::anyhow!(
-            "No template file was found in: {}",
-            template_path.display()
+            "Expected {} generated configs, got {}",
+            monitors.len(),
+            written.len()
         ));
     }
 
-    let content = fs::read_to_string(&template_path).context("Error reading template file")?;
+    Ok(written)
+}
+
+/// Say which template file is used, and which were passed over
+fn print_template_file(file: &TemplateFile) {
+    say!("Looking for templates in: {}", file.path.display());
+    for skipped in &file.skipped {
+        log::warn!(
+            "Skipping template file {}: it does not parse",
+            skipped.display()
+        );
+    }
+    log::info!("Using template file {}", file.path.display());
 
-    println!(
+    say!(
         "File contents (first 200 characters)):\n{}\n",
-        &content.chars().take(200).collect::<String>()
+        &file.content.chars().take(200).collect::<String>()
     );
+}
+
+/// Render every bar `cfg` would generate for `monitors`, in launch order,
+/// without writing anything, failing on the first assignment that cannot be
+/// rendered
+pub fn render_all(
+    cfg: &Config,
+    file: &TemplateFile,
+    monitors: &[String],
+) -> Result<Vec<(String, Value)>> {
+    render_with(cfg, &file.templates, monitors, file.dir())
+}
 
-    // Parse JSONC (JSON with comments)
-    let configs = parse_jsonc_templates(&content)?;
+fn render_with(
+    cfg: &Config,
+    templates: &[TemplateConfig],
+    monitors: &[String],
+    template_dir: &Path,
+) -> Result<Vec<(String, Value)>> {
+    let config_assignments = determine_config_assignments(cfg, monitors);
+
+    if cfg.display.combined_config {
+        let combined = build_combined_config(cfg, templates, &config_assignments, template_dir)?;
+        return Ok(vec![("combined".to_string(), combined)]);
+    }
 
-    Ok(configs)
+    config_assignments
+        .iter()
+        .map(|(monitor, template_type)| {
+            render_config(cfg, templates, monitor, template_type, template_dir)
+                .map(|config| (monitor.clone(), config))
+        })
+        .collect()
 }
 
-fn parse_jsonc_templates(content: &str) -> Result<Vec<TemplateConfig>> {
-    let mut templates = Vec::new();
+/// A `// TPL:` marker of a template file and the object it applies to
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateMarker {
+    /// 1-based line number
+    pub line: usize,
+    pub template_type: TemplateType,
+    /// Index of the JSON object the marker names, by position; `None` when
+    /// there are more markers than objects
+    pub object: Option<usize>,
+}
 
-    // Extract template markers
-    let mut template_types_in_order = Vec::new();
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("//") {
-            if let Some(tpl_type) = TemplateType::from_comment(trimmed) {
-                template_types_in_order.push(tpl_type);
+/// Every `// TPL:` marker in `content`, in order, with its line number
+fn marker_lines(content: &str) -> Vec<(usize, TemplateType)> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let trimmed = line.trim();
+            if !trimmed.starts_with("//") {
+                return None;
             }
-        }
-    }
+            TemplateType::from_comment(trimmed).map(|tpl_type| (i + 1, tpl_type))
+        })
+        .collect()
+}
 
+/// The template objects of a JSONC template file
+fn parse_template_objects(content: &str) -> Result<Vec<Value>> {
     // Clear comments
-    let mut result = String::new();
-    let mut chars = content.chars().peekable();
-
-    while let Some(ch) = chars.next() {
-        match ch {
-            '/' if chars.peek() == Some(&'/') => {
-                // Line comment - skip to the end of the line
-                chars.next(); // consume the second '/'
-                while let Some(c) = chars.next() {
-                    if c == '\n' {
-                        result.push('\n');
-                        break;
-                    }
-                }
-            }
-            '"' => {
-                // Within a string - keep everything including possible //
-                result.push(ch);
-                let mut escaped = false;
-                while let Some(c) = chars.next() {
-                    result.push(c);
-                    if escaped {
-                        escaped = false;
-                    } else if c == '\\' {
-                        escaped = true;
-                    } else if c == '"' {
-                        break;
-                    }
-                }
-            }
-            _ => {
-                result.push(ch);
-            }
-        }
-    }
+    let result = crate::jsonc::strip_comments(content).context("Error parsing template file")?;
+    let result = crate::jsonc::quote_bare_placeholders(&result);
 
-    // Parse the clean JSON
-    let json_array: Vec<Value> = serde_json::from_str(&result).context(format!(
+    // Parse the clean JSON (a file with everything commented out is empty)
+    if result.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(&result).context(format!(
         "Error parsing template file.\nFirst 300 characters of clean content:\n{}",
         &result.chars().take(300).collect::<String>()
-    ))?;
+    ))
+}
+
+/// Type of the object at `index` that has no marker of its own
+pub fn default_template_type(index: usize) -> TemplateType {
+    match index {
+        0 => TemplateType::Full,
+        1 => TemplateType::Simple,
+        _ => TemplateType::Custom(format!("template_{}", index)),
+    }
+}
+
+/// The markers of a template file with the object each one was matched to
+/// (the n-th marker names the n-th object), and the number of objects
+pub fn template_markers(content: &str) -> Result<(Vec<TemplateMarker>, usize)> {
+    let objects = parse_template_objects(content)?.len();
+    let markers = marker_lines(content)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (line, template_type))| TemplateMarker {
+            line,
+            template_type,
+            object: (i < objects).then_some(i),
+        })
+        .collect();
+    Ok((markers, objects))
+}
+
+fn parse_jsonc_templates(content: &str, allow_empty: bool) -> Result<Vec<TemplateConfig>> {
+    let mut templates = Vec::new();
+
+    // Extract template markers
+    let template_types_in_order: Vec<TemplateType> = marker_lines(content)
+        .into_iter()
+        .map(|(_, tpl_type)| tpl_type)
+        .collect();
+
+    let json_array = parse_template_objects(content)?;
 
     // Assign template types
     for (i, config) in json_array.into_iter().enumerate() {
-        let template_type = if i < template_types_in_order.len() {
-            template_types_in_order[i].clone()
-        } else {
-            match i {
-                0 => TemplateType::Full,
-                1 => TemplateType::Simple,
-                _ => TemplateType::Custom(format!("template_{}", i)),
-            }
-        };
+        let template_type = template_types_in_order
+            .get(i)
+            .cloned()
+            .unwrap_or_else(|| default_template_type(i));
 
         templates.push(TemplateConfig {
             template_type,
@@ -162,128 +602,2657 @@ fn parse_jsonc_templates(content: &str) -> Result<Vec<TemplateConfig>> {
     }
 
     if templates.is_empty() {
-        return Err(anyhow::anyhow!("No valid templates were found in the file"));
+        if !allow_empty {
+            return Err(anyhow::anyhow!("No valid templates were found in the file"));
+        }
+
+        println!(
+            "{}",
+            "⚠ No valid templates were found, using the built-in fallback template (--allow-empty-templates)"
+                .yellow()
+                .bold()
+        );
+        templates = fallback_templates()?;
     }
 
     Ok(templates)
 }
 
+/// Write the config of every monitor in `connected` (or the combined one)
+/// to the generated directory. `cached` are the files recorded in the
+/// cache, which may be overwritten even without the generator marker.
 pub fn generate_configs(
     cfg: &Config,
+    file: &TemplateFile,
     connected: &[String],
     wm: &WindowManager,
-    verbose: bool,
+    strict: bool,
+    cached: &[String],
 ) -> Result<()> {
-    let templates = load_templates(wm)?;
-
-    if verbose {
-        println!("Templates loaded: {}", templates.len());
-    }
+    print_template_file(file);
+    let templates = &file.templates;
+    log::debug!(
+        "Templates loaded: {} from {}",
+        templates.len(),
+        file.path.display()
+    );
 
     // Create directory of generated configs if it does not exist
-    let generated_dir = dirs::home_dir().unwrap().join(".config/waybar/generated");
+    let generated_dir = get_generated_dir();
     fs::create_dir_all(&generated_dir)?;
 
-    // Determine which configuration to use for each monitor
-    let config_assignments = determine_config_assignments(cfg, connected);
+    let file_mode = cfg.launch.file_mode()?;
+    // Geometry for `{{width}}`-style placeholders; without it only
+    // `{{monitor}}` is filled in
+    let geometry = &cfg.display.geometry;
 
-    for (monitor, template_type) in &config_assignments {
-        // Find the corresponding template
-        let template = templates
-            .iter()
-            .find(|t| &t.template_type == template_type)
-            .context(format!("No template was found for {:?}", template_type))?;
+    // Determine which configuration to use for each monitor
+    let mut config_assignments = determine_config_assignments(cfg, connected);
+    let template_dir = file.dir();
 
-        // Generate configuration with the configured output
-        let mut config = template.config.clone();
-        if let Some(obj) = config.as_object_mut() {
-            obj.insert("output".to_string(), Value::String(monitor.clone()));
+    if cfg.display.combined_config {
+        // One config holding every bar, launched by a single waybar process
+        let mut combined =
+            build_combined_config(cfg, templates, &config_assignments, template_dir)?;
+        substitute_variables(&mut combined, geometry);
+        let output_path = get_combined_config_path(wm);
+        keep_frozen_bars(&output_path, cfg, &mut combined);
+        if let Some(bars) = combined.as_array() {
+            for bar in bars {
+                let monitor = bar["output"].as_str().unwrap_or("?");
+                check_generated_config(monitor, bar, strict)?;
+                if let Some(template_type) = assigned(&config_assignments, monitor) {
+                    check_required_modules(cfg, monitor, template_type, bar)?;
+                }
+            }
         }
+        mark_generated(&mut combined);
+        write_generated(
+            &output_path,
+            &serde_json::to_string_pretty(&combined)?,
+            file_mode,
+            cached,
+        )?;
+        log::debug!(
+            "Generated combined config ({} bars) → {}",
+            config_assignments.len(),
+            output_path.display()
+        );
+
+        return Ok(());
+    }
+
+    skip_frozen(&generated_dir, cfg, wm, &mut config_assignments);
 
+    // Validate every bar before writing any, so --strict leaves no half-updated set
+    let mut rendered = Vec::new();
+    for (monitor, template_type) in &config_assignments {
+        let mut config = render_config(cfg, templates, monitor, template_type, template_dir)?;
+        substitute_variables(&mut config, geometry);
+        check_generated_config(monitor, &config, strict)?;
+        check_required_modules(cfg, monitor, template_type, &config)?;
+        rendered.push((monitor, template_type, config));
+    }
+
+    for (monitor, template_type, mut config) in rendered {
         // Save generated settings
-        let output_path = get_generated_config_path(wm, monitor, template_type);
+        let output_path = get_generated_config_path(cfg, wm, monitor, template_type);
+        mark_generated(&mut config);
         let json_str = serde_json::to_string_pretty(&config)?;
-        fs::write(&output_path, json_str)?;
-
-        if verbose {
-            println!(
-                "  {} Generated: {} → {:?}",
-                "✓".green(),
-                monitor.cyan(),
-                template_type
-            );
-        }
+        write_generated(&output_path, &json_str, file_mode, cached)?;
+        log::debug!(
+            "Generated: {} → {:?} ({})",
+            monitor,
+            template_type,
+            output_path.display()
+        );
     }
 
     Ok(())
 }
 
-fn determine_config_assignments(
+/// Drop `[display] frozen_monitors` from `assignments` so their configs in
+/// `dir` are left alone. A frozen monitor with no config to keep stays in,
+/// so it gets one generated.
+fn skip_frozen(
+    dir: &Path,
     cfg: &Config,
-    connected: &[String],
-) -> HashMap<String, TemplateType> {
-    let mut assignments = HashMap::new();
+    wm: &WindowManager,
+    assignments: &mut Vec<(String, TemplateType)>,
+) {
+    assignments.retain(|(monitor, template_type)| {
+        if !cfg.display.is_frozen(monitor) {
+            return true;
+        }
+        if frozen_config_in(dir, cfg, wm, monitor, template_type).is_some() {
+            return false;
+        }
 
-    if connected.len() == 1 {
-        // One monitor: always FULL
-        assignments.insert(connected[0].clone(), TemplateType::Full);
-    } else {
-        // Multiple monitors: FULL on the preferred one, SIMPLE on the others
-        let preferred = &cfg.display.preferred_monitor;
+        println!(
+            "{}",
+            format!(
+                "⚠ {} is frozen but has no generated config yet, generating it",
+                monitor
+            )
+            .yellow()
+        );
+        true
+    });
+}
 
-        for monitor in connected {
-            if monitor == preferred {
-                assignments.insert(monitor.clone(), TemplateType::Full);
-            } else {
-                assignments.insert(monitor.clone(), TemplateType::Simple);
-            }
-        }
+/// Config in `dir` a frozen `monitor` keeps using: the one for its
+/// assigned template, or else one generated for another template before
+/// the assignment changed
+fn frozen_config_in(
+    dir: &Path,
+    cfg: &Config,
+    wm: &WindowManager,
+    monitor: &str,
+    template_type: &TemplateType,
+) -> Option<PathBuf> {
+    let assigned = generated_config_path_in(dir, cfg, wm, monitor, template_type);
+    if assigned.exists() {
+        return Some(assigned);
     }
 
-    assignments
+    let prefix = format!("{}_{}_", wm.as_str(), monitor);
+    let mut found: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".json"))
+        })
+        .collect();
+    found.sort();
+    found.into_iter().next()
 }
 
-pub fn launch_waybar_instances(
+/// Config `monitor`'s bar is launched with: its generated config, or for a
+/// frozen monitor the config it kept
+pub fn monitor_config_path(
     cfg: &Config,
-    connected: &[String],
     wm: &WindowManager,
-    verbose: bool,
-) -> Result<()> {
-    let config_assignments = determine_config_assignments(cfg, connected);
-    let style_path = dirs::home_dir()
-        .unwrap()
-        .join(".config/waybar/omynix_style.css");
+    monitor: &str,
+    template_type: &TemplateType,
+) -> PathBuf {
+    cfg.display
+        .is_frozen(monitor)
+        .then(|| frozen_config_in(&get_generated_dir(), cfg, wm, monitor, template_type))
+        .flatten()
+        .unwrap_or_else(|| get_generated_config_path(cfg, wm, monitor, template_type))
+}
 
-    for (monitor, template_type) in &config_assignments {
-        let config_path = get_generated_config_path(wm, monitor, template_type);
+/// Put back the bars of frozen monitors from the combined config at
+/// `path`, as it was before this generation
+fn keep_frozen_bars(path: &Path, cfg: &Config, combined: &mut Value) {
+    let previous = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok());
+    let Some(Value::Array(previous)) = previous else {
+        return;
+    };
+    let Some(bars) = combined.as_array_mut() else {
+        return;
+    };
 
-        let type_str = match template_type {
-            TemplateType::Full => "FULL".green(),
-            TemplateType::Simple => "SIMPLE".blue(),
-            TemplateType::Custom(name) => name.yellow(),
+    for bar in bars {
+        let Some(monitor) = bar["output"].as_str().map(str::to_string) else {
+            continue;
         };
-
-        if verbose {
-            println!("Implement -- launch_waybar_instances()");
+        if !cfg.display.is_frozen(&monitor) {
+            continue;
+        }
+        match previous
+            .iter()
+            .find(|old| old["output"] == monitor.as_str())
+        {
+            Some(old) => *bar = old.clone(),
+            None => println!(
+                "{}",
+                format!(
+                    "⚠ {} is frozen but has no bar in {} yet, generating it",
+                    monitor,
+                    path.display()
+                )
+                .yellow()
+            ),
         }
+    }
+}
 
-        println!(
-            "  {} Starting waybar {} in: {}",
-            "→".cyan(),
-            type_str,
-            monitor.cyan()
-        );
+/// Top-level keys a bar config is expected to set
+const EXPECTED_BAR_KEYS: [&str; 4] = ["layer", "modules-left", "modules-center", "modules-right"];
 
-        Command::new("waybar")
-            .arg("-c")
-            .arg(&config_path)
-            .arg("-s")
-            .arg(&style_path)
-            .spawn()
-            .context("Error launching waybar")?;
+/// Problems waybar is likely to choke on in a generated bar config
+fn generated_config_problems(config: &Value) -> Vec<String> {
+    let Some(obj) = config.as_object() else {
+        return vec!["is not a JSON object".to_string()];
+    };
+
+    let missing: Vec<&str> = EXPECTED_BAR_KEYS
+        .into_iter()
+        .filter(|key| !obj.contains_key(*key))
+        .collect();
+    if missing.is_empty() {
+        Vec::new()
+    } else {
+        vec![format!("missing {}", missing.join(", "))]
+    }
+}
 
-        std::thread::sleep(std::time::Duration::from_millis(200));
+/// Warn about (or with `strict`, refuse) a questionable config for `monitor`
+fn check_generated_config(monitor: &str, config: &Value, strict: bool) -> Result<()> {
+    let problems = generated_config_problems(config);
+    if problems.is_empty() {
+        return Ok(());
     }
 
+    let message = format!("Generated config for {} {}", monitor, problems.join("; "));
+    if strict {
+        return Err(anyhow::anyhow!(message)).context("--strict: not launching");
+    }
+    println!("{}", format!("⚠ {}", message).yellow());
     Ok(())
 }
+
+/// Modules a template places in each section of its bar
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ModuleSummary {
+    pub template: String,
+    pub left: Vec<String>,
+    pub center: Vec<String>,
+    pub right: Vec<String>,
+}
+
+/// Module placements of every template in a template file
+pub fn module_summaries(templates: &[TemplateConfig]) -> Vec<ModuleSummary> {
+    let placed = |config: &Value, key: &str| -> Vec<String> {
+        config[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    };
+
+    templates
+        .iter()
+        .map(|template| ModuleSummary {
+            template: template.template_type.name().to_string(),
+            left: placed(&template.config, "modules-left"),
+            center: placed(&template.config, "modules-center"),
+            right: placed(&template.config, "modules-right"),
+        })
+        .collect()
+}
+
+/// `[display] required_modules` that no placement array
+/// (`modules-left`/`-center`/`-right`) of `config` references
+fn missing_required_modules<'a>(config: &Value, required: &'a [String]) -> Vec<&'a str> {
+    let placed: Vec<&str> = ["modules-left", "modules-center", "modules-right"]
+        .iter()
+        .filter_map(|key| config[*key].as_array())
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+
+    required
+        .iter()
+        .map(String::as_str)
+        .filter(|module| !placed.contains(module))
+        .collect()
+}
+
+/// Refuse a generated config that lost one of `[display] required_modules`
+fn check_required_modules(
+    cfg: &Config,
+    monitor: &str,
+    template_type: &TemplateType,
+    config: &Value,
+) -> Result<()> {
+    let missing = missing_required_modules(config, &cfg.display.required_modules);
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!(
+        "Generated config for {} (template '{}') is missing required modules: {}",
+        monitor,
+        template_type.name(),
+        missing.join(", ")
+    ))
+}
+
+/// The template for `template_type`. A file without a SIMPLE template
+/// still gets bars on every monitor: FULL is used instead, with a warning.
+/// Custom templates are named explicitly, so a missing one stays an error.
+fn find_template<'a>(
+    templates: &'a [TemplateConfig],
+    monitor: &str,
+    template_type: &TemplateType,
+) -> Result<&'a TemplateConfig> {
+    let find = |wanted: &TemplateType| templates.iter().find(|t| &t.template_type == wanted);
+    if let Some(template) = find(template_type) {
+        return Ok(template);
+    }
+
+    if *template_type == TemplateType::Simple
+        && let Some(full) = find(&TemplateType::Full)
+    {
+        println!(
+            "{}",
+            format!("⚠ No SIMPLE template, using FULL for {}", monitor).yellow()
+        );
+        return Ok(full);
+    }
+
+    Err(anyhow::anyhow!(
+        "No template was found for {:?}",
+        template_type
+    ))
+}
+
+/// Render the bar config for one monitor from its assigned template
+fn render_config(
+    cfg: &Config,
+    templates: &[TemplateConfig],
+    monitor: &str,
+    template_type: &TemplateType,
+    template_dir: &Path,
+) -> Result<Value> {
+    let template = find_template(templates, monitor, template_type)?;
+
+    // Generate configuration with the configured output
+    let mut config = template.config.clone();
+    if let Some(obj) = config.as_object_mut() {
+        obj.insert("output".to_string(), Value::String(monitor.to_string()));
+        if let Some(layer) = monitor_layer(cfg, monitor) {
+            obj.insert(
+                "layer".to_string(),
+                Value::String(layer.as_str().to_string()),
+            );
+        }
+    }
+
+    // Relative includes must keep pointing next to the template, not generated/
+    resolve_includes(&mut config, template_dir);
+
+    apply_module_overrides(&mut config, &cfg.modules)?;
+
+    Ok(config)
+}
+
+/// Fill in `{{monitor}}`, `{{width}}`, `{{height}}`, `{{refresh}}` and
+/// `{{scale}}` in a rendered bar (or array of bars) for the monitor in its
+/// `output`. A value that is only a numeric placeholder becomes a number;
+/// unknown placeholders are left as they are.
+fn substitute_variables(config: &mut Value, geometry: &[crate::monitor::MonitorInfo]) {
+    if let Value::Array(bars) = config {
+        for bar in bars {
+            substitute_variables(bar, geometry);
+        }
+        return;
+    }
+
+    let Some(monitor) = config["output"].as_str().map(str::to_string) else {
+        return;
+    };
+    let mut values = vec![("monitor", Value::String(monitor.clone()))];
+    if let Some(info) = geometry
+        .iter()
+        .find(|info| info.name == monitor && info.has_geometry())
+    {
+        values.push(("width", info.width.into()));
+        values.push(("height", info.height.into()));
+        values.push(("refresh", info.refresh.into()));
+        values.push(("scale", info.scale.into()));
+    }
+
+    replace_placeholders(config, &values);
+}
+
+fn replace_placeholders(value: &mut Value, values: &[(&str, Value)]) {
+    match value {
+        Value::String(text) => {
+            for (name, replacement) in values {
+                let token = format!("{{{{{}}}}}", name);
+                if *text == token {
+                    *value = replacement.clone();
+                    return;
+                }
+                if text.contains(&token) {
+                    let replacement = match replacement {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    *text = text.replace(&token, &replacement);
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| replace_placeholders(item, values)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|item| replace_placeholders(item, values)),
+        _ => {}
+    }
+}
+
+/// Whether a bar config places `module` in any of its module lists
+pub fn references_module(config: &Value, module: &str) -> bool {
+    ["modules-left", "modules-center", "modules-right"]
+        .iter()
+        .filter_map(|key| config.get(key).and_then(Value::as_array))
+        .flatten()
+        .any(|m| m.as_str() == Some(module))
+}
+
+/// Merge the configured `[modules.<name>]` defaults into the module objects
+/// of a bar config. Keys already set by the template are kept unless the
+/// override sets `force = true`.
+fn apply_module_overrides(
+    config: &mut Value,
+    overrides: &std::collections::BTreeMap<String, toml::Table>,
+) -> Result<()> {
+    for (module, table) in overrides {
+        if !references_module(config, module) && config.get(module).is_none() {
+            continue;
+        }
+
+        let force = table
+            .get("force")
+            .and_then(toml::Value::as_bool)
+            .unwrap_or(false);
+
+        let Some(obj) = config.as_object_mut() else {
+            continue;
+        };
+        let target = obj
+            .entry(module.clone())
+            .or_insert_with(|| Value::Object(Default::default()));
+        let Some(target) = target.as_object_mut() else {
+            continue;
+        };
+
+        for (key, value) in table.iter().filter(|(key, _)| key.as_str() != "force") {
+            if force || !target.contains_key(key) {
+                let value = serde_json::to_value(value)
+                    .context(format!("Invalid value for modules.{}.{}", module, key))?;
+                target.insert(key.clone(), value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a single waybar config holding one bar definition per monitor
+fn build_combined_config(
+    cfg: &Config,
+    templates: &[TemplateConfig],
+    assignments: &[(String, TemplateType)],
+    template_dir: &Path,
+) -> Result<Value> {
+    let bars = launch_order(cfg, assignments)
+        .iter()
+        .map(|(monitor, template_type)| {
+            render_config(cfg, templates, monitor, template_type, template_dir)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Value::Array(bars))
+}
+
+/// Rewrite relative paths in waybar's `include` key (string or array) to
+/// absolute paths based on the template's directory.
+fn resolve_includes(config: &mut Value, base_dir: &Path) {
+    let resolve = |path: &mut Value| {
+        if let Value::String(p) = path
+            && !p.starts_with('/')
+            && !p.starts_with('~')
+            && !p.starts_with('$')
+        {
+            *p = base_dir.join(&*p).to_string_lossy().into_owned();
+        }
+    };
+
+    match config.get_mut("include") {
+        Some(Value::Array(paths)) => paths.iter_mut().for_each(resolve),
+        Some(path) => resolve(path),
+        None => {}
+    }
+}
+
+/// Template for each connected monitor, ordered with the preferred monitor
+/// first, then as listed in `available_monitors`, then as detected
+pub fn determine_config_assignments(
+    cfg: &Config,
+    connected: &[String],
+) -> Vec<(String, TemplateType)> {
+    let mut assignments = Vec::new();
+
+    if connected.len() == 1 {
+        // One monitor: always FULL
+        assignments.push((connected[0].clone(), TemplateType::Full));
+    } else {
+        // Multiple monitors: FULL on the preferred one, SIMPLE on the others
+        let preferred = &cfg.display.preferred_monitor;
+
+        // The built-in panel gets its own template; if it was the preferred
+        // one, FULL moves to the external monitors instead
+        let internal_template = cfg.display.internal_template.as_deref();
+        let externals_full = internal_template.is_some()
+            && crate::monitor::is_internal(preferred)
+            && connected.iter().any(|m| crate::monitor::is_internal(m));
+
+        for monitor in connected {
+            let is_internal = crate::monitor::is_internal(monitor);
+
+            if let Some(name) = internal_template
+                && is_internal
+            {
+                assignments.push((monitor.clone(), TemplateType::from_name(name)));
+            } else if externals_full
+                || crate::monitor::names_equal(
+                    monitor,
+                    preferred,
+                    cfg.display.case_insensitive_match,
+                )
+            {
+                assignments.push((monitor.clone(), TemplateType::Full));
+            } else {
+                assignments.push((monitor.clone(), TemplateType::Simple));
+            }
+        }
+    }
+
+    // `[display.template_rules]`: connector families first, then exact
+    // monitor names so they override their family
+    let rules = &cfg.display.template_rules;
+    for (monitor, template_type) in assignments.iter_mut() {
+        let by_name = rules
+            .iter()
+            .find(|(key, _)| {
+                crate::monitor::names_equal(key, monitor, cfg.display.case_insensitive_match)
+            })
+            .map(|(_, name)| name);
+        let by_family = rules.get(&crate::monitor::connector_family(monitor));
+
+        if let Some(name) = by_name.or(by_family) {
+            *template_type = TemplateType::from_name(name);
+        }
+    }
+
+    // Monitors standing in portrait get the vertical variant
+    if let Some(name) = &cfg.display.vertical_template {
+        for monitor in &cfg.display.portrait {
+            if let Some(connected) =
+                crate::monitor::find_monitor(connected, monitor, cfg.display.case_insensitive_match)
+            {
+                reassign(&mut assignments, connected, TemplateType::from_name(name));
+            }
+        }
+    }
+
+    // `[hooks] assign_command` output replaces the built-in rules
+    for (monitor, name) in &cfg.display.hook_assign {
+        if let Some(connected) =
+            crate::monitor::find_monitor(connected, monitor, cfg.display.case_insensitive_match)
+        {
+            reassign(&mut assignments, connected, TemplateType::from_name(name));
+        }
+    }
+
+    // Explicit `--assign` entries replace whatever the rules above chose
+    for (monitor, name) in &cfg.display.assign {
+        if let Some(connected) =
+            crate::monitor::find_monitor(connected, monitor, cfg.display.case_insensitive_match)
+        {
+            reassign(&mut assignments, connected, TemplateType::from_name(name));
+        }
+    }
+
+    let rank = |monitor: &str| {
+        let ci = cfg.display.case_insensitive_match;
+        if crate::monitor::names_equal(monitor, &cfg.display.preferred_monitor, ci) {
+            return 0;
+        }
+        let available = &cfg.display.available_monitors;
+        available
+            .iter()
+            .position(|m| crate::monitor::names_equal(m, monitor, ci))
+            .map_or(available.len() + 1, |i| i + 1)
+    };
+    // Stable, so monitors in neither list keep the detection order
+    assignments.sort_by_key(|(monitor, _)| rank(monitor));
+    assignments
+}
+
+/// Replace the template assigned to `monitor`
+fn reassign(
+    assignments: &mut [(String, TemplateType)],
+    monitor: &str,
+    template_type: TemplateType,
+) {
+    if let Some(entry) = assignments.iter_mut().find(|(m, _)| m == monitor) {
+        entry.1 = template_type;
+    }
+}
+
+/// The template `assignments` gives `monitor`
+pub fn assigned<'a>(
+    assignments: &'a [(String, TemplateType)],
+    monitor: &str,
+) -> Option<&'a TemplateType> {
+    assignments
+        .iter()
+        .find(|(m, _)| m == monitor)
+        .map(|(_, template_type)| template_type)
+}
+
+/// Check that every `--assign` entry names a connected monitor and a
+/// template present in `templates`
+pub fn validate_assignments(
+    cfg: &Config,
+    connected: &[String],
+    templates: &[TemplateType],
+) -> Result<()> {
+    for (monitor, name) in &cfg.display.assign {
+        if crate::monitor::find_monitor(connected, monitor, cfg.display.case_insensitive_match)
+            .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "--assign {}={}: monitor {} is not connected",
+                monitor,
+                name,
+                monitor
+            ));
+        }
+
+        let template_type = TemplateType::from_name(name);
+        if !templates.contains(&template_type) {
+            return Err(anyhow::anyhow!(
+                "--assign {}={}: no {:?} template in the template file",
+                monitor,
+                name,
+                template_type
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `[hooks] assign_command` with `connected` as a JSON array on stdin
+/// and parse the monitor → template object it prints
+pub fn run_assign_hook(
+    command: &str,
+    connected: &[String],
+) -> Result<std::collections::BTreeMap<String, String>> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context(format!("Error running assign_command '{}'", command))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its input may exit before reading it
+        stdin
+            .write_all(serde_json::to_string(connected)?.as_bytes())
+            .ok();
+    }
+
+    let output = child
+        .wait_with_output()
+        .context("Error waiting for assign_command")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "assign_command '{}' failed with {}",
+            command,
+            output.status
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout).context(format!(
+        "assign_command '{}' did not print a JSON object of monitor → template",
+        command
+    ))
+}
+
+/// Check that every hook assignment names a connected monitor and a
+/// template present in `templates`
+pub fn validate_hook_assignments(
+    cfg: &Config,
+    connected: &[String],
+    templates: &[TemplateType],
+) -> Result<()> {
+    for (monitor, name) in &cfg.display.hook_assign {
+        if crate::monitor::find_monitor(connected, monitor, cfg.display.case_insensitive_match)
+            .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "assign_command returned {}={}: monitor {} is not connected",
+                monitor,
+                name,
+                monitor
+            ));
+        }
+
+        let template_type = TemplateType::from_name(name);
+        if !templates.contains(&template_type) {
+            return Err(anyhow::anyhow!(
+                "assign_command returned {}={}: no {:?} template in the template file",
+                monitor,
+                name,
+                template_type
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Template types defined in a template file's content
+pub fn template_types(content: &str, allow_empty: bool) -> Result<Vec<TemplateType>> {
+    Ok(parse_jsonc_templates(content, allow_empty)?
+        .into_iter()
+        .map(|t| t.template_type)
+        .collect())
+}
+
+pub fn launch_waybar_instances(
+    cfg: &Config,
+    connected: &[String],
+    wm: &WindowManager,
+) -> Result<Vec<u32>> {
+    let config_assignments = determine_config_assignments(cfg, connected);
+    let style_path = get_style_path(cfg);
+
+    if let Some(configured) = &cfg.display.style_path
+        && !style_path.exists()
+    {
+        return Err(anyhow::anyhow!(
+            "The stylesheet configured in [display] style_path ({}) does not exist: {}",
+            configured,
+            style_path.display()
+        ));
+    }
+
+    if cfg.display.combined_config {
+        if !cfg.styles.is_empty() || !cfg.display.monitor_styles.is_empty() {
+            println!(
+                "{}",
+                "⚠ [styles.<monitor>] and monitor_styles are ignored with combined_config (one stylesheet for every bar)"
+                    .yellow()
+            );
+        }
+        say!(
+            "  {} Starting waybar with {} bars in: {}",
+            "→".cyan(),
+            config_assignments.len(),
+            connected.join(", ").cyan()
+        );
+
+        let log_path = log_path_for(cfg, "combined")?;
+        let child = waybar_command(
+            "waybar",
+            &get_combined_config_path(wm),
+            &style_path,
+            log_path.as_deref(),
+        )?
+        .spawn()
+        .context("Error launching waybar")?;
+
+        let children = vec![("combined".to_string(), child.id())];
+        check_early_exits(&early_exits(&children, EARLY_EXIT_GRACE), 1)?;
+        return Ok(vec![child.id()]);
+    }
+
+    let mut children = Vec::new();
+    let mut failed = Vec::new();
+    let stagger = Duration::from_millis(cfg.launch.launch_stagger_ms);
+    let started = std::time::Instant::now();
+
+    for (monitor, template_type) in &launch_order(cfg, &config_assignments) {
+        let config_path = monitor_config_path(cfg, wm, monitor, template_type);
+
+        let type_str = match template_type {
+            TemplateType::Full => "FULL".green(),
+            TemplateType::Simple => "SIMPLE".blue(),
+            TemplateType::Custom(name) => name.yellow(),
+        };
+
+        log::debug!(
+            "Launching waybar for {} with {}",
+            monitor,
+            config_path.display()
+        );
+
+        say!(
+            "  {} Starting waybar {} in: {}",
+            "→".cyan(),
+            type_str,
+            monitor.cyan()
+        );
+
+        let child = spawn_monitor_bar(cfg, wm, monitor, &config_path, &style_path)?;
+        // One bar at a time, so two never race for the same layer surface
+        match confirm_started(child.id(), monitor, stagger) {
+            None => children.push((monitor.clone(), child.id())),
+            Some(dead) if !dead.succeeded() => failed.push((monitor.clone(), dead)),
+            Some(_) => {}
+        }
+    }
+
+    failed.extend(early_exits(&children, EARLY_EXIT_GRACE));
+    check_early_exits(&failed, config_assignments.len())?;
+    log::info!(
+        "Launched {} bars in {} ms",
+        children.len(),
+        started.elapsed().as_millis()
+    );
+    Ok(children.iter().map(|(_, pid)| *pid).collect())
+}
+
+/// Give a freshly started bar `stagger` to come up; how it ended if it
+/// died meanwhile, `None` when it is still running
+fn confirm_started(pid: u32, monitor: &str, stagger: Duration) -> Option<DeadInstance> {
+    let dead = crate::process::wait_and_check_alive(&[pid], stagger).pop();
+    match &dead {
+        Some(instance) => log::debug!(
+            "waybar on {} exited during startup ({})",
+            monitor,
+            instance.reason.as_deref().unwrap_or("exited")
+        ),
+        None => log::debug!("waybar on {} is up", monitor),
+    }
+    dead
+}
+
+/// How long a freshly started waybar gets to reject its config before the
+/// launch is considered successful
+const EARLY_EXIT_GRACE: Duration = Duration::from_millis(300);
+
+/// The bars among `children` (monitor and pid) that exited with a failure
+/// within `grace`, typically because waybar rejected the generated config
+fn early_exits(children: &[(String, u32)], grace: Duration) -> Vec<(String, DeadInstance)> {
+    let pids: Vec<u32> = children.iter().map(|(_, pid)| *pid).collect();
+    let dead = crate::process::wait_and_check_alive(&pids, grace);
+
+    children
+        .iter()
+        .filter_map(|(monitor, pid)| {
+            let instance = dead.iter().find(|d| d.pid == *pid)?;
+            (!instance.succeeded()).then(|| (monitor.clone(), instance.clone()))
+        })
+        .collect()
+}
+
+/// Fail the launch of `total` bars if any of them is among `failed`,
+/// naming each one; the bars that stayed up keep running
+fn check_early_exits(failed: &[(String, DeadInstance)], total: usize) -> Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    for (monitor, instance) in failed {
+        println!(
+            "  {} waybar exited right away on {} ({})",
+            "✗".red(),
+            monitor.cyan(),
+            instance.reason.as_deref().unwrap_or("exited")
+        );
+    }
+    Err(anyhow::anyhow!(
+        "{} of {} bars failed to stay up ({}); check the templates, or run `launch --check-templates-against-waybar` to see waybar's error",
+        failed.len(),
+        total,
+        failed
+            .iter()
+            .map(|(monitor, _)| monitor.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+/// Start the bar of one of `monitors` again, with the template it gets
+/// alongside the others, leaving the other bars alone. The caller owns the
+/// child and must reap it once it exits.
+pub fn relaunch_instance(
+    cfg: &Config,
+    monitors: &[String],
+    monitor: &str,
+    wm: &WindowManager,
+) -> Result<Child> {
+    let config_assignments = determine_config_assignments(cfg, monitors);
+    let template_type = assigned(&config_assignments, monitor)
+        .ok_or_else(|| anyhow::anyhow!("{} is not one of the launched monitors", monitor))?;
+
+    let config_path = monitor_config_path(cfg, wm, monitor, template_type);
+    spawn_monitor_bar(cfg, wm, monitor, &config_path, &get_style_path(cfg))
+}
+
+fn spawn_monitor_bar(
+    cfg: &Config,
+    wm: &WindowManager,
+    monitor: &str,
+    config_path: &Path,
+    style_path: &Path,
+) -> Result<Child> {
+    let log_path = log_path_for(cfg, monitor)?;
+    let base = base_style_for(cfg, monitor, style_path)?;
+    let monitor_style = monitor_style_path(&get_generated_dir(), cfg, wm, monitor, &base)?;
+    waybar_command("waybar", config_path, &monitor_style, log_path.as_deref())?
+        .spawn()
+        .context("Error launching waybar")
+}
+
+/// Rewrite the per-monitor stylesheets of `monitors` so running bars pick
+/// up `[styles]` changes on their next reload
+pub fn write_monitor_styles(cfg: &Config, monitors: &[String], wm: &WindowManager) -> Result<()> {
+    if cfg.display.combined_config {
+        return Ok(());
+    }
+
+    let style_path = get_style_path(cfg);
+    for monitor in monitors {
+        let base = base_style_for(cfg, monitor, &style_path)?;
+        monitor_style_path(&get_generated_dir(), cfg, wm, monitor, &base)?;
+    }
+
+    Ok(())
+}
+
+/// A waybar instance `launch` would start
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedBar {
+    /// Monitor and template, e.g. `DP-1 (full)`
+    pub label: String,
+    pub config: PathBuf,
+    pub style: PathBuf,
+}
+
+impl PlannedBar {
+    pub fn command(&self) -> String {
+        format!(
+            "waybar -c {} -s {}",
+            self.config.display(),
+            self.style.display()
+        )
+    }
+}
+
+/// The bars `launch_waybar_instances` would start for `monitors`, without
+/// writing or spawning anything (for `launch --dry-run`)
+pub fn launch_plan(cfg: &Config, monitors: &[String], wm: &WindowManager) -> Vec<PlannedBar> {
+    plan_in(&get_generated_dir(), cfg, monitors, wm)
+}
+
+fn plan_in(dir: &Path, cfg: &Config, monitors: &[String], wm: &WindowManager) -> Vec<PlannedBar> {
+    let style_path = get_style_path(cfg);
+
+    if cfg.display.combined_config {
+        return vec![PlannedBar {
+            label: format!("{} (combined)", monitors.join(", ")),
+            config: dir.join(format!("{}_combined.json", wm.as_str())),
+            style: style_path,
+        }];
+    }
+
+    launch_order(cfg, &determine_config_assignments(cfg, monitors))
+        .iter()
+        .map(|(monitor, template_type)| PlannedBar {
+            label: format!("{} ({})", monitor, template_type.name()),
+            config: generated_config_path_in(dir, cfg, wm, monitor, template_type),
+            style: match monitor_style(cfg, monitor) {
+                Some(_) => monitor_style_file(dir, wm, monitor),
+                None => monitor_style_override(&dirs::home_dir().unwrap(), cfg, monitor)
+                    .unwrap_or_else(|| style_path.clone()),
+            },
+        })
+        .collect()
+}
+
+/// `assignments` in the order bars are started: `[display] launch_order`
+/// first, then the others in assignment order
+pub fn launch_order(
+    cfg: &Config,
+    assignments: &[(String, TemplateType)],
+) -> Vec<(String, TemplateType)> {
+    let launch_order = &cfg.display.launch_order;
+    let rank = |monitor: &str| {
+        launch_order
+            .iter()
+            .position(|m| {
+                crate::monitor::names_equal(m, monitor, cfg.display.case_insensitive_match)
+            })
+            .unwrap_or(launch_order.len())
+    };
+
+    let mut ordered = assignments.to_vec();
+    ordered.sort_by_key(|(monitor, _)| rank(monitor));
+    ordered
+}
+
+/// `[styles.<monitor>]` entry for `monitor`, if any
+fn monitor_style<'a>(cfg: &'a Config, monitor: &str) -> Option<&'a crate::config::MonitorStyle> {
+    cfg.styles
+        .iter()
+        .find(|(name, _)| {
+            crate::monitor::names_equal(name, monitor, cfg.display.case_insensitive_match)
+        })
+        .map(|(_, style)| style)
+}
+
+fn monitor_style_file(dir: &Path, wm: &WindowManager, monitor: &str) -> PathBuf {
+    dir.join(format!("{}_{}_style.css", wm.as_str(), monitor))
+}
+
+/// Stylesheet for `monitor`'s bar: the base stylesheet, or a generated one
+/// importing it followed by the monitor's `[styles.<monitor>]` CSS
+fn monitor_style_path(
+    dir: &Path,
+    cfg: &Config,
+    wm: &WindowManager,
+    monitor: &str,
+    base: &Path,
+) -> Result<PathBuf> {
+    let Some(style) = monitor_style(cfg, monitor) else {
+        return Ok(base.to_path_buf());
+    };
+
+    // Importing keeps relative @imports in the base stylesheet working
+    let path = monitor_style_file(dir, wm, monitor);
+    fs::create_dir_all(dir)?;
+    write_generated(
+        &path,
+        &format!(
+            "{}\n@import url(\"{}\");\n\n/* [styles.{}] */\n{}\n",
+            STYLE_MARKER,
+            base.display(),
+            monitor,
+            style.css
+        ),
+        cfg.launch.file_mode()?,
+        &[],
+    )
+    .context(format!("Error writing stylesheet for {}", monitor))?;
+
+    Ok(path)
+}
+
+/// Key stamped into every generated bar config. Only files carrying it (or,
+/// for stylesheets, `STYLE_MARKER`) are ever overwritten or removed, so
+/// anything else placed in the generated directory is left alone.
+const GENERATOR_KEY: &str = "_generated_by";
+const GENERATOR: &str = env!("CARGO_PKG_NAME");
+const STYLE_MARKER: &str = concat!("/* _generated_by: ", env!("CARGO_PKG_NAME"), " */");
+
+/// Stamp `config`, a bar or an array of bars, as written by the manager
+fn mark_generated(config: &mut Value) {
+    let bars = match config {
+        Value::Array(bars) => bars.iter_mut().collect(),
+        bar => vec![bar],
+    };
+    for bar in bars {
+        if let Some(obj) = bar.as_object_mut() {
+            obj.insert(GENERATOR_KEY.to_string(), Value::from(GENERATOR));
+        }
+    }
+}
+
+/// Whether the file at `path` was written by the manager: it carries the
+/// marker, or is one of `cached`, the files recorded in the cache
+pub fn is_generated_file(path: &Path, cached: &[String]) -> bool {
+    if cached.iter().any(|c| Path::new(c) == path) {
+        return true;
+    }
+
+    let Ok(content) = fs::read_to_string(path) else {
+        return false;
+    };
+    if content.starts_with(STYLE_MARKER) {
+        return true;
+    }
+
+    let marked = |bar: &Value| bar.get(GENERATOR_KEY).and_then(Value::as_str) == Some(GENERATOR);
+    let parsed = crate::jsonc::strip_comments(&content)
+        .ok()
+        .and_then(|json| serde_json::from_str::<Value>(&json).ok());
+    match parsed {
+        Some(Value::Array(bars)) => !bars.is_empty() && bars.iter().all(marked),
+        Some(bar) => marked(&bar),
+        None => false,
+    }
+}
+
+/// `<wm>_<...>.json` or `<wm>_<monitor>_style.css`, as the manager names
+/// its files. Only trusted for the legacy migration: a hand-written file
+/// may well be named the same.
+fn has_generated_name(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+
+    WindowManager::ALL.iter().any(|wm| {
+        name.strip_prefix(wm.as_str())
+            .and_then(|rest| rest.strip_prefix('_'))
+            .is_some_and(|rest| rest.ends_with(".json") || rest.ends_with("_style.css"))
+    })
+}
+
+/// Write `contents` to `path` with `mode`. A file the manager did not
+/// write (see `is_generated_file`) is never replaced: generation fails
+/// instead, so it is not cached as up to date.
+fn write_generated(
+    path: &Path,
+    contents: &str,
+    mode: Option<u32>,
+    cached: &[String],
+) -> Result<()> {
+    if path.exists() && !is_generated_file(path, cached) {
+        return Err(anyhow::anyhow!(
+            "{} was not written by the manager; move it away to have it regenerated",
+            path.display()
+        ));
+    }
+
+    write_with_mode(path, contents, mode)
+}
+
+/// Remove the files in `dir` written by the manager (`cached` being the
+/// files recorded in the cache), then `dir` itself if nothing else is left.
+/// Returns the removed files and the ones kept.
+pub fn remove_generated(dir: &Path, cached: &[String]) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let (mut removed, mut kept) = (Vec::new(), Vec::new());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok((removed, kept));
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_file() && is_generated_file(&path, cached) {
+            fs::remove_file(&path).context(format!("Error removing {}", path.display()))?;
+            removed.push(path);
+        } else {
+            kept.push(path);
+        }
+    }
+
+    if kept.is_empty() {
+        fs::remove_dir(dir).context(format!("{} could not be removed", dir.display()))?;
+    }
+    removed.sort();
+    kept.sort();
+    Ok((removed, kept))
+}
+
+/// Write `contents` to `path`, creating it with `mode` (`[launch]
+/// generated_file_mode`) so it is never readable more widely, not even
+/// briefly. An existing file is switched to `mode` before anything is
+/// written; `None` leaves the umask's choice (or the existing mode) alone.
+pub fn write_with_mode(path: &Path, contents: &str, mode: Option<u32>) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    let mut file = options
+        .open(path)
+        .context(format!("Error writing {}", path.display()))?;
+
+    if let Some(mode) = mode {
+        // The creation mode is narrowed by the umask and ignored for
+        // existing files
+        file.set_permissions(fs::Permissions::from_mode(mode))
+            .context(format!("Error setting permissions of {}", path.display()))?;
+    }
+
+    file.write_all(contents.as_bytes())
+        .context(format!("Error writing {}", path.display()))
+}
+
+/// Log file for the waybar instance on `monitor`
+pub fn get_log_path(monitor: &str) -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().context("The data directory could not be retrieved")?;
+    Ok(data_dir
+        .join("omynix/modules/waybar-manager/logs")
+        .join(format!("waybar-{}.log", monitor)))
+}
+
+fn log_path_for(cfg: &Config, monitor: &str) -> Result<Option<PathBuf>> {
+    if !cfg.behavior.log_to_files {
+        return Ok(None);
+    }
+    get_log_path(monitor).map(Some)
+}
+
+/// Build the waybar command, appending its stdout/stderr to `log_path`
+/// when given (stdio is inherited otherwise)
+fn waybar_command(
+    waybar: &str,
+    config_path: &Path,
+    style_path: &Path,
+    log_path: Option<&Path>,
+) -> Result<Command> {
+    let mut command = Command::new(waybar);
+    command.arg("-c").arg(config_path).arg("-s").arg(style_path);
+
+    if let Some(log_path) = log_path {
+        if let Some(parent) = log_path.parent() {
+            fs::create_dir_all(parent).context("Error creating the log directory")?;
+        }
+        let log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)
+            .context(format!("Error opening log file {}", log_path.display()))?;
+        command.stdout(log.try_clone()?).stderr(log);
+    }
+
+    Ok(command)
+}
+
+/// What waybar made of a generated config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// Still running after the grace period
+    Accepted,
+    /// Exited with an error status
+    Rejected,
+    /// Exited cleanly or was killed before the grace period ended, so
+    /// the config could not be judged
+    Inconclusive,
+}
+
+/// Monitors by how waybar took their generated config
+#[derive(Debug, Default, PartialEq)]
+pub struct WaybarCheck {
+    pub rejected: Vec<String>,
+    pub inconclusive: Vec<String>,
+}
+
+/// Check each generated config by briefly launching waybar on a hidden
+/// copy of it (`start_hidden`), so no probe bar is drawn. Nothing is
+/// written to the generated directory: the copies and per-monitor
+/// stylesheets go to a scratch directory that is removed afterwards.
+pub fn check_against_waybar(
+    cfg: &Config,
+    connected: &[String],
+    wm: &WindowManager,
+) -> Result<WaybarCheck> {
+    let scratch = std::env::temp_dir().join(format!("omynix-waybar-check-{}", std::process::id()));
+    fs::create_dir_all(&scratch)?;
+    let result = check_against_waybar_in(&scratch, cfg, connected, wm);
+    fs::remove_dir_all(&scratch).ok();
+    result
+}
+
+fn check_against_waybar_in(
+    scratch: &Path,
+    cfg: &Config,
+    connected: &[String],
+    wm: &WindowManager,
+) -> Result<WaybarCheck> {
+    let style_path = get_style_path(cfg);
+    let grace = std::time::Duration::from_millis(800);
+    let mut check = WaybarCheck::default();
+
+    let probes = if cfg.display.combined_config {
+        let config_path = hidden_copy(&get_combined_config_path(wm), scratch)?;
+        let probe = probe_config("waybar", &config_path, &style_path, grace)?;
+        connected.iter().map(|m| (m.clone(), probe)).collect()
+    } else {
+        let mut probes = Vec::new();
+        for (monitor, template_type) in determine_config_assignments(cfg, connected) {
+            let config_path = monitor_config_path(cfg, wm, &monitor, &template_type);
+            let config_path = hidden_copy(&config_path, scratch)?;
+            let base = base_style_for(cfg, &monitor, &style_path)?;
+            let style_path = monitor_style_path(scratch, cfg, wm, &monitor, &base)?;
+            probes.push((
+                monitor,
+                probe_config("waybar", &config_path, &style_path, grace)?,
+            ));
+        }
+        probes
+    };
+
+    for (monitor, probe) in probes {
+        match probe {
+            Probe::Accepted => {}
+            Probe::Rejected => check.rejected.push(monitor),
+            Probe::Inconclusive => check.inconclusive.push(monitor),
+        }
+    }
+
+    Ok(check)
+}
+
+/// Copy of the config at `path` in `dir` with every bar set to
+/// `start_hidden`
+fn hidden_copy(path: &Path, dir: &Path) -> Result<PathBuf> {
+    let content = fs::read_to_string(path).context(format!("Error reading {}", path.display()))?;
+    let mut config: Value = serde_json::from_str(&crate::jsonc::strip_comments(&content)?)
+        .context(format!("{} is not valid JSON", path.display()))?;
+
+    let bars = match &mut config {
+        Value::Array(bars) => bars.iter_mut().collect(),
+        bar => vec![bar],
+    };
+    for bar in bars {
+        if let Some(obj) = bar.as_object_mut() {
+            obj.insert("start_hidden".to_string(), Value::Bool(true));
+        }
+    }
+
+    let copy = dir.join(path.file_name().unwrap_or_default());
+    fs::write(&copy, serde_json::to_string_pretty(&config)?)?;
+    Ok(copy)
+}
+
+/// Launch `waybar` on a config and judge it by whether it is still running
+/// after `grace` (see `Probe`); the probe instance is killed afterwards.
+fn probe_config(
+    waybar: &str,
+    config_path: &Path,
+    style_path: &Path,
+    grace: std::time::Duration,
+) -> Result<Probe> {
+    let mut child = waybar_command(waybar, config_path, style_path, None)?
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("Error launching waybar for validation")?;
+
+    match crate::process::wait_and_check_alive(&[child.id()], grace).first() {
+        Some(dead) if dead.exit_code.is_some_and(|code| code != 0) => Ok(Probe::Rejected),
+        Some(_) => Ok(Probe::Inconclusive),
+        None => {
+            child.kill().ok();
+            child.wait().ok();
+            Ok(Probe::Accepted)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_confirm_started_checks_each_bar() {
+        let stagger = Duration::from_millis(100);
+        // Reaped by confirm_started itself
+        let failing = Command::new("sh")
+            .args(["-c", "exit 1"])
+            .spawn()
+            .unwrap()
+            .id();
+        let dead = confirm_started(failing, "DP-1", stagger).unwrap();
+        assert_eq!(dead.exit_code, Some(1));
+        assert!(check_early_exits(&[("DP-1".to_string(), dead)], 2).is_err());
+
+        let mut running = Command::new("sleep").arg("5").spawn().unwrap();
+        assert_eq!(confirm_started(running.id(), "DP-2", stagger), None);
+        running.kill().unwrap();
+        running.wait().unwrap();
+    }
+
+    #[test]
+    fn test_early_exits_reports_only_failed_bars() {
+        let spawn = |script: &str| Command::new("sh").args(["-c", script]).spawn().unwrap();
+        let mut running = spawn("sleep 5");
+        let children = vec![
+            ("DP-1".to_string(), spawn("exit 1").id()),
+            ("DP-2".to_string(), spawn("exit 0").id()),
+            ("HDMI-A-1".to_string(), running.id()),
+        ];
+
+        let failed = early_exits(&children, EARLY_EXIT_GRACE);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, "DP-1");
+        assert_eq!(failed[0].1.exit_code, Some(1));
+        let err = check_early_exits(&failed, children.len()).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("1 of 3 bars failed to stay up (DP-1)")
+        );
+        assert!(check_early_exits(&[], children.len()).is_ok());
+
+        running.kill().unwrap();
+        running.wait().unwrap();
+    }
+
+    #[test]
+    fn test_allow_empty_templates_uses_fallback() {
+        let src = "[\n  // TPL:FULL\n  // {\"layer\": \"top\"}\n]";
+
+        let err = parse_jsonc_templates(src, false).unwrap_err();
+        assert!(err.to_string().contains("No valid templates"));
+
+        let templates = parse_jsonc_templates(src, true).unwrap();
+        let types: Vec<_> = templates.iter().map(|t| &t.template_type).collect();
+        assert_eq!(types, vec![&TemplateType::Full, &TemplateType::Simple]);
+        assert_eq!(templates[0].config["modules-left"], json!(["clock"]));
+    }
+
+    #[test]
+    fn test_generated_config_validation() {
+        let complete = json!({
+            "layer": "top",
+            "modules-left": [],
+            "modules-center": ["clock"],
+            "modules-right": []
+        });
+        assert!(generated_config_problems(&complete).is_empty());
+        assert!(generated_config_problems(&fallback_templates().unwrap()[0].config).is_empty());
+
+        let partial = json!({"layer": "top", "modules-left": ["clock"]});
+        assert_eq!(
+            generated_config_problems(&partial),
+            vec!["missing modules-center, modules-right"]
+        );
+        assert!(check_generated_config("DP-1", &partial, false).is_ok());
+        let err = check_generated_config("DP-1", &partial, true).unwrap_err();
+        assert!(format!("{:#}", err).contains("Generated config for DP-1 missing"));
+
+        assert_eq!(
+            generated_config_problems(&json!(["not", "a", "bar"])),
+            vec!["is not a JSON object"]
+        );
+    }
+
+    #[test]
+    fn test_waybar_output_goes_to_log_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("waybar");
+        fs::write(&stub, "#!/bin/sh\necho \"out $2\"\necho \"err $4\" >&2\n").unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let log = dir.path().join("logs/waybar-DP-1.log");
+        let config = dir.path().join("config.json");
+        let style = dir.path().join("style.css");
+        let stub = stub.to_str().unwrap();
+
+        for _ in 0..2 {
+            let status = waybar_command(stub, &config, &style, Some(&log))
+                .unwrap()
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        // Both streams land in the file, and later runs append
+        let content = fs::read_to_string(&log).unwrap();
+        let expected = format!("out {}\nerr {}\n", config.display(), style.display());
+        assert_eq!(content, expected.repeat(2));
+    }
+
+    fn sample_templates() -> Vec<TemplateConfig> {
+        vec![
+            TemplateConfig {
+                template_type: TemplateType::Full,
+                config: json!({ "output": "CONFIGURED_FROM_SCRIPT", "modules-left": ["clock"] }),
+            },
+            TemplateConfig {
+                template_type: TemplateType::Simple,
+                config: json!({ "output": "CONFIGURED_FROM_SCRIPT", "modules-left": [] }),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_single_mode_generates_only_the_preferred_monitor() {
+        let dir = tempfile::tempdir().unwrap();
+        let wm = WindowManager::Niri;
+        let mut cfg = Config::default();
+        cfg.display.mode = "single".to_string();
+        cfg.display.preferred_monitor = "HDMI-A-1".to_string();
+        let connected = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+
+        // What launch does: narrow the connected monitors, then generate
+        let monitors = crate::monitor::select_monitors_with(&cfg, &connected, || None)
+            .unwrap()
+            .monitors;
+        assert_eq!(monitors, ["HDMI-A-1"]);
+
+        let rendered = render_with(&cfg, &sample_templates(), &monitors, dir.path()).unwrap();
+        assert_eq!(rendered.len(), 1);
+        assert_eq!(rendered[0].1["output"], "HDMI-A-1");
+        assert_eq!(
+            crate::cache::generated_files_in(dir.path(), &cfg, &monitors, &wm),
+            vec![generated_config_path_in(
+                dir.path(),
+                &cfg,
+                &wm,
+                "HDMI-A-1",
+                &TemplateType::Full
+            )]
+        );
+    }
+
+    #[test]
+    fn test_frozen_monitor_config_is_left_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let wm = WindowManager::Niri;
+        let mut cfg = Config::default();
+        cfg.display.mode = "multiple".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        cfg.display.frozen_monitors = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let monitors = vec![
+            "DP-1".to_string(),
+            "eDP-1".to_string(),
+            "HDMI-A-1".to_string(),
+        ];
+
+        // Tuned while DP-1 still had the simple template
+        let tuned = generated_config_path_in(dir.path(), &cfg, &wm, "DP-1", &TemplateType::Simple);
+        fs::write(&tuned, r#"{"output": "DP-1", "height": 42}"#).unwrap();
+
+        let mut assignments = determine_config_assignments(&cfg, &monitors);
+        skip_frozen(dir.path(), &cfg, &wm, &mut assignments);
+        // HDMI-A-1 has nothing to keep, so it is generated once
+        assert_eq!(
+            assignments,
+            vec![
+                ("eDP-1".to_string(), TemplateType::Simple),
+                ("HDMI-A-1".to_string(), TemplateType::Simple)
+            ]
+        );
+        assert_eq!(
+            frozen_config_in(dir.path(), &cfg, &wm, "DP-1", &TemplateType::Full),
+            Some(tuned.clone())
+        );
+        assert_eq!(
+            fs::read_to_string(&tuned).unwrap(),
+            r#"{"output": "DP-1", "height": 42}"#
+        );
+
+        // Frozen monitors never make the cache look stale
+        let edp = generated_config_path_in(dir.path(), &cfg, &wm, "eDP-1", &TemplateType::Simple);
+        fs::write(edp, "{}").unwrap();
+        assert!(crate::cache::generated_files_exist_in(
+            dir.path(),
+            &cfg,
+            &monitors,
+            &wm
+        ));
+    }
+
+    #[test]
+    fn test_frozen_bar_kept_in_combined_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("niri_combined.json");
+        fs::write(
+            &path,
+            r#"[{"output": "DP-1", "height": 42}, {"output": "eDP-1", "height": 42}]"#,
+        )
+        .unwrap();
+
+        let mut cfg = Config::default();
+        cfg.display.frozen_monitors = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let mut combined = json!([
+            {"output": "DP-1", "height": 30},
+            {"output": "eDP-1", "height": 30},
+            {"output": "HDMI-A-1", "height": 30}
+        ]);
+        keep_frozen_bars(&path, &cfg, &mut combined);
+
+        let heights: Vec<_> = combined
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bar| bar["height"].as_i64().unwrap())
+            .collect();
+        assert_eq!(heights, vec![42, 30, 30]);
+    }
+
+    #[test]
+    fn test_launch_plan_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = dir.path().join("generated");
+        let mut cfg = Config::default();
+        cfg.display.mode = "multiple".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        cfg.display.style_path = Some("/styles/bar.css".to_string());
+        cfg.styles.insert(
+            "eDP-1".to_string(),
+            crate::config::MonitorStyle {
+                css: "* { font-size: 11px; }".to_string(),
+            },
+        );
+        let monitors = vec!["eDP-1".to_string(), "DP-1".to_string()];
+
+        let plan = plan_in(&generated, &cfg, &monitors, &WindowManager::Niri);
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan[0].label, "DP-1 (full)");
+        assert_eq!(
+            plan[0].command(),
+            format!(
+                "waybar -c {} -s /styles/bar.css",
+                generated_config_path_in(
+                    &generated,
+                    &cfg,
+                    &WindowManager::Niri,
+                    "DP-1",
+                    &TemplateType::Full
+                )
+                .display()
+            )
+        );
+        assert_eq!(plan[1].label, "eDP-1 (simple)");
+        assert_eq!(plan[1].style, generated.join("niri_eDP-1_style.css"));
+        assert!(!generated.exists());
+    }
+
+    #[test]
+    fn test_bars_launch_in_configured_order() {
+        let mut cfg = Config::default();
+        cfg.display.mode = "multiple".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        cfg.display.launch_order = vec!["HDMI-A-1".to_string(), "DP-1".to_string()];
+        cfg.display.available_monitors = vec![
+            "eDP-1".to_string(),
+            "DP-1".to_string(),
+            "HDMI-A-1".to_string(),
+        ];
+        let monitors = vec![
+            "DP-2".to_string(),
+            "DP-1".to_string(),
+            "eDP-1".to_string(),
+            "HDMI-A-1".to_string(),
+        ];
+
+        let order = launch_order(&cfg, &determine_config_assignments(&cfg, &monitors));
+        let names: Vec<&str> = order.iter().map(|(m, _)| m.as_str()).collect();
+        assert_eq!(names, vec!["HDMI-A-1", "DP-1", "eDP-1", "DP-2"]);
+
+        let plan = plan_in(Path::new("/tmp"), &cfg, &monitors, &WindowManager::Niri);
+        let labels: Vec<&str> = plan.iter().map(|bar| bar.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "HDMI-A-1 (simple)",
+                "DP-1 (full)",
+                "eDP-1 (simple)",
+                "DP-2 (simple)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_markers_report_lines_and_objects() {
+        let content = r#"[
+  // TPL:FULL
+  { "layer": "top" },
+  // TPL:SIMPLE
+  { "layer": "bottom" },
+  { "layer": "overlay" }
+]
+// TPL:vertical
+"#;
+        let (markers, objects) = template_markers(content).unwrap();
+        assert_eq!(objects, 3);
+        assert_eq!(
+            markers,
+            vec![
+                TemplateMarker {
+                    line: 2,
+                    template_type: TemplateType::Full,
+                    object: Some(0),
+                },
+                TemplateMarker {
+                    line: 4,
+                    template_type: TemplateType::Simple,
+                    object: Some(1),
+                },
+                TemplateMarker {
+                    line: 8,
+                    template_type: TemplateType::Custom("vertical".to_string()),
+                    object: Some(2),
+                },
+            ]
+        );
+
+        let (markers, objects) = template_markers("// TPL:FULL\n// TPL:SIMPLE\n[{}]").unwrap();
+        assert_eq!(objects, 1);
+        assert_eq!(markers[1].object, None);
+    }
+
+    #[test]
+    fn test_template_missing_required_module_fails() {
+        let mut cfg = Config::default();
+        cfg.display.mode = "multiple".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        cfg.display.required_modules = vec!["clock".to_string()];
+        let templates = sample_templates();
+
+        let full = render_config(
+            &cfg,
+            &templates,
+            "DP-1",
+            &TemplateType::Full,
+            Path::new("/tmp"),
+        )
+        .unwrap();
+        assert!(check_required_modules(&cfg, "DP-1", &TemplateType::Full, &full).is_ok());
+
+        // The SIMPLE template has no clock
+        let simple = render_config(
+            &cfg,
+            &templates,
+            "HDMI-A-1",
+            &TemplateType::Simple,
+            Path::new("/tmp"),
+        )
+        .unwrap();
+        let err =
+            check_required_modules(&cfg, "HDMI-A-1", &TemplateType::Simple, &simple).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Generated config for HDMI-A-1 (template 'simple') is missing required modules: clock"
+        );
+    }
+
+    #[test]
+    fn test_full_only_template_file_covers_every_monitor() {
+        let mut cfg = Config::default();
+        cfg.display.mode = "multiple".to_string();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        let full_only: Vec<TemplateConfig> = sample_templates()
+            .into_iter()
+            .filter(|t| t.template_type == TemplateType::Full)
+            .collect();
+        let monitors = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+
+        let rendered = render_with(&cfg, &full_only, &monitors, Path::new("/tmp")).unwrap();
+        assert_eq!(rendered.len(), 2);
+        assert_eq!(rendered[1].0, "HDMI-A-1");
+        assert_eq!(rendered[1].1["output"], "HDMI-A-1");
+        assert_eq!(rendered[1].1["modules-left"], json!(["clock"]));
+
+        // Without FULL there is nothing to fall back to
+        let simple_only: Vec<TemplateConfig> = sample_templates()
+            .into_iter()
+            .filter(|t| t.template_type == TemplateType::Simple)
+            .collect();
+        assert!(render_with(&cfg, &simple_only, &monitors, Path::new("/tmp")).is_err());
+    }
+
+    #[test]
+    fn test_render_profiles_reports_invalid_assignment() {
+        let mut cfg = Config::default();
+        cfg.profiles.insert(
+            "home".to_string(),
+            crate::config::Display {
+                mode: "multiple".to_string(),
+                preferred_monitor: "HDMI-A-1".to_string(),
+                available_monitors: vec!["HDMI-A-1".to_string(), "eDP-1".to_string()],
+                ..cfg.display.clone()
+            },
+        );
+        cfg.profiles.insert(
+            "travel".to_string(),
+            crate::config::Display {
+                mode: "multiple".to_string(),
+                preferred_monitor: "DP-1".to_string(),
+                available_monitors: vec!["DP-1".to_string(), "eDP-1".to_string()],
+                internal_template: Some("custom:missing".to_string()),
+                ..cfg.display.clone()
+            },
+        );
+
+        let render = |name: &str| {
+            let profile = cfg.with_profile(name).unwrap();
+            let monitors = profile.display.available_monitors.clone();
+            render_with(&profile, &sample_templates(), &monitors, Path::new("/tmp"))
+        };
+
+        let home = render("home").unwrap();
+        assert_eq!(home.len(), 2);
+        assert_eq!(home[0].0, "HDMI-A-1");
+        assert_eq!(home[0].1["modules-left"], json!(["clock"]));
+
+        let err = render("travel").unwrap_err();
+        assert!(err.to_string().contains("missing"), "{}", err);
+    }
+
+    #[test]
+    fn test_connector_family_rules() {
+        let mut cfg = Config::default();
+        for (key, template) in [
+            ("edp", "simple"),
+            ("hdmi", "full"),
+            ("dp", "full"),
+            ("DP-3", "custom:media"),
+        ] {
+            cfg.display
+                .template_rules
+                .insert(key.to_string(), template.to_string());
+        }
+        let connected: Vec<String> = ["eDP-1", "HDMI-A-1", "DP-2", "DP-3"]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+
+        let assignments: HashMap<_, _> = determine_config_assignments(&cfg, &connected)
+            .into_iter()
+            .collect();
+        assert_eq!(assignments["eDP-1"], TemplateType::Simple);
+        assert_eq!(assignments["HDMI-A-1"], TemplateType::Full);
+        assert_eq!(assignments["DP-2"], TemplateType::Full);
+        // A per-name rule overrides the `dp` family
+        assert_eq!(
+            assignments["DP-3"],
+            TemplateType::Custom("media".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cli_assignments_override_listed_monitors_only() {
+        let mut cfg = Config::default();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        let connected = vec![
+            "DP-1".to_string(),
+            "HDMI-A-1".to_string(),
+            "DP-2".to_string(),
+        ];
+        cfg.display
+            .assign
+            .insert("DP-1".to_string(), "simple".to_string());
+        cfg.display
+            .assign
+            .insert("HDMI-A-1".to_string(), "custom:media".to_string());
+
+        let assignments: HashMap<_, _> = determine_config_assignments(&cfg, &connected)
+            .into_iter()
+            .collect();
+        assert_eq!(assignments["DP-1"], TemplateType::Simple);
+        assert_eq!(
+            assignments["HDMI-A-1"],
+            TemplateType::Custom("media".to_string())
+        );
+        assert_eq!(assignments["DP-2"], TemplateType::Simple);
+
+        let available = [TemplateType::Full, TemplateType::Simple];
+        let err = validate_assignments(&cfg, &connected, &available).unwrap_err();
+        assert!(err.to_string().contains("media"), "{}", err);
+
+        cfg.display.assign.remove("HDMI-A-1");
+        cfg.display
+            .assign
+            .insert("DP-9".to_string(), "full".to_string());
+        let err = validate_assignments(&cfg, &connected, &available).unwrap_err();
+        assert!(err.to_string().contains("not connected"), "{}", err);
+    }
+
+    #[test]
+    fn test_generated_path_has_clean_extension() {
+        let path = generated_config_path_in(
+            Path::new("/tmp/generated"),
+            &Config::default(),
+            &WindowManager::Hyprland,
+            "eDP-1",
+            &TemplateType::Full,
+        );
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(name, "hyprland_eDP-1_full.json");
+        assert!(!name.contains(char::is_whitespace));
+    }
+
+    #[test]
+    fn test_migrate_legacy_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("niri_eDP-1_full. json"), "{}").unwrap();
+        fs::write(dir.path().join("niri_combined.json"), "[]").unwrap();
+
+        assert_eq!(migrate_legacy_file_names(dir.path()).unwrap(), 1);
+        assert!(is_generated_file(
+            &dir.path().join("niri_eDP-1_full.json"),
+            &[]
+        ));
+        assert!(!dir.path().join("niri_eDP-1_full. json").exists());
+        assert_eq!(migrate_legacy_file_names(dir.path()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_monitor_inline_css_in_launched_stylesheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("omynix_style.css");
+        let mut cfg = Config::default();
+        cfg.styles.insert(
+            "DP-2".to_string(),
+            crate::config::MonitorStyle {
+                css: "window#waybar { background: #ff5555; }".to_string(),
+            },
+        );
+
+        let path =
+            monitor_style_path(dir.path(), &cfg, &WindowManager::Niri, "DP-2", &base).unwrap();
+        assert_eq!(path, dir.path().join("niri_DP-2_style.css"));
+        let css = fs::read_to_string(&path).unwrap();
+        assert!(css.starts_with(&format!(
+            "{}\n@import url(\"{}\");",
+            STYLE_MARKER,
+            base.display()
+        )));
+        assert!(css.contains("window#waybar { background: #ff5555; }"));
+
+        // Monitors without inline CSS use the base stylesheet directly
+        let path =
+            monitor_style_path(dir.path(), &cfg, &WindowManager::Niri, "eDP-1", &base).unwrap();
+        assert_eq!(path, base);
+    }
+
+    #[test]
+    fn test_monitor_styles_replace_the_global_stylesheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path();
+        fs::write(home.join("portrait.css"), "").unwrap();
+        let global = home.join("omynix_style.css");
+        let mut cfg = Config::default();
+        cfg.display
+            .monitor_styles
+            .insert("DP-2".to_string(), "~/portrait.css".to_string());
+        cfg.display
+            .monitor_styles
+            .insert("HDMI-A-1".to_string(), "missing.css".to_string());
+
+        assert_eq!(
+            monitor_style_override(home, &cfg, "DP-2"),
+            Some(home.join("portrait.css"))
+        );
+        assert_eq!(
+            monitor_style_override(home, &cfg, "HDMI-A-1"),
+            Some(home.join("missing.css"))
+        );
+        assert_eq!(monitor_style_override(home, &cfg, "eDP-1"), None);
+
+        // Absolute paths are used as they are
+        let portrait = home.join("portrait.css");
+        cfg.display
+            .monitor_styles
+            .insert("DP-2".to_string(), portrait.display().to_string());
+        assert_eq!(base_style_for(&cfg, "DP-2", &global).unwrap(), portrait);
+        assert_eq!(base_style_for(&cfg, "eDP-1", &global).unwrap(), global);
+        cfg.display.monitor_styles.insert(
+            "HDMI-A-1".to_string(),
+            home.join("missing.css").display().to_string(),
+        );
+        assert!(base_style_for(&cfg, "HDMI-A-1", &global).is_err());
+    }
+
+    #[test]
+    fn test_selftest() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = selftest(dir.path()).unwrap();
+
+        let names: Vec<_> = written
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["niri_DP-1_full.json", "niri_eDP-1_simple.json"]);
+    }
+
+    #[test]
+    fn test_assignments_follow_preferred_then_available_order() {
+        let mut cfg = Config::default();
+        cfg.display.preferred_monitor = "DP-2".to_string();
+        cfg.display.available_monitors = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        let connected = vec![
+            "HDMI-A-1".to_string(),
+            "DP-3".to_string(),
+            "DP-2".to_string(),
+            "eDP-1".to_string(),
+        ];
+
+        let assignments = determine_config_assignments(&cfg, &connected);
+        let names: Vec<&str> = assignments.iter().map(|(m, _)| m.as_str()).collect();
+        assert_eq!(names, vec!["DP-2", "eDP-1", "HDMI-A-1", "DP-3"]);
+        assert_eq!(assigned(&assignments, "DP-2"), Some(&TemplateType::Full));
+        assert_eq!(assigned(&assignments, "DP-3"), Some(&TemplateType::Simple));
+    }
+
+    #[test]
+    fn test_combined_config_has_one_bar_per_monitor() {
+        let assignments = vec![
+            ("HDMI-A-1".to_string(), TemplateType::Full),
+            ("eDP-1".to_string(), TemplateType::Simple),
+        ];
+
+        let combined = build_combined_config(
+            &Config::default(),
+            &sample_templates(),
+            &assignments,
+            Path::new("/tmp"),
+        )
+        .unwrap();
+        let bars = combined.as_array().unwrap();
+        assert_eq!(bars.len(), 2);
+
+        for bar in bars {
+            match bar["output"].as_str().unwrap() {
+                "HDMI-A-1" => assert_eq!(bar["modules-left"], json!(["clock"])),
+                "eDP-1" => assert_eq!(bar["modules-left"], json!([])),
+                other => panic!("unexpected output {}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_internal_panel_gets_internal_template() {
+        let mut cfg = Config::default();
+        cfg.display.preferred_monitor = "eDP-1".to_string();
+        cfg.display.internal_template = Some("simple".to_string());
+
+        let connected = vec!["eDP-1".to_string(), "HDMI-A-1".to_string()];
+        let assignments: HashMap<_, _> = determine_config_assignments(&cfg, &connected)
+            .into_iter()
+            .collect();
+        assert_eq!(assignments["eDP-1"], TemplateType::Simple);
+        assert_eq!(assignments["HDMI-A-1"], TemplateType::Full);
+
+        // Without the option the preferred-monitor logic applies
+        cfg.display.internal_template = None;
+        let assignments: HashMap<_, _> = determine_config_assignments(&cfg, &connected)
+            .into_iter()
+            .collect();
+        assert_eq!(assignments["eDP-1"], TemplateType::Full);
+        assert_eq!(assignments["HDMI-A-1"], TemplateType::Simple);
+    }
+
+    #[test]
+    fn test_bom_prefixed_template_parses_and_hashes_the_same() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = "[\n  // TPL:FULL\n  {\"layer\": \"top\"}\n]";
+        let plain = dir.path().join("plain.jsonc");
+        let with_bom = dir.path().join("bom.jsonc");
+        fs::write(&plain, content).unwrap();
+        fs::write(&with_bom, format!("\u{feff}{}", content)).unwrap();
+
+        let read = read_template(&with_bom).unwrap();
+        let templates = parse_jsonc_templates(&read, false).unwrap();
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].template_type, TemplateType::Full);
+
+        let cfg = Config::default();
+        assert_eq!(
+            crate::cache::generation_hash(&cfg, &read),
+            crate::cache::generation_hash(&cfg, &read_template(&plain).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_monitor_layer_overrides_template_layer() {
+        let content = r#"[
+  // TPL:FULL
+  {"layer": "top", "position": "top"}
+]"#;
+        let templates = parse_jsonc_templates(content, false).unwrap();
+        let mut cfg = Config::default();
+        cfg.display
+            .monitor_layers
+            .insert("HDMI-A-1".to_string(), crate::config::Layer::Overlay);
+
+        let render = |monitor| {
+            render_config(
+                &cfg,
+                &templates,
+                monitor,
+                &TemplateType::Full,
+                Path::new("/tmp"),
+            )
+            .unwrap()
+        };
+        assert_eq!(render("HDMI-A-1")["layer"], "overlay");
+        assert_eq!(render("DP-1")["layer"], "top");
+    }
+
+    #[test]
+    fn test_module_summaries_list_placements_per_template() {
+        let content = r#"[
+  // TPL:FULL
+  {"modules-left": ["niri/workspaces"], "modules-center": ["clock"], "modules-right": ["tray", "battery"]},
+  // TPL:SIMPLE
+  {"modules-center": ["clock"]}
+]"#;
+        let summaries = module_summaries(&parse_jsonc_templates(content, false).unwrap());
+        assert_eq!(
+            summaries,
+            vec![
+                ModuleSummary {
+                    template: "full".to_string(),
+                    left: vec!["niri/workspaces".to_string()],
+                    center: vec!["clock".to_string()],
+                    right: vec!["tray".to_string(), "battery".to_string()],
+                },
+                ModuleSummary {
+                    template: "simple".to_string(),
+                    left: vec![],
+                    center: vec!["clock".to_string()],
+                    right: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_geometry_placeholders_substituted() {
+        let content = r#"[
+  // TPL:FULL
+  {"width": {{width}}, "height": {{height}}, "custom/name": {"format": "{{monitor}} @ {{refresh}}Hz"}, "x": "{{unknown}}"}
+]"#;
+        let templates = parse_jsonc_templates(content, false).unwrap();
+        let cfg = Config::default();
+        let mut config = render_config(
+            &cfg,
+            &templates,
+            "DP-1",
+            &TemplateType::Full,
+            Path::new("/tmp"),
+        )
+        .unwrap();
+
+        let geometry = [crate::monitor::MonitorInfo {
+            name: "DP-1".to_string(),
+            width: 2560,
+            height: 1440,
+            refresh: 144.0,
+            scale: 1.0,
+            ..Default::default()
+        }];
+        substitute_variables(&mut config, &geometry);
+
+        assert_eq!(config["width"], 2560);
+        assert_eq!(config["height"], 1440);
+        assert_eq!(config["custom/name"]["format"], "DP-1 @ 144.0Hz");
+        assert_eq!(config["x"], "{{unknown}}");
+
+        // Without geometry only the monitor name is known
+        let mut config = render_config(
+            &cfg,
+            &templates,
+            "DP-2",
+            &TemplateType::Full,
+            Path::new("/tmp"),
+        )
+        .unwrap();
+        substitute_variables(&mut config, &geometry);
+        assert_eq!(config["width"], "{{width}}");
+        assert_eq!(config["custom/name"]["format"], "DP-2 @ {{refresh}}Hz");
+    }
+
+    #[test]
+    fn test_assign_hook_drives_assignments() {
+        let mut cfg = Config::default();
+        cfg.display.preferred_monitor = "DP-1".to_string();
+        let connected = vec!["DP-1".to_string(), "eDP-1".to_string()];
+
+        // The stub only answers when it was handed the monitor list
+        let hook = r#"grep -q '\["DP-1","eDP-1"\]' && echo '{"eDP-1": "full", "DP-1": "media"}'"#;
+        cfg.display.hook_assign = run_assign_hook(hook, &connected).unwrap();
+        let assignments: HashMap<_, _> = determine_config_assignments(&cfg, &connected)
+            .into_iter()
+            .collect();
+        assert_eq!(assignments["eDP-1"], TemplateType::Full);
+        assert_eq!(
+            assignments["DP-1"],
+            TemplateType::Custom("media".to_string())
+        );
+
+        // Only templates from the template file are accepted
+        let available = [TemplateType::Full, TemplateType::Simple];
+        assert!(validate_hook_assignments(&cfg, &connected, &available).is_err());
+        cfg.display
+            .hook_assign
+            .insert("DP-1".to_string(), "simple".to_string());
+        assert!(validate_hook_assignments(&cfg, &connected, &available).is_ok());
+
+        assert!(run_assign_hook("echo not json", &connected).is_err());
+        assert!(run_assign_hook("exit 3", &connected).is_err());
+    }
+
+    #[test]
+    fn test_generated_file_mode_applied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = Config::default();
+        cfg.launch.generated_file_mode = Some("0600".to_string());
+        cfg.styles.insert(
+            "DP-1".to_string(),
+            crate::config::MonitorStyle {
+                css: "* {}".to_string(),
+            },
+        );
+
+        let path = monitor_style_path(
+            dir.path(),
+            &cfg,
+            &WindowManager::Niri,
+            "DP-1",
+            Path::new("/tmp/style.css"),
+        )
+        .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o600);
+
+        // An existing file is narrowed too; unset leaves its mode alone
+        let other = dir.path().join("other.json");
+        fs::write(&other, "{}").unwrap();
+        fs::set_permissions(&other, fs::Permissions::from_mode(0o644)).unwrap();
+        write_with_mode(&other, "[]", Some(0o600)).unwrap();
+        assert_eq!(
+            fs::metadata(&other).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        write_with_mode(&other, "{}", None).unwrap();
+        assert_eq!(
+            fs::metadata(&other).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(fs::read_to_string(&other).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_foreign_files_in_generated_dir_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let foreign = dir.path().join("my-bar.json");
+        let unmarked = dir.path().join("niri_DP-1_full.json");
+        let legacy = dir.path().join("niri_DP-1_full. json");
+        let cached = dir.path().join("bar-DP-2.json");
+        for path in [&foreign, &unmarked, &legacy, &cached] {
+            fs::write(path, r#"{"output": "DP-1"}"#).unwrap();
+        }
+        let cache = [cached.display().to_string()];
+
+        // A foreign file fails the write instead of being skipped
+        let mut config = json!({"output": "DP-1"});
+        mark_generated(&mut config);
+        let err = write_generated(&foreign, &config.to_string(), None, &cache).unwrap_err();
+        assert!(err.to_string().contains("not written by the manager"));
+        assert_eq!(
+            fs::read_to_string(&foreign).unwrap(),
+            r#"{"output": "DP-1"}"#
+        );
+
+        // Named like ours is not enough; being in the cache is
+        assert!(!is_generated_file(&unmarked, &[]));
+        assert!(is_generated_file(&cached, &cache));
+        assert!(write_generated(&unmarked, &config.to_string(), None, &cache).is_err());
+        write_generated(&cached, &config.to_string(), None, &cache).unwrap();
+
+        // The legacy file is renamed over nothing it does not own
+        assert_eq!(migrate_legacy_file_names(dir.path()).unwrap(), 0);
+        assert!(legacy.exists());
+        fs::remove_file(&unmarked).unwrap();
+        assert_eq!(migrate_legacy_file_names(dir.path()).unwrap(), 1);
+        assert!(is_generated_file(&unmarked, &[]));
+        fs::write(&unmarked, r#"{"output": "DP-1"}"#).unwrap();
+
+        // Pruning takes only the manager's own files
+        let (removed, kept) = remove_generated(dir.path(), &cache).unwrap();
+        assert_eq!(removed, vec![cached.clone()]);
+        assert_eq!(kept, vec![foreign.clone(), unmarked.clone()]);
+        assert!(foreign.exists() && unmarked.exists() && !cached.exists());
+    }
+
+    #[test]
+    fn test_rotated_monitor_gets_vertical_template() {
+        let mut cfg = Config::default();
+        cfg.display.preferred_monitor = "HDMI-A-1".to_string();
+        cfg.display.vertical_template = Some("vertical".to_string());
+
+        let connected = vec!["HDMI-A-1".to_string(), "DP-1".to_string()];
+        let landscape_hash = crate::cache::generation_hash(&cfg, "[]");
+        let mut cached = crate::cache::CacheEntry {
+            template_hash: landscape_hash,
+            style_hash: String::new(),
+            bars_hash: "bars".to_string(),
+            monitors: connected.clone(),
+            preferred_monitor: "HDMI-A-1".to_string(),
+            timestamp: 0,
+            last_launch: 0,
+            file_hashes: Default::default(),
+            assignments: Default::default(),
+        };
+
+        let rotated = crate::monitor::MonitorInfo {
+            name: "DP-1".to_string(),
+            transform: 90,
+            ..Default::default()
+        };
+        assert!(rotated.is_portrait());
+        cfg.display.portrait = vec![rotated.name];
+
+        let assignments: HashMap<_, _> = determine_config_assignments(&cfg, &connected)
+            .into_iter()
+            .collect();
+        assert_eq!(assignments["HDMI-A-1"], TemplateType::Full);
+        assert_eq!(
+            assignments["DP-1"],
+            TemplateType::Custom("vertical".to_string())
+        );
+
+        let inputs = crate::cache::GenerationInputs {
+            template_hash: crate::cache::generation_hash(&cfg, "[]"),
+            bars_hash: "bars".to_string(),
+            monitors: connected.clone(),
+            preferred_monitor: "HDMI-A-1".to_string(),
+            generated_files_exist: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            crate::cache::regeneration_reason(Some(&cached), &inputs),
+            Some(crate::cache::Reason::TemplateChanged)
+        );
+        cached.template_hash = inputs.template_hash.clone();
+        assert_eq!(
+            crate::cache::regeneration_reason(Some(&cached), &inputs),
+            None
+        );
+    }
+
+    #[test]
+    fn test_probe_config_with_stub() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stub waybar: exits with an error on configs containing "bad",
+        // exits cleanly on "quit", otherwise keeps running like a healthy
+        // bar, but only when the probe bar is hidden
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("waybar");
+        fs::write(
+            &stub,
+            "#!/bin/sh\ngrep -q start_hidden \"$2\" || exit 2\ngrep -q bad \"$2\" && exit 1\ngrep -q quit \"$2\" && exit 0\nexec sleep 5\n",
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let scratch = dir.path().join("scratch");
+        fs::create_dir(&scratch).unwrap();
+        let probe = |layer: &str| {
+            let path = dir.path().join(format!("{}.json", layer));
+            fs::write(&path, format!(r#"[{{"layer": "{}"}}]"#, layer)).unwrap();
+            let copy = hidden_copy(&path, &scratch).unwrap();
+            let style = dir.path().join("style.css");
+            probe_config(
+                stub.to_str().unwrap(),
+                &copy,
+                &style,
+                std::time::Duration::from_millis(300),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(probe("top"), Probe::Accepted);
+        assert_eq!(probe("bad"), Probe::Rejected);
+        assert_eq!(probe("quit"), Probe::Inconclusive);
+    }
+
+    #[test]
+    fn test_module_overrides_apply_to_every_template() {
+        let mut cfg = Config {
+            modules: toml::from_str(
+                r#"
+                [clock]
+                format = "{:%H:%M}"
+                interval = 30
+                "#,
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let templates = vec![
+            TemplateConfig {
+                template_type: TemplateType::Full,
+                config: json!({
+                    "modules-center": ["clock"],
+                    "clock": { "interval": 1 }
+                }),
+            },
+            TemplateConfig {
+                template_type: TemplateType::Simple,
+                config: json!({ "modules-right": ["clock"] }),
+            },
+            TemplateConfig {
+                template_type: TemplateType::Custom("media".to_string()),
+                config: json!({ "modules-right": ["mpris"] }),
+            },
+        ];
+
+        let dir = Path::new("/tmp");
+        let full = render_config(&cfg, &templates, "DP-1", &TemplateType::Full, dir).unwrap();
+        let simple = render_config(&cfg, &templates, "DP-2", &TemplateType::Simple, dir).unwrap();
+        let media = render_config(
+            &cfg,
+            &templates,
+            "DP-3",
+            &TemplateType::Custom("media".to_string()),
+            dir,
+        )
+        .unwrap();
+
+        // Template-local values win without `force`
+        assert_eq!(
+            full["clock"],
+            json!({ "format": "{:%H:%M}", "interval": 1 })
+        );
+        assert_eq!(
+            simple["clock"],
+            json!({ "format": "{:%H:%M}", "interval": 30 })
+        );
+        assert!(media.get("clock").is_none());
+
+        cfg.modules
+            .get_mut("clock")
+            .unwrap()
+            .insert("force".to_string(), toml::Value::Boolean(true));
+        let full = render_config(&cfg, &templates, "DP-1", &TemplateType::Full, dir).unwrap();
+        assert_eq!(
+            full["clock"],
+            json!({ "format": "{:%H:%M}", "interval": 30 })
+        );
+    }
+
+    #[test]
+    fn test_theme_resolves_template_and_style() {
+        let dir = tempfile::tempdir().unwrap();
+        let waybar_dir = &dir.path().join(".config/waybar");
+        fs::create_dir_all(waybar_dir.join("themes/gruvbox")).unwrap();
+        fs::write(waybar_dir.join("themes/gruvbox/style.css"), "").unwrap();
+
+        assert_eq!(
+            resolve_style_path(dir.path(), waybar_dir, None, None),
+            waybar_dir.join("omynix_style.css")
+        );
+        assert_eq!(
+            resolve_style_path(dir.path(), waybar_dir, Some("gruvbox"), Some("~/x.css")),
+            waybar_dir.join("themes/gruvbox/style.css")
+        );
+
+        // The theme has no template of its own, so the default one is kept
+        let resolve = || {
+            let candidates = template_candidates(
+                waybar_dir,
+                &waybar_dir.join("templates"),
+                Some("gruvbox"),
+                &WindowManager::Niri,
+            );
+            resolve_templates_in(&candidates, Path::new("niri.jsonc"), false).map(|file| file.path)
+        };
+        let valid = "[\n  // TPL:FULL\n  {\"layer\": \"top\"}\n]";
+        fs::create_dir_all(waybar_dir.join("templates")).unwrap();
+        fs::write(waybar_dir.join("templates/niri.jsonc"), valid).unwrap();
+        assert_eq!(resolve().unwrap(), waybar_dir.join("templates/niri.jsonc"));
+        fs::write(waybar_dir.join("themes/gruvbox/niri.jsonc"), valid).unwrap();
+        assert_eq!(
+            resolve().unwrap(),
+            waybar_dir.join("themes/gruvbox/niri.jsonc")
+        );
+    }
+
+    #[test]
+    fn test_malformed_template_falls_back_to_next_candidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let waybar_dir = dir.path();
+        let templates = waybar_dir.join("templates");
+        fs::create_dir_all(&templates).unwrap();
+        let candidates = template_candidates(waybar_dir, &templates, None, &WindowManager::Niri);
+        let usual = templates.join("niri.jsonc");
+        let resolve = |allow_empty| resolve_templates_in(&candidates, &usual, allow_empty);
+
+        // Nothing there: the usual file is named
+        let err = resolve(false).unwrap_err();
+        assert!(err.to_string().contains("niri.jsonc"));
+
+        // Nothing usable: the first file's error is reported
+        fs::write(templates.join("niri.jsonc"), "[ {\"layer\": ").unwrap();
+        let err = resolve(false).unwrap_err();
+        assert!(err.to_string().contains("niri.jsonc"));
+
+        let valid = "[\n  // TPL:FULL\n  {\"layer\": \"top\"}\n]";
+        fs::write(templates.join("default.jsonc"), valid).unwrap();
+        let file = resolve(false).unwrap();
+        assert_eq!(file.path, templates.join("default.jsonc"));
+        assert_eq!(file.skipped, vec![templates.join("niri.jsonc")]);
+        assert_eq!(file.templates[0].config["layer"], "top");
+
+        fs::write(templates.join("niri.json"), valid).unwrap();
+        assert_eq!(resolve(false).unwrap().path, templates.join("niri.json"));
+
+        // An intentionally empty template is kept with --allow-empty-templates
+        fs::write(templates.join("niri.jsonc"), "[\n  // nothing yet\n]").unwrap();
+        assert_eq!(resolve(false).unwrap().path, templates.join("niri.json"));
+        let file = resolve(true).unwrap();
+        assert_eq!(file.path, templates.join("niri.jsonc"));
+        assert!(file.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_xdg_config_home_moves_the_waybar_dir() {
+        let home = Path::new("/home/user");
+        let xdg = Path::new("/data/config");
+
+        let waybar = waybar_dir(Some(xdg), home);
+        assert_eq!(waybar, xdg.join("waybar"));
+        assert_eq!(
+            templates_dir(home, &waybar, None),
+            xdg.join("waybar/templates")
+        );
+        assert_eq!(waybar_dir(None, home), home.join(".config/waybar"));
+    }
+
+    #[test]
+    fn test_templates_dir_override_is_used_for_loading() {
+        let home = Path::new("/home/user");
+        assert_eq!(
+            templates_dir(home, &home.join(".config/waybar"), None),
+            home.join(".config/waybar/templates")
+        );
+        assert_eq!(
+            templates_dir(
+                home,
+                &home.join(".config/waybar"),
+                Some("~/dotfiles/waybar")
+            ),
+            home.join("dotfiles/waybar")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("sway.jsonc"),
+            "[\n  // TPL:FULL\n  {\"layer\": \"bottom\"}\n]",
+        )
+        .unwrap();
+        let mut cfg = Config::default();
+        cfg.paths.templates_dir = Some(dir.path().display().to_string());
+
+        assert_eq!(
+            get_templates_path(&cfg, &WindowManager::Sway, None),
+            dir.path().join("sway.jsonc")
+        );
+        let file = resolve_templates(&cfg, &WindowManager::Sway, None, false).unwrap();
+        assert_eq!(file.templates[0].config["layer"], "bottom");
+
+        // --templates-dir wins over the configured directory
+        let flag = tempfile::tempdir().unwrap();
+        fs::write(
+            flag.path().join("sway.jsonc"),
+            "[\n  // TPL:FULL\n  {\"layer\": \"overlay\"}\n]",
+        )
+        .unwrap();
+        let file = resolve_templates(&cfg, &WindowManager::Sway, Some(flag.path()), false).unwrap();
+        assert_eq!(file.path, flag.path().join("sway.jsonc"));
+        assert_eq!(file.templates[0].config["layer"], "overlay");
+    }
+
+    #[test]
+    fn test_configured_style_path() {
+        let home = Path::new("/home/user");
+        for configured in ["~/dots/bar.css", "dots/bar.css"] {
+            assert_eq!(
+                resolve_style_path(home, &home.join(".config/waybar"), None, Some(configured)),
+                home.join("dots/bar.css")
+            );
+        }
+        assert_eq!(
+            resolve_style_path(
+                home,
+                &home.join(".config/waybar"),
+                None,
+                Some("/etc/waybar/style.css")
+            ),
+            Path::new("/etc/waybar/style.css")
+        );
+        // A theme without a stylesheet falls through to the configured one
+        assert_eq!(
+            resolve_style_path(
+                home,
+                &home.join(".config/waybar"),
+                Some("nord"),
+                Some("dots/bar.css")
+            ),
+            home.join("dots/bar.css")
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_includes() {
+        let base = Path::new("/home/user/.config/waybar/templates");
+
+        let mut config = json!({ "include": "modules/custom.json" });
+        resolve_includes(&mut config, base);
+        assert_eq!(
+            config["include"],
+            "/home/user/.config/waybar/templates/modules/custom.json"
+        );
+
+        let mut config = json!({ "include": ["a.json", "/etc/xdg/waybar/b.json", "~/c.json"] });
+        resolve_includes(&mut config, base);
+        assert_eq!(
+            config["include"],
+            json!([
+                "/home/user/.config/waybar/templates/a.json",
+                "/etc/xdg/waybar/b.json",
+                "~/c.json"
+            ])
+        );
+    }
+}
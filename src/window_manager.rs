@@ -1,3 +1,4 @@
+use crate::exit::Failure;
 use anyhow::{Result, anyhow};
 use std::env;
 use std::process::Command;
@@ -7,37 +8,184 @@ pub enum WindowManager {
     Hyprland,
     Mango,
     Niri,
+    Sway,
+    River,
+    Labwc,
+    Dwl,
+    Wayfire,
 }
 
 impl WindowManager {
+    /// Default detection order. Fixed, so a compositor nested in another
+    /// always loses (or wins) the same way.
+    pub const ALL: [WindowManager; 8] = [
+        WindowManager::Hyprland,
+        WindowManager::Mango,
+        WindowManager::Niri,
+        WindowManager::Sway,
+        WindowManager::River,
+        WindowManager::Labwc,
+        WindowManager::Dwl,
+        WindowManager::Wayfire,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        WindowManager::ALL
+            .into_iter()
+            .find(|wm| wm.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             WindowManager::Hyprland => "hyprland",
             WindowManager::Mango => "mango",
             WindowManager::Niri => "niri",
+            WindowManager::Sway => "sway",
+            WindowManager::River => "river",
+            WindowManager::Labwc => "labwc",
+            WindowManager::Dwl => "dwl",
+            WindowManager::Wayfire => "wayfire",
         }
     }
 }
 
-pub fn detect_window_manager() -> Result<WindowManager> {
-    // Detect Hyprland by environment variable
-    if env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
-        return Ok(WindowManager::Hyprland);
-    }
+/// Detect the running window manager, probing the candidates in `order`
+/// (window manager names); an empty order probes every supported one.
+pub fn detect_window_manager(order: &[String]) -> Result<WindowManager> {
+    let candidates = if order.is_empty() {
+        WindowManager::ALL.to_vec()
+    } else {
+        order
+            .iter()
+            .map(|name| {
+                WindowManager::from_name(name).ok_or_else(|| {
+                    anyhow!("Unknown window manager '{}' in [detection] order", name)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    detect_with(&candidates, is_running)
+        .or_else(|| session_desktop().and_then(|desktop| from_desktop(&desktop, &candidates)))
+        .ok_or_else(|| {
+            Failure::NoWindowManager(
+                candidates
+                    .iter()
+                    .map(|wm| format!("{:?}", wm))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+            .into()
+        })
+}
+
+fn detect_with(
+    candidates: &[WindowManager],
+    probe: impl Fn(&WindowManager) -> bool,
+) -> Option<WindowManager> {
+    candidates.iter().copied().find(|wm| probe(wm))
+}
+
+/// Desktop of the current login session as reported by logind, for
+/// display managers that don't export the usual environment variables
+fn session_desktop() -> Option<String> {
+    let session = env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_string());
+    let output = Command::new("loginctl")
+        .args(["show-session", &session, "-p", "Desktop"])
+        .output()
+        .ok()?;
+
+    parse_session_desktop(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Value of the `Desktop=` line printed by `loginctl show-session -p Desktop`
+fn parse_session_desktop(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Desktop="))
+        .map(str::trim)
+        .filter(|desktop| !desktop.is_empty())
+        .map(str::to_string)
+}
+
+/// Map a session desktop name (e.g. "Hyprland", "mangowc") to one of `candidates`
+fn from_desktop(desktop: &str, candidates: &[WindowManager]) -> Option<WindowManager> {
+    let desktop = desktop.to_ascii_lowercase();
+    candidates
+        .iter()
+        .copied()
+        .find(|wm| desktop.starts_with(wm.as_str()))
+}
+
+/// Environment variables that influence detection, directly or through the
+/// session; the last ones are not consulted but explain most surprises
+const DETECTION_ENV_VARS: [&str; 8] = [
+    "HYPRLAND_INSTANCE_SIGNATURE",
+    "SWAYSOCK",
+    "WAYFIRE_SOCKET",
+    "XDG_SESSION_ID",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_SESSION_DESKTOP",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+];
+
+/// Processes looked up with `pgrep -x` during detection
+const PROBED_PROCESSES: [&str; 7] = ["mango", "niri", "sway", "river", "labwc", "dwl", "wayfire"];
+
+/// Everything detection looks at, for pasting into bug reports
+pub fn dump_env() -> String {
+    format_env_dump(
+        |key| env::var(key).ok(),
+        is_process_running,
+        session_desktop(),
+    )
+}
 
-    // Detect Mango by process
-    if is_process_running("mango") {
-        return Ok(WindowManager::Mango);
+fn format_env_dump(
+    lookup: impl Fn(&str) -> Option<String>,
+    probe: impl Fn(&str) -> bool,
+    session: Option<String>,
+) -> String {
+    let mut lines = vec!["Environment:".to_string()];
+    for key in DETECTION_ENV_VARS {
+        let value = lookup(key).unwrap_or_else(|| "(unset)".to_string());
+        lines.push(format!("  {}={}", key, value));
     }
 
-    // Detect Niri by process
-    if is_process_running("niri") {
-        return Ok(WindowManager::Niri);
+    lines.push("Processes (pgrep -x):".to_string());
+    for name in PROBED_PROCESSES {
+        let state = if probe(name) { "running" } else { "not found" };
+        lines.push(format!("  {}: {}", name, state));
     }
 
-    Err(anyhow!(
-        "No compatible window manager was detected (Hyprland, Mango, Niri)"
-    ))
+    lines.push(format!(
+        "Session desktop (loginctl): {}",
+        session.as_deref().unwrap_or("(unknown)")
+    ));
+    lines.join("\n")
+}
+
+fn is_running(wm: &WindowManager) -> bool {
+    match wm {
+        // Detect Hyprland by environment variable
+        WindowManager::Hyprland => env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok(),
+        // Detect Mango by process
+        WindowManager::Mango => is_process_running("mango"),
+        // Detect Niri by process
+        WindowManager::Niri => is_process_running("niri"),
+        // Detect Sway by its IPC socket, falling back to the process
+        WindowManager::Sway => env::var("SWAYSOCK").is_ok() || is_process_running("sway"),
+        // Detect River by process
+        WindowManager::River => is_process_running("river"),
+        // Detect labwc and dwl by process
+        WindowManager::Labwc => is_process_running("labwc"),
+        WindowManager::Dwl => is_process_running("dwl"),
+        // Detect Wayfire by its IPC socket, falling back to the process
+        WindowManager::Wayfire => {
+            env::var("WAYFIRE_SOCKET").is_ok() || is_process_running("wayfire")
+        }
+    }
 }
 
 fn is_process_running(process_name: &str) -> bool {
@@ -48,3 +196,77 @@ fn is_process_running(process_name: &str) -> bool {
         .map(|output| output.status.success())
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detection_order_picks_first_running() {
+        let running =
+            |wm: &WindowManager| matches!(wm, WindowManager::Hyprland | WindowManager::Niri);
+
+        assert_eq!(
+            detect_with(&WindowManager::ALL, running),
+            Some(WindowManager::Hyprland)
+        );
+        assert_eq!(
+            detect_with(&[WindowManager::Niri, WindowManager::Hyprland], running),
+            Some(WindowManager::Niri)
+        );
+        assert_eq!(detect_with(&[WindowManager::Mango], running), None);
+
+        // labwc nested in sway (or the other way around): sway comes first
+        let nested = |wm: &WindowManager| matches!(wm, WindowManager::Sway | WindowManager::Labwc);
+        assert_eq!(
+            detect_with(&WindowManager::ALL, nested),
+            Some(WindowManager::Sway)
+        );
+    }
+
+    #[test]
+    fn test_session_desktop_maps_to_window_manager() {
+        let desktop = parse_session_desktop("Desktop=Hyprland\n").unwrap();
+        assert_eq!(
+            from_desktop(&desktop, &WindowManager::ALL),
+            Some(WindowManager::Hyprland)
+        );
+        assert_eq!(
+            from_desktop("mangowc", &WindowManager::ALL),
+            Some(WindowManager::Mango)
+        );
+        // Only configured candidates are considered
+        assert_eq!(from_desktop("sway", &[WindowManager::Niri]), None);
+        assert_eq!(from_desktop("GNOME", &WindowManager::ALL), None);
+        assert_eq!(parse_session_desktop("Desktop=\n"), None);
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(WindowManager::from_name("Niri"), Some(WindowManager::Niri));
+        assert_eq!(WindowManager::from_name("kwin"), None);
+        assert_eq!(
+            WindowManager::from_name("labwc"),
+            Some(WindowManager::Labwc)
+        );
+        assert_eq!(WindowManager::from_name("DWL"), Some(WindowManager::Dwl));
+    }
+
+    #[test]
+    fn test_env_dump_lists_every_probe() {
+        let dump = format_env_dump(
+            |key| (key == "WAYLAND_DISPLAY").then(|| "wayland-1".to_string()),
+            |name| name == "niri",
+            Some("niri".to_string()),
+        );
+
+        for key in DETECTION_ENV_VARS {
+            assert!(dump.contains(key), "missing {}", key);
+        }
+        assert!(dump.contains("WAYLAND_DISPLAY=wayland-1"));
+        assert!(dump.contains("HYPRLAND_INSTANCE_SIGNATURE=(unset)"));
+        assert!(dump.contains("niri: running"));
+        assert!(dump.contains("sway: not found"));
+        assert!(dump.contains("Session desktop (loginctl): niri"));
+    }
+}